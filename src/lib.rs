@@ -1,7 +1,20 @@
 extern crate self as static_sqlite;
 pub use static_sqlite_async::{
-    execute, execute_all, open, query, query_first, rows, stream, Error, FromRow, Result,
-    Savepoint, Sqlite, Value,
+    execute, execute_all, open, open_bounded, open_supervised, open_with, query, query_first, rows,
+    stream, stream_with, BatchOptions, CloseOptions, Error, Event, Executor, FromRow, Histogram,
+    Maintenance, MaintenanceHandle, MaintenanceReport, Metrics, Pool, PoolOptions, Priority,
+    QueueStats, Result, Savepoint, Sqlite, StatementMetrics, StreamOptions, Transaction,
+    TransactionMode, Value, WaitTimes,
+};
+/// The blocking api, what `sql!` functions use with `#![sync]`
+pub use static_sqlite_core as sync;
+pub use static_sqlite_core::{
+    register_vfs, AppliedMigration, Checkpoint, CheckpointMode, Column, ConstraintOp, Counter,
+    DbStatus, DefaultFile, DefaultVfs, FaultFile, FaultKind, FaultOp, FaultVfs, Faults, FirstRow,
+    ForeignKey, ForeignKeyViolation, Hidden, Index, IndexColumn, IndexConstraint, IndexInfo,
+    IndexOrigin, IntegrityProblem, Limit, Limits, LockLevel, MemoryFile, MemoryStatus, MemoryVfs,
+    Migration, MigrationReport, MigrationStatus, MigrationStep, Migrations, OpenFlags, OpenOptions,
+    OrderBy, Row, RowIndex, Schema, Status, Table, Trigger, Update, VTab, VTabRow, VecRows, VecTab,
+    Vfs, VfsFile, VfsPath, View,
 };
-pub use static_sqlite_core::FirstRow;
 pub use static_sqlite_macros::sql;
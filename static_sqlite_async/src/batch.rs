@@ -0,0 +1,207 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::oneshot;
+
+use crate::{
+    core, metrics::Label, queue, supervisor, timeout::Progress, Error, Message, Result, Sqlite,
+};
+
+/// How many queued writes the worker commits together, see `Sqlite::batched`
+#[derive(Debug, Clone, Copy)]
+pub struct BatchOptions {
+    /// Commits once this many writes ran
+    pub max_statements: usize,
+    /// Commits once this long passed since the first write of the batch
+    pub max_delay: Duration,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            max_statements: 100,
+            max_delay: Duration::from_millis(5),
+        }
+    }
+}
+
+/// Runs a write, returns whether it succeeded and a function that
+/// replies to the caller once the outcome of the commit is known
+type WriteFn = Box<dyn FnOnce(&core::Sqlite) -> (bool, Reply) + Send + 'static>;
+type Reply = Box<dyn FnOnce(Result<()>) + Send + 'static>;
+
+pub(crate) struct Write {
+    sql: String,
+    options: BatchOptions,
+    progress: Arc<Progress>,
+    run: WriteFn,
+}
+
+impl Write {
+    /// Runs on its own, outside of a batch, unless the caller gave up already
+    pub(crate) fn run(self, conn: &core::Sqlite) {
+        if self.progress.start(conn) {
+            let (_, reply) = (self.run)(conn);
+            reply(Ok(()));
+        }
+    }
+}
+
+impl Sqlite {
+    /// A handle to the same connection whose writes, including the ones
+    /// generated functions make, are committed together with the other
+    /// batched writes queued meanwhile. Each write runs in a savepoint,
+    /// so one failing does not roll back the others in its batch
+    pub fn batched(&self, options: BatchOptions) -> Sqlite {
+        let mut db = self.clone();
+        db.batch = Some(options);
+        db
+    }
+
+    /// Like `call`, but joins a batch when the handle is `batched`. The sql of
+    /// `label` tells writes apart, reads and transaction control never batch
+    pub(crate) async fn call_sql<F, R>(&self, label: Label, function: F) -> Result<R>
+    where
+        F: FnOnce(&core::Sqlite) -> Result<R> + 'static + Send,
+        R: Send + 'static,
+    {
+        let Some(options) = self.batch else {
+            return self.call_labeled(Some(label), function).await;
+        };
+
+        let (sender, receiver) = oneshot::channel::<Result<R>>();
+        let progress = Progress::new();
+        let sql = label.sql.clone();
+        let metrics = self.metrics.clone();
+        let queued_at = Instant::now();
+        let run: WriteFn = Box::new(move |conn| {
+            let started = Instant::now();
+            metrics.queued(started - queued_at);
+            let result = catch_unwind(AssertUnwindSafe(|| function(conn)))
+                .unwrap_or_else(|panic| Err(Error::Panicked(supervisor::panic_message(&*panic))));
+            let succeeded = result.is_ok();
+            metrics.ran(Some(&label), started.elapsed(), succeeded);
+            let reply: Reply = Box::new(move |committed| {
+                let _ = sender.send(committed.and(result));
+            });
+            (succeeded, reply)
+        });
+        let message = Message::Batch(Write {
+            sql,
+            options,
+            progress: progress.clone(),
+            run,
+        });
+        let call = async {
+            self.queue.send(message, self.priority).await?;
+            receiver.await.map_err(|_| Error::ConnectionClosed)?
+        };
+        tokio::pin!(call);
+
+        // a batched write can not be interrupted without failing the others,
+        // once it started the caller waits for the outcome of its commit
+        let result = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, &mut call).await {
+                Ok(result) => result,
+                Err(_) => match progress.abandon() {
+                    Some(err) => Err(err),
+                    None => call.await,
+                },
+            },
+            None => call.await,
+        };
+        if let Err(err) = &result {
+            self.metrics.error(err);
+        }
+
+        result
+    }
+}
+
+/// Remembers which statements write, so the worker prepares each only once
+#[derive(Default)]
+pub(crate) struct Writes(HashMap<String, bool>);
+
+impl Writes {
+    fn writes(&mut self, conn: &core::Sqlite, sql: &str) -> bool {
+        if let Some(writes) = self.0.get(sql) {
+            return *writes;
+        }
+        // statements that fail to prepare run alone and report their error
+        let writes = conn
+            .prepare(sql, &[])
+            .map(|statement| !statement.readonly())
+            .unwrap_or(false);
+        self.0.insert(sql.to_string(), writes);
+
+        writes
+    }
+}
+
+/// Runs `first` and the batched writes queued behind it in one transaction.
+/// The first message that does not join the batch goes into `deferred`
+pub(crate) fn run(
+    conn: &core::Sqlite,
+    first: Write,
+    receiver: &queue::QueueReceiver,
+    deferred: &mut VecDeque<Message>,
+    writes: &mut Writes,
+) {
+    if !conn.is_autocommit() || !writes.writes(conn, &first.sql) {
+        return first.run(conn);
+    }
+    if conn.execute_all("begin immediate").is_err() {
+        return first.run(conn);
+    }
+
+    let options = first.options;
+    let deadline = Instant::now() + options.max_delay;
+    let mut replies = vec![];
+    replies.extend(savepoint(conn, first));
+    while replies.len() < options.max_statements {
+        match receiver.recv_deadline(deadline) {
+            Some(Message::Batch(write)) if writes.writes(conn, &write.sql) => {
+                replies.extend(savepoint(conn, write))
+            }
+            Some(message) => {
+                deferred.push_back(message);
+                break;
+            }
+            None => break,
+        }
+    }
+
+    let committed = conn.execute_all("commit").map(|_| ());
+    if committed.is_err() && !conn.is_autocommit() {
+        let _ = conn.execute_all("rollback");
+    }
+    for reply in replies {
+        reply(match &committed {
+            Ok(()) => Ok(()),
+            Err(err) => Err(Error::Sqlite(err.to_string())),
+        });
+    }
+}
+
+/// Runs `write` in a savepoint, rolled back if it fails. Skips the
+/// writes whose callers timed out in the queue
+fn savepoint(conn: &core::Sqlite, write: Write) -> Option<Reply> {
+    if !write.progress.start(conn) {
+        return None;
+    }
+    if conn.execute_all("savepoint batched").is_err() {
+        let (_, reply) = (write.run)(conn);
+        return Some(reply);
+    }
+    let (succeeded, reply) = (write.run)(conn);
+    if !succeeded {
+        let _ = conn.execute_all("rollback to batched");
+    }
+    let _ = conn.execute_all("release batched");
+
+    Some(reply)
+}
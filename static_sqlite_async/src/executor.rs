@@ -0,0 +1,171 @@
+use std::{borrow::Cow, future::Future};
+
+use futures::Stream;
+
+use crate::{core, FromRow, Pool, Result, Savepoint, Sqlite, Transaction, Value};
+
+/// Where generated functions run their statements: a connection, a
+/// `Transaction`, a `Pool` or, in place, a sync core `Sqlite` or `Savepoint`.
+/// `name` is the generated function's, used to label metrics
+pub trait Executor {
+    fn execute(
+        &self,
+        name: Option<&'static str>,
+        sql: Cow<'static, str>,
+        params: Vec<Value>,
+    ) -> impl Future<Output = Result<i32>>;
+
+    fn query<T: FromRow + Send + 'static>(
+        &self,
+        name: Option<&'static str>,
+        sql: Cow<'static, str>,
+        params: Vec<Value>,
+    ) -> impl Future<Output = Result<Vec<T>>>;
+
+    fn query_first<T: FromRow + Send + 'static>(
+        &self,
+        name: Option<&'static str>,
+        sql: Cow<'static, str>,
+        params: Vec<Value>,
+    ) -> impl Future<Output = Result<Option<T>>>;
+
+    fn stream<T: FromRow + Send + 'static>(
+        &self,
+        name: Option<&'static str>,
+        sql: Cow<'static, str>,
+        params: Vec<Value>,
+    ) -> impl Future<Output = Result<impl Stream<Item = Result<T>> + use<Self, T>>>;
+}
+
+impl Sqlite {
+    fn named(&self, name: Option<&'static str>) -> Cow<'_, Sqlite> {
+        match name {
+            Some(name) => Cow::Owned(self.labeled(name)),
+            None => Cow::Borrowed(self),
+        }
+    }
+}
+
+impl Executor for Sqlite {
+    async fn execute(
+        &self,
+        name: Option<&'static str>,
+        sql: Cow<'static, str>,
+        params: Vec<Value>,
+    ) -> Result<i32> {
+        crate::execute(&self.named(name), sql.into_owned(), params).await
+    }
+
+    async fn query<T: FromRow + Send + 'static>(
+        &self,
+        name: Option<&'static str>,
+        sql: Cow<'static, str>,
+        params: Vec<Value>,
+    ) -> Result<Vec<T>> {
+        crate::query(&self.named(name), sql, params).await
+    }
+
+    async fn query_first<T: FromRow + Send + 'static>(
+        &self,
+        name: Option<&'static str>,
+        sql: Cow<'static, str>,
+        params: Vec<Value>,
+    ) -> Result<Option<T>> {
+        crate::query_first(&self.named(name), sql, params).await
+    }
+
+    async fn stream<T: FromRow + Send + 'static>(
+        &self,
+        name: Option<&'static str>,
+        sql: Cow<'static, str>,
+        params: Vec<Value>,
+    ) -> Result<impl Stream<Item = Result<T>> + use<T>> {
+        crate::stream(&self.named(name), sql, params).await
+    }
+}
+
+/// Implements `Executor` for a handle that derefs to one
+macro_rules! deref_executor {
+    ($(impl$(<$lt:lifetime>)? for $ty:ty),*) => {$(
+        impl$(<$lt>)? Executor for $ty {
+            fn execute(
+                &self,
+                name: Option<&'static str>,
+                sql: Cow<'static, str>,
+                params: Vec<Value>,
+            ) -> impl Future<Output = Result<i32>> {
+                Executor::execute(&**self, name, sql, params)
+            }
+
+            fn query<T: FromRow + Send + 'static>(
+                &self,
+                name: Option<&'static str>,
+                sql: Cow<'static, str>,
+                params: Vec<Value>,
+            ) -> impl Future<Output = Result<Vec<T>>> {
+                Executor::query(&**self, name, sql, params)
+            }
+
+            fn query_first<T: FromRow + Send + 'static>(
+                &self,
+                name: Option<&'static str>,
+                sql: Cow<'static, str>,
+                params: Vec<Value>,
+            ) -> impl Future<Output = Result<Option<T>>> {
+                Executor::query_first(&**self, name, sql, params)
+            }
+
+            fn stream<T: FromRow + Send + 'static>(
+                &self,
+                name: Option<&'static str>,
+                sql: Cow<'static, str>,
+                params: Vec<Value>,
+            ) -> impl Future<Output = Result<impl Stream<Item = Result<T>> + use<$($lt,)? T>>> {
+                Executor::stream(&**self, name, sql, params)
+            }
+        }
+    )*};
+}
+
+deref_executor!(impl for Transaction, impl for Pool, impl<'a> for Savepoint<'a>);
+
+/// Runs the statements right away on the calling thread
+impl Executor for core::Sqlite {
+    async fn execute(
+        &self,
+        _name: Option<&'static str>,
+        sql: Cow<'static, str>,
+        params: Vec<Value>,
+    ) -> Result<i32> {
+        core::Sqlite::execute(self, &sql, params)
+    }
+
+    async fn query<T: FromRow + Send + 'static>(
+        &self,
+        _name: Option<&'static str>,
+        sql: Cow<'static, str>,
+        params: Vec<Value>,
+    ) -> Result<Vec<T>> {
+        core::Sqlite::query(self, &sql, &params)
+    }
+
+    async fn query_first<T: FromRow + Send + 'static>(
+        &self,
+        _name: Option<&'static str>,
+        sql: Cow<'static, str>,
+        params: Vec<Value>,
+    ) -> Result<Option<T>> {
+        core::Sqlite::query_first(self, &sql, &params)
+    }
+
+    /// Reads every row up front, the statement can not outlive the call
+    async fn stream<T: FromRow + Send + 'static>(
+        &self,
+        _name: Option<&'static str>,
+        sql: Cow<'static, str>,
+        params: Vec<Value>,
+    ) -> Result<impl Stream<Item = Result<T>> + use<T>> {
+        let rows = self.iter(&sql, &params)?.collect::<Vec<_>>();
+        Ok(futures::stream::iter(rows))
+    }
+}
@@ -1,57 +1,383 @@
 // Inspired by the incredible tokio-rusqlite crate
 // https://github.com/programatik29/tokio-rusqlite/blob/master/src/lib.rs
 
-use crossbeam_channel::Sender;
 pub use futures::Stream;
 use static_sqlite_core as core;
-use tokio::sync::oneshot;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{broadcast, mpsc::error::TrySendError, oneshot};
+
+mod batch;
+mod executor;
+mod metrics;
+mod pool;
+mod queue;
+mod supervisor;
+mod timeout;
+mod transaction;
+pub use batch::BatchOptions;
+pub use executor::Executor;
+pub use metrics::{Histogram, Metrics, StatementMetrics};
+pub use pool::{Pool, PoolOptions};
+pub use queue::{Priority, QueueStats, WaitTimes};
+pub use supervisor::{open_supervised, Event};
+pub use transaction::Transaction;
 
 pub use static_sqlite_core::*;
 
-type CallFn = Box<dyn FnOnce(&mut core::Sqlite) + Send + 'static>;
+type CallFn = Box<dyn FnOnce(&core::Sqlite) + Send + 'static>;
+/// Runs the calls queued meanwhile, waiting up to the duration for one when none are.
+/// Returns false once the worker can not run any more of them, see `StreamOptions::yield_every`
+type YieldFn<'a> = &'a mut dyn FnMut(&core::Sqlite, Duration) -> bool;
+/// Gets a function that runs the messages queued meanwhile, see `YieldFn`
+type StreamFn = Box<dyn for<'a> FnOnce(&core::Sqlite, YieldFn<'a>) + Send + 'static>;
 
 enum Message {
     Execute(CallFn),
-    Close(oneshot::Sender<std::result::Result<(), Error>>),
+    Stream(StreamFn),
+    /// A write a `batched` handle made
+    Batch(batch::Write),
+    Close {
+        checkpoint: Option<CheckpointMode>,
+        reply: Option<oneshot::Sender<Result<()>>>,
+    },
 }
 
 #[derive(Clone)]
 pub struct Sqlite {
-    sender: Sender<Message>,
+    queue: queue::Queue,
+    _last: Arc<LastHandle>,
+    readers: Option<Arc<pool::Readers>>,
+    events: broadcast::Sender<Event>,
+    /// Covers the time a call waits in the queue plus the time it runs
+    timeout: Option<Duration>,
+    priority: Priority,
+    batch: Option<BatchOptions>,
+    metrics: Arc<metrics::Recorder>,
+    /// Names the statements of this handle in the metrics
+    label: Option<&'static str>,
+}
+
+/// Closes the connection once every clone of the user facing handle is dropped,
+/// internal senders like the maintenance task do not keep it open
+struct LastHandle(queue::Queue);
+
+impl Drop for LastHandle {
+    fn drop(&mut self) {
+        let message = Message::Close {
+            checkpoint: None,
+            reply: None,
+        };
+        let _ = self.0.send_now(message, Priority::Background);
+    }
+}
+
+/// How `Sqlite::close_with` shuts the worker down
+#[derive(Debug, Clone, Default)]
+pub struct CloseOptions {
+    /// Checkpoint the wal before closing
+    pub checkpoint: Option<CheckpointMode>,
+    /// Give up waiting after this long, the worker still closes
+    /// the connection once it is done with the work queued before
+    pub timeout: Option<Duration>,
 }
 
 impl Sqlite {
+    /// Runs the work queued so far, then closes the connection. Anything
+    /// queued afterwards, from this or any other clone, fails with
+    /// `Error::ConnectionClosed`. If sqlite refuses to close the worker keeps running
     pub async fn close(self) -> Result<()> {
-        let (sender, receiver) = oneshot::channel::<std::result::Result<(), Error>>();
-
-        if let Err(crossbeam_channel::SendError(_)) = self.sender.send(Message::Close(sender)) {
-            return Ok(());
-        }
+        self.close_with(CloseOptions::default()).await
+    }
 
-        let result = receiver.await;
+    pub async fn close_with_timeout(self, timeout: Duration) -> Result<()> {
+        self.close_with(CloseOptions {
+            timeout: Some(timeout),
+            ..Default::default()
+        })
+        .await
+    }
 
-        if result.is_err() {
+    pub async fn close_with(self, options: CloseOptions) -> Result<()> {
+        let (sender, receiver) = oneshot::channel::<Result<()>>();
+        let message = Message::Close {
+            checkpoint: options.checkpoint,
+            reply: Some(sender),
+        };
+        if self.queue.close(message).is_err() {
             return Ok(());
         }
 
-        result.unwrap().map_err(|e| Error::Sqlite(e.to_string()))
+        let result = match options.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, receiver)
+                .await
+                .map_err(|_| Error::CloseTimeout { waited: timeout })?,
+            None => receiver.await,
+        };
+
+        // the worker is gone, so the connection is closed already
+        result.unwrap_or(Ok(()))
     }
 
     pub async fn call<F, R>(&self, function: F) -> Result<R>
+    where
+        F: FnOnce(&core::Sqlite) -> Result<R> + 'static + Send,
+        R: Send + 'static,
+    {
+        self.call_labeled(None, function).await
+    }
+
+    /// Like `call`, records the metrics of `label` too
+    async fn call_labeled<F, R>(&self, label: Option<metrics::Label>, function: F) -> Result<R>
     where
         F: FnOnce(&core::Sqlite) -> Result<R> + 'static + Send,
         R: Send + 'static,
     {
         let (sender, receiver) = oneshot::channel::<Result<R>>();
+        let progress = timeout::Progress::new();
+        let worker_progress = progress.clone();
+        let metrics = self.metrics.clone();
+        let queued_at = Instant::now();
+
+        let message = Message::Execute(Box::new(move |conn| {
+            if !worker_progress.start(conn) {
+                return;
+            }
+            let started = Instant::now();
+            metrics.queued(started - queued_at);
+            let result = catch_unwind(AssertUnwindSafe(|| function(conn)));
+            worker_progress.finish(conn);
+            let succeeded = matches!(result, Ok(Ok(_)));
+            metrics.ran(label.as_ref(), started.elapsed(), succeeded);
+            match result {
+                Ok(value) => {
+                    let _ = sender.send(value);
+                }
+                Err(panic) => {
+                    let message = supervisor::panic_message(&*panic);
+                    let _ = sender.send(Err(Error::Panicked(message)));
+                    // lets the worker recover the connection
+                    resume_unwind(panic);
+                }
+            }
+        }));
+        // waiting for room in the queue counts against the timeout too
+        let call = async {
+            self.queue.send(message, self.priority).await?;
+            receiver.await.map_err(|_| Error::ConnectionClosed)?
+        };
+
+        let result = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, call).await {
+                Ok(result) => result,
+                Err(_) => Err(progress.time_out()),
+            },
+            None => call.await,
+        };
+        if let Err(err) = &result {
+            self.metrics.error(err);
+        }
+
+        result
+    }
+
+    /// Runs `function` on an idle reader of a `Pool`, on the connection itself otherwise
+    pub async fn read<F, R>(&self, function: F) -> Result<R>
+    where
+        F: FnOnce(&core::Sqlite) -> Result<R> + 'static + Send,
+        R: Send + 'static,
+    {
+        match self.readers.as_ref().and_then(|readers| readers.idle()) {
+            Some((mut reader, _in_flight)) => {
+                reader.timeout = self.timeout;
+                reader.label = self.label;
+                reader.call(function).await
+            }
+            None => self.call(function).await,
+        }
+    }
+
+    /// The connection `sql` should run on, a reader when it is read only
+    async fn route(&self, sql: &str) -> Result<(Sqlite, Option<pool::InFlight>)> {
+        if let Some(readers) = &self.readers {
+            if readers.readonly(sql).await? {
+                if let Some((mut reader, in_flight)) = readers.idle() {
+                    reader.timeout = self.timeout;
+                    reader.label = self.label;
+                    return Ok((reader, Some(in_flight)));
+                }
+            }
+        }
+
+        Ok((self.clone(), None))
+    }
+}
+
+/// What the background maintenance task runs, every `interval`
+#[derive(Debug, Clone)]
+pub struct Maintenance {
+    pub interval: Duration,
+    pub checkpoint: Option<CheckpointMode>,
+    pub optimize: bool,
+    pub analyze: bool,
+    pub incremental_vacuum: Option<u32>,
+    pub quick_check: bool,
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60 * 60),
+            checkpoint: Some(CheckpointMode::Passive),
+            optimize: true,
+            analyze: false,
+            incremental_vacuum: None,
+            quick_check: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MaintenanceReport {
+    pub ran_at: SystemTime,
+    pub checkpoint: Option<Checkpoint>,
+    pub problems: Vec<IntegrityProblem>,
+    pub errors: Vec<String>,
+}
+
+impl Maintenance {
+    fn run(&self, conn: &core::Sqlite) -> MaintenanceReport {
+        let mut report = MaintenanceReport {
+            ran_at: SystemTime::now(),
+            checkpoint: None,
+            problems: vec![],
+            errors: vec![],
+        };
+        if let Some(mode) = self.checkpoint {
+            match conn.wal_checkpoint(mode) {
+                Ok(checkpoint) => report.checkpoint = Some(checkpoint),
+                Err(err) => report.errors.push(err.to_string()),
+            }
+        }
+        if self.quick_check {
+            match conn.quick_check() {
+                Ok(problems) => report.problems = problems,
+                Err(err) => report.errors.push(err.to_string()),
+            }
+        }
+        if self.analyze {
+            if let Err(err) = conn.analyze() {
+                report.errors.push(err.to_string());
+            }
+        }
+        if self.optimize {
+            if let Err(err) = conn.optimize() {
+                report.errors.push(err.to_string());
+            }
+        }
+        if let Some(pages) = self.incremental_vacuum {
+            if let Err(err) = conn.incremental_vacuum(pages) {
+                report.errors.push(err.to_string());
+            }
+        }
+
+        report
+    }
+}
+
+/// Stops the background maintenance task when dropped
+pub struct MaintenanceHandle {
+    task: tokio::task::AbortHandle,
+    last_report: Arc<Mutex<Option<MaintenanceReport>>>,
+}
+
+impl MaintenanceHandle {
+    pub fn last_report(&self) -> Option<MaintenanceReport> {
+        self.last_report.lock().unwrap().clone()
+    }
+
+    pub fn stop(self) {}
+}
+
+impl Drop for MaintenanceHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Sqlite {
+    /// A snapshot of the connection's `sqlite3_db_status` counters, its
+    /// limits and the process wide memory status, taken on the worker thread
+    pub async fn status(&self) -> Result<Status> {
+        self.call(|conn| conn.status()).await
+    }
+
+    /// Runs `migrations` on the worker thread, see `Migrations::run`
+    pub async fn migrate(&self, migrations: Migrations) -> Result<MigrationReport> {
+        self.call(move |conn| migrations.run(conn)).await
+    }
 
-        self.sender
-            .send(Message::Execute(Box::new(move |conn| {
-                let value = function(conn);
-                let _ = sender.send(value);
-            })))
-            .map_err(|_| Error::ConnectionClosed)?;
+    /// Runs `migrations` up to `target` on the worker thread, see `Migrations::run_to`
+    pub async fn migrate_to(
+        &self,
+        migrations: Migrations,
+        target: &str,
+    ) -> Result<MigrationReport> {
+        let target = target.to_string();
+        self.call(move |conn| migrations.run_to(conn, &target))
+            .await
+    }
+
+    /// Reverts `migrations` down to `target` on the worker thread, see `Migrations::run_down`
+    pub async fn migrate_down(
+        &self,
+        migrations: Migrations,
+        target: &str,
+    ) -> Result<MigrationReport> {
+        let target = target.to_string();
+        self.call(move |conn| migrations.run_down(conn, &target))
+            .await
+    }
 
-        receiver.await.map_err(|_| Error::ConnectionClosed)?
+    /// Which of `migrations` are applied and pending, see `Migrations::status`
+    pub async fn migration_status(&self, migrations: Migrations) -> Result<MigrationStatus> {
+        self.call(move |conn| migrations.status(conn)).await
+    }
+
+    /// Runs `maintenance` on the worker thread every `maintenance.interval`
+    /// until the returned handle is dropped or the connection is closed.
+    /// The interval is a task on the current tokio runtime
+    pub fn maintain(&self, maintenance: Maintenance) -> MaintenanceHandle {
+        let last_report = Arc::new(Mutex::new(None));
+        let reports = last_report.clone();
+        let queue = self.queue.clone();
+
+        let task = tokio::spawn(async move {
+            let period = maintenance.interval.max(Duration::from_millis(1));
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let maintenance = maintenance.clone();
+                let reports = reports.clone();
+                let message = Message::Execute(Box::new(move |conn| {
+                    let report = maintenance.run(conn);
+                    *reports.lock().unwrap() = Some(report);
+                }));
+                if queue.send_now(message, Priority::Background).is_err() {
+                    break;
+                }
+            }
+        });
+
+        MaintenanceHandle {
+            task: task.abort_handle(),
+            last_report,
+        }
     }
 }
 
@@ -60,110 +386,410 @@ pub async fn open(path: impl ToString) -> Result<Sqlite> {
     start(move || core::Sqlite::open(&path)).await
 }
 
+pub async fn open_with(path: impl ToString, options: OpenOptions) -> Result<Sqlite> {
+    let path = path.to_string();
+    start(move || core::Sqlite::open_with(&path, &options)).await
+}
+
+/// Like `open_with`, but at most `capacity` calls wait in the worker's queue,
+/// further calls wait for room before they are queued
+pub async fn open_bounded(
+    path: impl ToString,
+    options: OpenOptions,
+    capacity: usize,
+) -> Result<Sqlite> {
+    let path = path.to_string();
+    start_with(
+        move || core::Sqlite::open_with(&path, &options),
+        false,
+        Some(capacity),
+    )
+    .await
+}
+
 async fn start<F>(open: F) -> Result<Sqlite>
 where
-    F: FnOnce() -> Result<core::Sqlite> + Send + 'static,
+    F: Fn() -> Result<core::Sqlite> + Send + 'static,
+{
+    start_with(open, false, None).await
+}
+
+/// Opens the connection on a new worker thread. When `supervised` a call
+/// that panics makes the worker reopen the connection with `open`
+async fn start_with<F>(open: F, supervised: bool, capacity: Option<usize>) -> Result<Sqlite>
+where
+    F: Fn() -> Result<core::Sqlite> + Send + 'static,
 {
-    let (sender, receiver) = crossbeam_channel::unbounded::<Message>();
+    let (queue, receiver) = queue::queue(capacity);
     let (result_sender, result_receiver) = oneshot::channel();
+    let (events, _) = broadcast::channel(16);
+    let worker = supervisor::Worker {
+        open: Box::new(open),
+        supervised,
+        events: events.clone(),
+    };
 
     std::thread::spawn(move || {
-        let mut conn = match open() {
-            Ok(c) => c,
-            Err(e) => {
+        let conn = match catch_unwind(AssertUnwindSafe(|| (worker.open)())) {
+            Ok(Ok(c)) => c,
+            Ok(Err(e)) => {
                 let _ = result_sender.send(Err(e));
                 return;
             }
+            Err(panic) => {
+                let message = supervisor::panic_message(&*panic);
+                let _ = result_sender.send(Err(Error::Panicked(message)));
+                return;
+            }
         };
 
         if let Err(_e) = result_sender.send(Ok(())) {
             return;
         }
 
-        while let Ok(message) = receiver.recv() {
-            match message {
-                Message::Execute(f) => f(&mut conn),
-                Message::Close(_s) => {
-                    todo!("Message::Close")
-                    // let result = drop(conn);
-
-                    // match result {
-                    //     Ok(v) => {
-                    //         s.send(Ok(v)).expect("failed to send message");
-                    //         break;
-                    //     }
-                    //     Err((c, e)) => {
-                    //         conn = c;
-                    //         s.send(Err(e)).expect("failed to receive message");
-                    //     }
-                    // }
-                }
-            }
-        }
+        run(conn, receiver, worker);
     });
 
     result_receiver
         .await
-        .expect("failed to receive message")
-        .map(|_| Sqlite { sender })
+        .map_err(|_| Error::ConnectionClosed)?
+        .map(|_| Sqlite {
+            _last: Arc::new(LastHandle(queue.clone())),
+            queue,
+            readers: None,
+            events,
+            timeout: None,
+            priority: Priority::default(),
+            batch: None,
+            metrics: Arc::default(),
+            label: None,
+        })
+}
+
+fn run(mut conn: core::Sqlite, receiver: queue::QueueReceiver, worker: supervisor::Worker) {
+    // messages a yielding stream came across but could not run in between
+    let mut deferred = VecDeque::new();
+    let mut writes = batch::Writes::default();
+    while let Some(message) = deferred.pop_front().or_else(|| receiver.recv()) {
+        let (checkpoint, reply) = match message {
+            Message::Execute(f) => {
+                if let Err(panic) = catch_unwind(AssertUnwindSafe(|| f(&conn))) {
+                    worker.recover(&mut conn, panic);
+                }
+                continue;
+            }
+            Message::Stream(f) => {
+                let mut panics = vec![];
+                let streamed = catch_unwind(AssertUnwindSafe(|| {
+                    f(&conn, &mut |conn, wait| {
+                        if !deferred.is_empty() {
+                            return false;
+                        }
+                        let deadline = Instant::now() + wait;
+                        let mut next = receiver.recv_deadline(deadline);
+                        if next.is_none() && Instant::now() < deadline {
+                            // every handle is gone, nothing will be queued anymore
+                            return false;
+                        }
+                        while let Some(message) = next {
+                            match message {
+                                Message::Execute(f) => {
+                                    if let Err(panic) = catch_unwind(AssertUnwindSafe(|| f(conn))) {
+                                        panics.push(panic);
+                                    }
+                                }
+                                message => {
+                                    deferred.push_back(message);
+                                    return false;
+                                }
+                            }
+                            next = receiver.try_recv();
+                        }
+                        true
+                    })
+                }));
+                panics.extend(streamed.err());
+                for panic in panics {
+                    worker.recover(&mut conn, panic);
+                }
+                continue;
+            }
+            Message::Batch(write) => {
+                let batched = catch_unwind(AssertUnwindSafe(|| {
+                    batch::run(&conn, write, &receiver, &mut deferred, &mut writes)
+                }));
+                if let Err(panic) = batched {
+                    worker.recover(&mut conn, panic);
+                }
+                continue;
+            }
+            Message::Close { checkpoint, reply } => (checkpoint, reply),
+        };
+
+        let checkpointed = match checkpoint {
+            Some(mode) => conn.wal_checkpoint(mode).map(|_| ()),
+            None => Ok(()),
+        };
+        match (conn.close(), reply) {
+            (Ok(()), reply) => {
+                if let Some(reply) = reply {
+                    let _ = reply.send(checkpointed);
+                }
+                break;
+            }
+            (Err((c, err)), Some(reply)) => {
+                conn = c;
+                receiver.reopen();
+                let _ = reply.send(Err(err));
+            }
+            // nobody is left to report to, close_v2 finishes once
+            // the remaining statements are finalized
+            (Err((c, _)), None) => {
+                drop(c);
+                break;
+            }
+        }
+    }
+
+    // drops the calls queued behind the close, failing them with ConnectionClosed
+    drop(deferred);
+    while let Some(message) = receiver.try_recv() {
+        drop(message);
+    }
 }
 
 pub async fn execute(conn: &Sqlite, sql: String, params: Vec<Value>) -> Result<i32> {
-    conn.call(move |conn| conn.execute(&sql, params)).await
+    let (db, _in_flight) = conn.route(&sql).await?;
+    let label = db.label(&sql);
+    db.call_sql(label, move |conn| conn.execute(&sql, params))
+        .await
 }
 
-pub async fn execute_all(conn: &Sqlite, sql: &'static str) -> Result<()> {
-    let _ = conn.call(move |conn| conn.execute(sql, vec![])).await;
+pub async fn execute_all(conn: &Sqlite, sql: impl Into<String>) -> Result<()> {
+    let sql = sql.into();
+    conn.call(move |conn| conn.execute(&sql, vec![])).await?;
     Ok(())
 }
 
 pub async fn query<T: FromRow + Send + 'static>(
     conn: &Sqlite,
-    sql: &'static str,
+    sql: impl Into<Cow<'static, str>>,
     params: Vec<Value>,
 ) -> Result<Vec<T>> {
-    conn.call(move |conn| conn.query(sql, &params)).await
+    let sql = sql.into();
+    let (conn, _in_flight) = conn.route(&sql).await?;
+    let label = conn.label(&sql);
+    let rows = conn
+        .call_sql(label.clone(), move |conn| conn.query(&sql, &params))
+        .await?;
+    conn.metrics.rows(Some(&label), rows.len());
+
+    Ok(rows)
 }
 
 pub async fn query_first<T: FromRow + Send + 'static>(
     conn: &Sqlite,
-    sql: &'static str,
+    sql: impl Into<Cow<'static, str>>,
     params: Vec<Value>,
 ) -> Result<Option<T>> {
-    conn.call(move |conn| conn.query_first(sql, &params)).await
+    let sql = sql.into();
+    let (conn, _in_flight) = conn.route(&sql).await?;
+    let label = conn.label(&sql);
+    let row = conn
+        .call_sql(label.clone(), move |conn| conn.query_first(&sql, &params))
+        .await?;
+    conn.metrics.rows(Some(&label), row.is_some() as usize);
+
+    Ok(row)
+}
+
+/// How `stream_with` hands rows to the caller
+#[derive(Debug, Clone)]
+pub struct StreamOptions {
+    /// How many rows the worker reads ahead of the consumer
+    pub buffer: usize,
+    /// Run the calls queued meanwhile after every this many rows and
+    /// while the buffer is full, so a long stream does not hold up the connection.
+    /// With `None` the worker blocks on a full buffer, so a consumer that awaits
+    /// a call on the same connection for every row never gets it to run
+    pub yield_every: Option<usize>,
 }
 
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            buffer: 64,
+            yield_every: Some(64),
+        }
+    }
+}
+
+/// Streams rows with the default `StreamOptions`, which run the calls the
+/// consumer makes on the same connection while it reads the rows
 pub async fn stream<T: FromRow + Send + 'static>(
     conn: &Sqlite,
-    sql: &'static str,
+    sql: impl Into<Cow<'static, str>>,
     params: Vec<Value>,
 ) -> Result<impl Stream<Item = static_sqlite_core::Result<T>>> {
-    let (sender, receiver) = std::sync::mpsc::channel();
+    stream_with(conn, sql, params, StreamOptions::default()).await
+}
 
-    conn.sender
-        .send(Message::Execute(Box::new(move |conn| {
-            let value = conn.iter(sql, &params).unwrap();
+/// Streams rows through a bounded channel. The worker waits while the buffer
+/// is full and stops, finalizing the statement, once the stream is dropped.
+/// A statement that fails to prepare is the stream's first and only item.
+/// The handle's timeout covers the wait for the queue and for the first row,
+/// a stream that times out yields the error and ends. The rows after the
+/// first take as long as the consumer does
+pub async fn stream_with<T: FromRow + Send + 'static>(
+    conn: &Sqlite,
+    sql: impl Into<Cow<'static, str>>,
+    params: Vec<Value>,
+    options: StreamOptions,
+) -> Result<impl Stream<Item = static_sqlite_core::Result<T>>> {
+    let sql = sql.into();
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(options.buffer.max(1));
+    let (conn, in_flight) = conn.route(&sql).await?;
 
-            for item in value {
-                let res = sender.send(item);
-                if res.is_err() {
-                    break;
+    let priority = conn.priority;
+    let metrics = conn.metrics.clone();
+    let active = metrics.stream();
+    let label = conn.label(&sql);
+    let progress = timeout::Progress::new();
+    let worker_progress = progress.clone();
+    let queued_at = Instant::now();
+    let deadline = conn
+        .timeout
+        .map(|timeout| tokio::time::Instant::from_std(queued_at) + timeout);
+    let message = Message::Stream(Box::new(move |conn, yield_now| {
+        let _in_flight = in_flight;
+        if !worker_progress.start(conn) {
+            return;
+        }
+        let started = Instant::now();
+        metrics.queued(started - queued_at);
+        let mut sent = 0;
+        let streamed = stream_rows(
+            conn,
+            &sql,
+            &params,
+            &sender,
+            options,
+            yield_now,
+            &mut || {
+                sent += 1;
+                // the timeout only covers the first row
+                if sent == 1 {
+                    worker_progress.finish(conn);
                 }
-            }
-        })))
-        .map_err(|_| Error::ConnectionClosed)?;
+            },
+        );
+        worker_progress.finish(conn);
+        metrics.ran(Some(&label), started.elapsed(), streamed.is_ok());
+        metrics.rows(Some(&label), sent);
+        if let Err(err) = streamed {
+            metrics.error(&err);
+            let _ = sender.blocking_send(Err(err));
+        }
+    }));
+    // waiting for room in the queue counts against the timeout too
+    let queued = match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, conn.queue.send(message, priority))
+            .await
+            .unwrap_or_else(|_| Err(progress.time_out())),
+        None => conn.queue.send(message, priority).await,
+    };
+    if let Err(err) = queued {
+        conn.metrics.error(&err);
+        return Err(err);
+    }
 
+    let metrics = conn.metrics.clone();
     Ok(async_stream::stream! {
-        for item in receiver {
+        let _active = active;
+        let mut next = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(first) => first,
+                Err(_) => {
+                    let err = progress.time_out();
+                    metrics.error(&err);
+                    yield Err(err);
+                    return;
+                }
+            },
+            None => receiver.recv().await,
+        };
+        while let Some(item) = next {
             yield item;
+            next = receiver.recv().await;
         }
     })
 }
 
+/// Sends the rows of `sql` until the receiver is gone, calling `sent` after each.
+/// Returns the error that ended the stream early
+fn stream_rows<T: FromRow>(
+    conn: &core::Sqlite,
+    sql: &str,
+    params: &[Value],
+    sender: &tokio::sync::mpsc::Sender<Result<T>>,
+    options: StreamOptions,
+    yield_now: YieldFn<'_>,
+    sent: &mut dyn FnMut(),
+) -> Result<()> {
+    let rows = conn.iter(sql, params)?;
+
+    let Some(every) = options.yield_every else {
+        for item in rows {
+            let item = item?;
+            if sender.blocking_send(Ok(item)).is_err() {
+                break;
+            }
+            sent();
+        }
+        return Ok(());
+    };
+    for (i, item) in rows.enumerate() {
+        let mut item = Ok(item?);
+        // keeps running queued calls while the consumer catches up, waiting
+        // on the queue in between checks of the buffer
+        loop {
+            match sender.try_send(item) {
+                Ok(()) => break,
+                Err(TrySendError::Full(rejected)) => {
+                    item = rejected;
+                    if !yield_now(conn, Duration::from_millis(1)) {
+                        if sender.blocking_send(item).is_err() {
+                            return Ok(());
+                        }
+                        break;
+                    }
+                }
+                Err(TrySendError::Closed(_)) => return Ok(()),
+            }
+        }
+        sent();
+        if (i + 1) % every.max(1) == 0 {
+            yield_now(conn, Duration::ZERO);
+        }
+    }
+
+    Ok(())
+}
+
+/// Rows of a query built at runtime as column name and value pairs,
+/// `query::<Row>` looks their columns up by name or index instead
 pub async fn rows(
-    conn: Sqlite,
-    sql: &'static str,
-    params: &'static [Value],
+    conn: &Sqlite,
+    sql: impl Into<Cow<'static, str>>,
+    params: Vec<Value>,
 ) -> Result<Vec<Vec<(String, Value)>>> {
-    conn.call(|conn| conn.rows(sql, params)).await
+    let sql = sql.into();
+    let (conn, _in_flight) = conn.route(&sql).await?;
+    let label = conn.label(&sql);
+    let rows = conn
+        .call_labeled(Some(label.clone()), move |conn| conn.rows(&sql, &params))
+        .await?;
+    conn.metrics.rows(Some(&label), rows.len());
+
+    Ok(rows)
 }
@@ -0,0 +1,340 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{Error, Sqlite};
+
+/// Upper bounds of the latency histograms, like prometheus' default buckets
+const BUCKETS: [Duration; 14] = [
+    Duration::from_micros(50),
+    Duration::from_micros(100),
+    Duration::from_micros(250),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_micros(2500),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub count: u64,
+    pub sum: Duration,
+    /// How many observations were at most each upper bound, cumulative
+    pub buckets: Vec<(Duration, u64)>,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            sum: Duration::ZERO,
+            buckets: BUCKETS.iter().map(|bound| (*bound, 0)).collect(),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => self.sum / count as u32,
+        }
+    }
+
+    fn observe(&mut self, value: Duration) {
+        self.count += 1;
+        self.sum += value;
+        for (bound, count) in &mut self.buckets {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+    }
+}
+
+/// How many entries `Metrics::statements` keys by their sql, the unlabeled
+/// statements seen afterwards share `OTHER_STATEMENTS`
+const MAX_STATEMENTS: usize = 256;
+const OTHER_STATEMENTS: &str = "other";
+
+/// Metrics of one statement, keyed by its label or its sql in `Metrics::statements`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatementMetrics {
+    pub sql: String,
+    pub calls: u64,
+    pub errors: u64,
+    pub rows: u64,
+    pub execution: Histogram,
+}
+
+/// A snapshot of a connection's metrics, see `Sqlite::metrics`.
+/// Clones of a handle, its transactions and a pool's readers share them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    pub calls: u64,
+    /// How long calls waited in the queue before the worker ran them
+    pub queue_wait: Histogram,
+    pub execution: Histogram,
+    /// Unlabeled statements past the first 256 are counted under `"other"`
+    pub statements: BTreeMap<String, StatementMetrics>,
+    pub rows: u64,
+    /// Failed calls by `Error::code`
+    pub errors: BTreeMap<&'static str, u64>,
+    pub active_streams: u64,
+    pub open_transactions: u64,
+}
+
+impl Metrics {
+    /// Renders the snapshot in the prometheus text format
+    pub fn prometheus(&self) -> String {
+        let mut out = String::new();
+        counter(&mut out, "static_sqlite_calls_total", "", self.calls);
+        histogram(
+            &mut out,
+            "static_sqlite_queue_wait_seconds",
+            "",
+            &self.queue_wait,
+        );
+        histogram(
+            &mut out,
+            "static_sqlite_execution_seconds",
+            "",
+            &self.execution,
+        );
+        counter(&mut out, "static_sqlite_rows_total", "", self.rows);
+        for (name, statement) in &self.statements {
+            let label = format!("statement=\"{}\"", escape(name));
+            counter(
+                &mut out,
+                "static_sqlite_statement_calls_total",
+                &label,
+                statement.calls,
+            );
+            counter(
+                &mut out,
+                "static_sqlite_statement_errors_total",
+                &label,
+                statement.errors,
+            );
+            counter(
+                &mut out,
+                "static_sqlite_statement_rows_total",
+                &label,
+                statement.rows,
+            );
+            histogram(
+                &mut out,
+                "static_sqlite_statement_execution_seconds",
+                &label,
+                &statement.execution,
+            );
+        }
+        for (code, errors) in &self.errors {
+            let label = format!("code=\"{code}\"");
+            counter(&mut out, "static_sqlite_errors_total", &label, *errors);
+        }
+        let _ = writeln!(out, "static_sqlite_active_streams {}", self.active_streams);
+        let _ = writeln!(
+            out,
+            "static_sqlite_open_transactions {}",
+            self.open_transactions
+        );
+
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, labels: &str, value: u64) {
+    match labels {
+        "" => writeln!(out, "{name} {value}"),
+        labels => writeln!(out, "{name}{{{labels}}} {value}"),
+    }
+    .unwrap();
+}
+
+fn histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    let prefix = match labels {
+        "" => String::new(),
+        labels => format!("{labels},"),
+    };
+    for (bound, count) in &histogram.buckets {
+        let le = bound.as_secs_f64();
+        writeln!(out, "{name}_bucket{{{prefix}le=\"{le}\"}} {count}").unwrap();
+    }
+    let count = histogram.count;
+    writeln!(out, "{name}_bucket{{{prefix}le=\"+Inf\"}} {count}").unwrap();
+    let labels = match labels {
+        "" => String::new(),
+        labels => format!("{{{labels}}}"),
+    };
+    let sum = histogram.sum.as_secs_f64();
+    writeln!(out, "{name}_sum{labels} {sum}").unwrap();
+    writeln!(out, "{name}_count{labels} {count}").unwrap();
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Which statement a call runs, for the per statement metrics
+#[derive(Clone)]
+pub(crate) struct Label {
+    pub(crate) name: Option<&'static str>,
+    pub(crate) sql: String,
+}
+
+impl Label {
+    fn key(&self) -> &str {
+        self.name.unwrap_or(&self.sql)
+    }
+}
+
+/// Where the handles of a connection record their metrics
+#[derive(Default)]
+pub(crate) struct Recorder(Mutex<Metrics>);
+
+impl Recorder {
+    pub(crate) fn queued(&self, waited: Duration) {
+        let mut metrics = self.0.lock().unwrap();
+        metrics.calls += 1;
+        metrics.queue_wait.observe(waited);
+    }
+
+    pub(crate) fn ran(&self, label: Option<&Label>, running: Duration, succeeded: bool) {
+        let mut metrics = self.0.lock().unwrap();
+        metrics.execution.observe(running);
+        if let Some(label) = label {
+            let statement = metrics.statement(label);
+            statement.calls += 1;
+            statement.execution.observe(running);
+            if !succeeded {
+                statement.errors += 1;
+            }
+        }
+    }
+
+    pub(crate) fn rows(&self, label: Option<&Label>, rows: usize) {
+        let mut metrics = self.0.lock().unwrap();
+        metrics.rows += rows as u64;
+        if let Some(label) = label {
+            metrics.statement(label).rows += rows as u64;
+        }
+    }
+
+    pub(crate) fn error(&self, err: &Error) {
+        *self.0.lock().unwrap().errors.entry(err.code()).or_default() += 1;
+    }
+
+    /// Counts an active stream until the guard is dropped
+    pub(crate) fn stream(self: &Arc<Self>) -> Active {
+        self.0.lock().unwrap().active_streams += 1;
+        Active(self.clone(), |metrics| &mut metrics.active_streams)
+    }
+
+    /// Counts an open transaction until the guard is dropped
+    pub(crate) fn transaction(self: &Arc<Self>) -> Active {
+        self.0.lock().unwrap().open_transactions += 1;
+        Active(self.clone(), |metrics| &mut metrics.open_transactions)
+    }
+}
+
+impl Metrics {
+    fn statement(&mut self, label: &Label) -> &mut StatementMetrics {
+        let key = label.key();
+        let full = self.statements.len() >= MAX_STATEMENTS;
+        let (key, sql) = match label.name {
+            None if full && !self.statements.contains_key(key) => (OTHER_STATEMENTS, ""),
+            _ => (key, label.sql.as_str()),
+        };
+        self.statements
+            .entry(key.to_string())
+            .or_insert_with(|| StatementMetrics {
+                sql: sql.to_string(),
+                ..Default::default()
+            })
+    }
+}
+
+pub(crate) struct Active(Arc<Recorder>, fn(&mut Metrics) -> &mut u64);
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        *(self.1)(&mut self.0 .0.lock().unwrap()) -= 1;
+    }
+}
+
+impl Sqlite {
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.0.lock().unwrap().clone()
+    }
+
+    /// A handle to the same connection whose statements show up under `name`
+    /// in `Metrics::statements`, generated functions use their own name
+    pub fn labeled(&self, name: &'static str) -> Sqlite {
+        let mut db = self.clone();
+        db.label = Some(name);
+        db
+    }
+
+    pub(crate) fn label(&self, sql: &str) -> Label {
+        Label {
+            name: self.label,
+            sql: sql.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(75));
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_secs(60));
+
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.buckets[0], (Duration::from_micros(50), 0));
+        assert_eq!(histogram.buckets[1], (Duration::from_micros(100), 1));
+        assert_eq!(histogram.buckets[6], (Duration::from_millis(5), 2));
+        assert_eq!(histogram.buckets.last().unwrap().1, 2);
+    }
+
+    #[test]
+    fn unlabeled_statements_are_capped() {
+        let recorder = Recorder::default();
+        for i in 0..MAX_STATEMENTS + 44 {
+            let label = Label {
+                name: None,
+                sql: format!("select {i}"),
+            };
+            recorder.ran(Some(&label), Duration::ZERO, true);
+        }
+        let label = Label {
+            name: Some("named"),
+            sql: "select 1".into(),
+        };
+        recorder.ran(Some(&label), Duration::ZERO, true);
+
+        let metrics = recorder.0.lock().unwrap();
+        assert_eq!(metrics.statements.len(), MAX_STATEMENTS + 2);
+        assert_eq!(metrics.statements[OTHER_STATEMENTS].calls, 44);
+        assert_eq!(metrics.statements["select 0"].calls, 1);
+        assert_eq!(metrics.statements["named"].calls, 1);
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{open_bounded, open_with, Error, OpenOptions, Result, Sqlite};
+
+/// How many sql strings `Readers::readonly` remembers, the ones seen
+/// afterwards are prepared every time
+const MAX_READONLY: usize = 256;
+
+/// How `Pool::open` opens its connections
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    /// How many read only connections to open next to the writer
+    pub readers: usize,
+    /// Used for the writer, the readers open the same way but read only
+    pub open: OpenOptions,
+    /// Statements run on every connection right after it is opened,
+    /// like `pragma foreign_keys = on`
+    pub init: Vec<String>,
+    /// Bounds the writer's queue, see `open_bounded`
+    pub queue_capacity: Option<usize>,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            readers: 4,
+            open: OpenOptions::default(),
+            init: vec![],
+            queue_capacity: None,
+        }
+    }
+}
+
+/// One writer connection plus read only connections on the same wal mode
+/// database. Derefs to `Sqlite`, so generated functions take `&pool`: read
+/// only statements that return rows run on an idle reader, everything else
+/// on the writer.
+/// `call` always runs on the writer, `read` on a reader
+pub struct Pool {
+    db: Sqlite,
+}
+
+impl Pool {
+    /// Opens the writer, switches the database to wal mode and opens the readers.
+    /// In memory databases do not work, as every connection would get its own
+    pub async fn open(path: impl ToString, options: PoolOptions) -> Result<Pool> {
+        let path = path.to_string();
+        let mut db = match options.queue_capacity {
+            Some(capacity) => open_bounded(&path, options.open.clone(), capacity).await?,
+            None => open_with(&path, options.open.clone()).await?,
+        };
+        let init = options.init.clone();
+        db.call(move |conn| {
+            let mode = conn.rows("pragma journal_mode = wal", &[])?;
+            if mode
+                .first()
+                .and_then(|row| row.first())
+                .map(|(_, mode)| mode)
+                != Some(&"wal".into())
+            {
+                return Err(Error::Sqlite("pool requires a database in wal mode".into()));
+            }
+            init.iter()
+                .try_for_each(|sql| conn.execute_all(sql).map(|_| ()))
+        })
+        .await?;
+
+        let mut readers = Vec::with_capacity(options.readers);
+        for _ in 0..options.readers {
+            let open = OpenOptions {
+                read_only: true,
+                create: false,
+                ..options.open.clone()
+            };
+            let mut reader = open_with(&path, open).await?;
+            reader.metrics = db.metrics.clone();
+            let init = options.init.clone();
+            reader
+                .call(move |conn| {
+                    init.iter()
+                        .try_for_each(|sql| conn.execute_all(sql).map(|_| ()))
+                })
+                .await?;
+            readers.push(Reader {
+                db: reader,
+                in_flight: Arc::default(),
+            });
+        }
+        db.readers = Some(Arc::new(Readers {
+            conns: readers,
+            readonly: Mutex::default(),
+        }));
+
+        Ok(Pool { db })
+    }
+
+    /// Closes the readers, then the writer
+    pub async fn close(self) -> Result<()> {
+        if let Some(readers) = &self.db.readers {
+            for reader in &readers.conns {
+                reader.db.clone().close().await?;
+            }
+        }
+        self.db.close().await
+    }
+}
+
+impl Deref for Pool {
+    type Target = Sqlite;
+
+    fn deref(&self) -> &Self::Target {
+        &self.db
+    }
+}
+
+pub(crate) struct Readers {
+    conns: Vec<Reader>,
+    /// Whether a sql string can run on a reader, up to `MAX_READONLY` of them
+    readonly: Mutex<HashMap<String, bool>>,
+}
+
+struct Reader {
+    db: Sqlite,
+    in_flight: Arc<AtomicUsize>,
+}
+
+/// Counts a call against its reader until dropped
+pub(crate) struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Readers {
+    /// The reader with the fewest calls in flight
+    pub(crate) fn idle(&self) -> Option<(Sqlite, InFlight)> {
+        let reader = self
+            .conns
+            .iter()
+            .min_by_key(|reader| reader.in_flight.load(Ordering::SeqCst))?;
+        reader.in_flight.fetch_add(1, Ordering::SeqCst);
+
+        Some((reader.db.clone(), InFlight(reader.in_flight.clone())))
+    }
+
+    /// Prepares `sql` on a reader the first time it is seen. Only read only
+    /// statements that return rows run on a reader, `sqlite3_stmt_readonly`
+    /// is also true for `begin`, `commit`, `savepoint`, `attach` and pragmas
+    /// that set connection state. Statements that fail to prepare count as
+    /// writes, so the writer reports the error
+    pub(crate) async fn readonly(&self, sql: &str) -> Result<bool> {
+        if let Some(readonly) = self.readonly.lock().unwrap().get(sql) {
+            return Ok(*readonly);
+        }
+        let Some((reader, _in_flight)) = self.idle() else {
+            return Ok(false);
+        };
+        let owned = sql.to_string();
+        let readonly = reader
+            .call(move |conn| {
+                Ok(conn
+                    .prepare(&owned, &[])
+                    .map(|stmt| stmt.readonly() && stmt.column_count() > 0))
+            })
+            .await?;
+        match readonly {
+            Ok(readonly) => {
+                let mut cache = self.readonly.lock().unwrap();
+                if cache.len() < MAX_READONLY {
+                    cache.insert(sql.to_string(), readonly);
+                }
+                Ok(readonly)
+            }
+            Err(_) => Ok(false),
+        }
+    }
+}
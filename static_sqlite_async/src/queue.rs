@@ -0,0 +1,244 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{select, Receiver, Sender};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{Error, Message, Result, Sqlite};
+
+/// Interactive calls always run before the queued background ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Priority {
+    #[default]
+    Interactive,
+    Background,
+}
+
+/// A snapshot of the worker queue, see `Sqlite::queue_stats`
+#[derive(Debug, Clone, Default)]
+pub struct QueueStats {
+    /// `None` when the queue is unbounded
+    pub capacity: Option<usize>,
+    pub interactive_depth: usize,
+    pub background_depth: usize,
+    /// How long the messages taken off the queue so far waited in it
+    pub interactive_wait: WaitTimes,
+    pub background_wait: WaitTimes,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WaitTimes {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl WaitTimes {
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => self.total / count as u32,
+        }
+    }
+
+    fn record(&mut self, waited: Duration) {
+        self.count += 1;
+        self.total += waited;
+        self.max = self.max.max(waited);
+    }
+}
+
+/// The sending half of the worker queue, one lane per `Priority`.
+/// With a capacity, senders wait for a free slot
+#[derive(Clone)]
+pub(crate) struct Queue {
+    interactive: Sender<Queued>,
+    background: Sender<Queued>,
+    slots: Option<Arc<Semaphore>>,
+    capacity: Option<usize>,
+    waits: Arc<Mutex<[WaitTimes; 2]>>,
+    /// Set once a close is queued, nothing is queued behind it
+    closed: Arc<AtomicBool>,
+}
+
+pub(crate) struct QueueReceiver {
+    interactive: Receiver<Queued>,
+    background: Receiver<Queued>,
+    waits: Arc<Mutex<[WaitTimes; 2]>>,
+    closed: Arc<AtomicBool>,
+}
+
+struct Queued {
+    message: Message,
+    priority: Priority,
+    queued_at: Instant,
+    /// Frees the slot once the worker takes the message
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+pub(crate) fn queue(capacity: Option<usize>) -> (Queue, QueueReceiver) {
+    let (interactive, interactive_receiver) = crossbeam_channel::unbounded();
+    let (background, background_receiver) = crossbeam_channel::unbounded();
+    let waits = Arc::new(Mutex::new([WaitTimes::default(); 2]));
+    let closed = Arc::new(AtomicBool::new(false));
+    let queue = Queue {
+        interactive,
+        background,
+        slots: capacity.map(|capacity| Arc::new(Semaphore::new(capacity.max(1)))),
+        capacity,
+        waits: waits.clone(),
+        closed: closed.clone(),
+    };
+    let receiver = QueueReceiver {
+        interactive: interactive_receiver,
+        background: background_receiver,
+        waits,
+        closed,
+    };
+
+    (queue, receiver)
+}
+
+impl Queue {
+    /// Waits for a free slot when the queue is full
+    pub(crate) async fn send(&self, message: Message, priority: Priority) -> Result<()> {
+        let slot = match &self.slots {
+            Some(slots) => Some(
+                slots
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .map_err(|_| Error::ConnectionClosed)?,
+            ),
+            None => None,
+        };
+        self.push(message, priority, slot)
+    }
+
+    /// Skips the capacity, for messages sent where waiting is not possible
+    pub(crate) fn send_now(&self, message: Message, priority: Priority) -> Result<()> {
+        self.push(message, priority, None)
+    }
+
+    /// Queues `close` behind everything queued so far, in either lane,
+    /// and refuses the messages sent afterwards
+    pub(crate) fn close(&self, close: Message) -> Result<()> {
+        self.closed.store(true, Ordering::SeqCst);
+        self.lane(close, Priority::Background, None)
+    }
+
+    fn push(
+        &self,
+        message: Message,
+        priority: Priority,
+        slot: Option<OwnedSemaphorePermit>,
+    ) -> Result<()> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(Error::ConnectionClosed);
+        }
+        self.lane(message, priority, slot)
+    }
+
+    fn lane(
+        &self,
+        message: Message,
+        priority: Priority,
+        slot: Option<OwnedSemaphorePermit>,
+    ) -> Result<()> {
+        let lane = match priority {
+            Priority::Interactive => &self.interactive,
+            Priority::Background => &self.background,
+        };
+        lane.send(Queued {
+            message,
+            priority,
+            queued_at: Instant::now(),
+            _slot: slot,
+        })
+        .map_err(|_| Error::ConnectionClosed)
+    }
+
+    fn stats(&self) -> QueueStats {
+        let [interactive_wait, background_wait] = *self.waits.lock().unwrap();
+        QueueStats {
+            capacity: self.capacity,
+            interactive_depth: self.interactive.len(),
+            background_depth: self.background.len(),
+            interactive_wait,
+            background_wait,
+        }
+    }
+}
+
+impl QueueReceiver {
+    /// Blocks until there is a message, `None` once every sender is gone
+    pub(crate) fn recv(&self) -> Option<Message> {
+        if let Some(message) = self.try_recv() {
+            return Some(message);
+        }
+        let queued = select! {
+            recv(self.interactive) -> queued => queued.ok()?,
+            recv(self.background) -> queued => queued.ok()?,
+        };
+
+        Some(self.taken(queued))
+    }
+
+    /// Like `recv`, but gives up at `deadline`
+    pub(crate) fn recv_deadline(&self, deadline: Instant) -> Option<Message> {
+        if let Some(message) = self.try_recv() {
+            return Some(message);
+        }
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let queued = select! {
+            recv(self.interactive) -> queued => queued.ok()?,
+            recv(self.background) -> queued => queued.ok()?,
+            default(timeout) => return None,
+        };
+
+        Some(self.taken(queued))
+    }
+
+    pub(crate) fn try_recv(&self) -> Option<Message> {
+        let queued = self
+            .interactive
+            .try_recv()
+            .or_else(|_| self.background.try_recv())
+            .ok()?;
+
+        Some(self.taken(queued))
+    }
+
+    /// Accepts messages again, after sqlite refused to close
+    pub(crate) fn reopen(&self) {
+        self.closed.store(false, Ordering::SeqCst);
+    }
+
+    fn taken(&self, queued: Queued) -> Message {
+        self.waits.lock().unwrap()[queued.priority as usize].record(queued.queued_at.elapsed());
+        queued.message
+    }
+}
+
+impl Sqlite {
+    /// A handle to the same connection whose calls queue with `priority`
+    pub fn with_priority(&self, priority: Priority) -> Sqlite {
+        let mut db = self.clone();
+        db.priority = priority;
+        db
+    }
+
+    /// Shorthand for `with_priority(Priority::Background)`, for batch jobs
+    pub fn background(&self) -> Sqlite {
+        self.with_priority(Priority::Background)
+    }
+
+    pub fn queue_stats(&self) -> QueueStats {
+        self.queue.stats()
+    }
+}
@@ -0,0 +1,105 @@
+use std::{any::Any, time::Duration};
+
+use futures::Stream;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{core, start_with, OpenOptions, Result, Sqlite};
+
+/// What happened to the connection behind a `Sqlite`, see `Sqlite::events`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A call panicked, the panic was turned into `Error::Panicked`
+    Panicked(String),
+    /// A supervised connection was reopened after a panic
+    Reopened,
+    /// Reopening failed, the old connection stays in use
+    ReopenFailed(String),
+}
+
+/// Opens `path` like `open_with`, then runs `init`, which is where functions,
+/// hooks and pragmas go. Whenever a call panics the connection is reopened
+/// and `init` runs again. In memory databases come back empty
+pub async fn open_supervised<F>(
+    path: impl ToString,
+    options: OpenOptions,
+    init: F,
+) -> Result<Sqlite>
+where
+    F: Fn(&core::Sqlite) -> Result<()> + Send + 'static,
+{
+    let path = path.to_string();
+    let open = move || {
+        let conn = core::Sqlite::open_with(&path, &options)?;
+        init(&conn)?;
+        Ok(conn)
+    };
+
+    start_with(open, true, None).await
+}
+
+impl Sqlite {
+    /// Runs `select 1` on the worker, returning how long the round trip took
+    pub async fn ping(&self) -> Result<Duration> {
+        let started = std::time::Instant::now();
+        self.call(|conn| conn.execute_all("select 1")).await?;
+        Ok(started.elapsed())
+    }
+
+    /// Every `Event` from now on. Events are dropped for subscribers that fall far behind
+    pub fn events(&self) -> impl Stream<Item = Event> {
+        let mut receiver = self.events.subscribe();
+        async_stream::stream! {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => yield event,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+}
+
+pub(crate) struct Worker {
+    pub(crate) open: Box<dyn Fn() -> Result<core::Sqlite> + Send>,
+    pub(crate) supervised: bool,
+    pub(crate) events: broadcast::Sender<Event>,
+}
+
+impl Worker {
+    /// Called after a message panicked. Rolls back whatever transaction the
+    /// panic left open, supervised connections are reopened instead
+    pub(crate) fn recover(&self, conn: &mut core::Sqlite, panic: Box<dyn Any + Send>) {
+        let _ = self.events.send(Event::Panicked(panic_message(&*panic)));
+        if !self.supervised {
+            if !conn.is_autocommit() {
+                let _ = conn.execute_all("rollback");
+            }
+            return;
+        }
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| (self.open)())) {
+            Ok(Ok(reopened)) => {
+                *conn = reopened;
+                let _ = self.events.send(Event::Reopened);
+            }
+            Ok(Err(err)) => {
+                let _ = self.events.send(Event::ReopenFailed(err.to_string()));
+            }
+            Err(panic) => {
+                let _ = self
+                    .events
+                    .send(Event::ReopenFailed(panic_message(&*panic)));
+            }
+        }
+    }
+}
+
+pub(crate) fn panic_message(panic: &(dyn Any + Send)) -> String {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match panic.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown panic".into(),
+        },
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{core, Error, Sqlite};
+
+impl Sqlite {
+    /// A handle to the same connection whose calls, including the ones
+    /// generated functions make, fail after `timeout`
+    pub fn with_timeout(&self, timeout: Duration) -> Sqlite {
+        let mut db = self.clone();
+        db.timeout = Some(timeout);
+        db
+    }
+
+    /// The timeout of this handle and the clones made from it afterwards
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+/// Where a call with a timeout is, shared by the caller and the worker
+pub(crate) struct Progress {
+    queued_at: Instant,
+    state: Mutex<State>,
+}
+
+enum State {
+    Queued,
+    Running(Instant, core::InterruptHandle),
+    Done(Duration, Duration),
+    /// Timed out in the queue, the worker skips it
+    Abandoned,
+}
+
+impl Progress {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Progress {
+            queued_at: Instant::now(),
+            state: Mutex::new(State::Queued),
+        })
+    }
+
+    /// Called by the worker, false if the caller gave up already
+    pub(crate) fn start(&self, conn: &core::Sqlite) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Abandoned => false,
+            _ => {
+                conn.clear_interrupt();
+                *state = State::Running(Instant::now(), conn.interrupt_handle());
+                true
+            }
+        }
+    }
+
+    /// Clears the interrupt of a timed out call, under the lock so
+    /// a late `time_out` can not leave it set for the next call
+    pub(crate) fn finish(&self, conn: &core::Sqlite) {
+        let mut state = self.state.lock().unwrap();
+        if let State::Running(started, _) = *state {
+            *state = State::Done(started - self.queued_at, started.elapsed());
+        }
+        conn.clear_interrupt();
+    }
+
+    /// Like `time_out`, for the calls that must not be interrupted. None
+    /// once the worker started the call, the caller waits for it then
+    pub(crate) fn abandon(&self) -> Option<Error> {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Queued | State::Abandoned => {
+                *state = State::Abandoned;
+                Some(Error::QueueTimeout {
+                    queued: self.queued_at.elapsed(),
+                })
+            }
+            State::Running(..) | State::Done(..) => None,
+        }
+    }
+
+    /// Called by the caller once its deadline passed. Interrupts the running
+    /// statement, under the lock so it never hits the next call's statement
+    pub(crate) fn time_out(&self) -> Error {
+        let mut state = self.state.lock().unwrap();
+        match &*state {
+            State::Queued | State::Abandoned => {
+                *state = State::Abandoned;
+                Error::QueueTimeout {
+                    queued: self.queued_at.elapsed(),
+                }
+            }
+            State::Running(started, interrupt) => {
+                interrupt.interrupt();
+                Error::Timeout {
+                    queued: *started - self.queued_at,
+                    running: started.elapsed(),
+                }
+            }
+            State::Done(queued, running) => Error::Timeout {
+                queued: *queued,
+                running: *running,
+            },
+        }
+    }
+}
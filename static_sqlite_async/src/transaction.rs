@@ -0,0 +1,112 @@
+use std::{ops::Deref, sync::Arc};
+
+use tokio::sync::oneshot;
+
+use crate::{core, queue, Error, LastHandle, Message, Result, Sqlite, TransactionMode};
+
+/// A transaction started with `Sqlite::begin`. The worker runs nothing but
+/// this transaction's calls until it is committed, rolled back or dropped,
+/// dropping it rolls back. Derefs to `Sqlite`, so generated functions take `&tx`
+pub struct Transaction {
+    db: Sqlite,
+}
+
+impl Transaction {
+    pub async fn commit(self) -> Result<()> {
+        self.db
+            .call(|conn| conn.execute_all("commit").map(|_| ()))
+            .await
+    }
+
+    pub async fn rollback(self) -> Result<()> {
+        self.db
+            .call(|conn| conn.execute_all("rollback").map(|_| ()))
+            .await
+    }
+}
+
+impl Deref for Transaction {
+    type Target = Sqlite;
+
+    fn deref(&self) -> &Self::Target {
+        &self.db
+    }
+}
+
+impl Sqlite {
+    /// Runs `f` in a transaction on the worker thread, so no other call can
+    /// interleave with it. Commits when `f` returns `Ok`, rolls back otherwise
+    pub async fn transaction<F, R>(&self, mode: TransactionMode, f: F) -> Result<R>
+    where
+        F: FnOnce(&core::Sqlite) -> Result<R> + 'static + Send,
+        R: Send + 'static,
+    {
+        let metrics = self.metrics.clone();
+        self.call(move |conn| {
+            let _open = metrics.transaction();
+            conn.transaction(mode, f)
+        })
+        .await
+    }
+
+    pub async fn begin(&self) -> Result<Transaction> {
+        self.begin_with(TransactionMode::Deferred).await
+    }
+
+    pub async fn begin_with(&self, mode: TransactionMode) -> Result<Transaction> {
+        let (queue, receiver) = queue::queue(None);
+        let (begun, begin_result) = oneshot::channel::<Result<()>>();
+        let metrics = self.metrics.clone();
+
+        // not `call`, its reply would only come once the transaction is over
+        let message = Message::Execute(Box::new(move |conn| {
+            if let Err(err) = conn.execute_all(mode.begin()) {
+                let _ = begun.send(Err(err));
+                return;
+            }
+            let _open = metrics.transaction();
+            let _ = begun.send(Ok(()));
+            run_transaction(conn, receiver);
+        }));
+        self.queue.send(message, self.priority).await?;
+        begin_result.await.map_err(|_| Error::ConnectionClosed)??;
+
+        Ok(Transaction {
+            db: Sqlite {
+                _last: Arc::new(LastHandle(queue.clone())),
+                queue,
+                readers: None,
+                events: self.events.clone(),
+                timeout: self.timeout,
+                priority: self.priority,
+                batch: None,
+                metrics: self.metrics.clone(),
+                label: self.label,
+            },
+        })
+    }
+}
+
+/// Runs the transaction's calls until it is committed or rolled back,
+/// rolling back if every handle to it is dropped first
+fn run_transaction(conn: &core::Sqlite, receiver: queue::QueueReceiver) {
+    while let Some(message) = receiver.recv() {
+        match message {
+            Message::Execute(f) => f(conn),
+            Message::Stream(f) => f(conn, &mut |_, _| false),
+            Message::Batch(write) => write.run(conn),
+            Message::Close { reply, .. } => {
+                if let Some(reply) = reply {
+                    let _ = reply.send(Ok(()));
+                }
+                break;
+            }
+        }
+        if conn.is_autocommit() {
+            return;
+        }
+    }
+    if !conn.is_autocommit() {
+        let _ = conn.execute_all("rollback");
+    }
+}
@@ -0,0 +1,146 @@
+use static_sqlite_ffi::{sqlite3_bind_pointer, sqlite3_stmt, sqlite3_value, sqlite3_value_pointer};
+
+use std::{
+    ffi::{c_int, c_void, CStr},
+    sync::Arc,
+};
+
+use crate::{ConstraintOp, Error, IndexInfo, Result, Sqlite, VTab, VTabRow, Value};
+
+/// The pointer type `Value::Array` is bound with, see `sqlite3_bind_pointer`
+pub(crate) const POINTER_TYPE: &CStr = c"rarray";
+
+impl Value {
+    /// A list parameter for `where id in rarray(?)`
+    pub fn array<T: Into<Value>>(values: impl IntoIterator<Item = T>) -> Value {
+        Value::Array(Arc::new(values.into_iter().map(Into::into).collect()))
+    }
+}
+
+pub(crate) unsafe fn bind_array(
+    stmt: *mut sqlite3_stmt,
+    index: c_int,
+    values: &Arc<Vec<Value>>,
+) -> c_int {
+    sqlite3_bind_pointer(
+        stmt,
+        index,
+        Arc::into_raw(values.clone()) as *mut c_void,
+        POINTER_TYPE.as_ptr(),
+        Some(drop_array),
+    )
+}
+
+/// Clones the array behind a pointer value, if it is one
+pub(crate) unsafe fn array_from_raw(value: *mut sqlite3_value) -> Option<Arc<Vec<Value>>> {
+    let ptr = sqlite3_value_pointer(value, POINTER_TYPE.as_ptr()) as *const Vec<Value>;
+    match ptr.is_null() {
+        true => None,
+        false => {
+            Arc::increment_strong_count(ptr);
+            Some(Arc::from_raw(ptr))
+        }
+    }
+}
+
+pub(crate) unsafe extern "C" fn drop_array(ptr: *mut c_void) {
+    drop(Arc::from_raw(ptr as *const Vec<Value>));
+}
+
+/// The eponymous `rarray(?)` table valued function every connection gets
+pub(crate) struct RArray;
+
+const ARRAY_COLUMN: i32 = 1;
+
+impl VTab for RArray {
+    type Aux = ();
+    type Rows = std::vec::IntoIter<Result<VTabRow>>;
+
+    fn connect(_aux: &(), _args: &[String]) -> Result<(String, Self)> {
+        Ok(("create table x(value, array hidden)".into(), RArray))
+    }
+
+    fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+        let array = info.constraints().into_iter().position(|constraint| {
+            constraint.column == ARRAY_COLUMN
+                && constraint.op == ConstraintOp::Eq
+                && constraint.usable
+        });
+        match array {
+            Some(i) => {
+                info.use_constraint(i, 1, true);
+                info.set_idx_num(1);
+                info.set_estimated_cost(1.0);
+            }
+            // without its argument rarray is empty, make sure sqlite never picks this plan
+            None => info.set_estimated_cost(f64::MAX),
+        }
+
+        Ok(())
+    }
+
+    fn filter(&self, idx_num: i32, _idx_str: Option<&str>, args: &[Value]) -> Result<Self::Rows> {
+        let values = match (idx_num, args) {
+            (1, [Value::Array(values)]) => values.clone(),
+            (1, [Value::Null]) | (0, _) => Arc::default(),
+            _ => {
+                return Err(Error::Sqlite(
+                    "rarray() expects a Value::Array parameter".into(),
+                ))
+            }
+        };
+        let rows: Vec<_> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                Ok(VTabRow {
+                    rowid: i as i64 + 1,
+                    values: vec![value.clone()],
+                })
+            })
+            .collect();
+
+        Ok(rows.into_iter())
+    }
+}
+
+impl Sqlite {
+    pub(crate) fn create_array_module(&self) -> Result<()> {
+        self.create_eponymous_module::<RArray>("rarray", ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rarray_works() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        db.execute_all("create table Row (id integer primary key, name text)")?;
+        for name in ["a", "b", "c"] {
+            db.execute("insert into Row (name) values (?)", vec![name.into()])?;
+        }
+
+        let rows = db.rows(
+            "select id from Row where id in rarray(?) order by id",
+            &[Value::array([1i64, 3])],
+        )?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1][0].1, Value::Integer(3));
+
+        let rows = db.rows(
+            "select id from Row where name in (select value from rarray(?))",
+            &[Value::array(["b"])],
+        )?;
+        assert_eq!(rows, vec![vec![("id".to_string(), Value::Integer(2))]]);
+
+        let rows = db.rows(
+            "select id from Row where id in rarray(?)",
+            &[Value::array(Vec::<i64>::new())],
+        )?;
+        assert!(rows.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,23 +1,36 @@
 use static_sqlite_ffi::{
     sqlite3, sqlite3_bind_blob, sqlite3_bind_double, sqlite3_bind_int64, sqlite3_bind_null,
     sqlite3_bind_parameter_count, sqlite3_bind_parameter_name, sqlite3_bind_text, sqlite3_changes,
-    sqlite3_close, sqlite3_column_bytes, sqlite3_column_count, sqlite3_column_double,
-    sqlite3_column_int64, sqlite3_column_name, sqlite3_column_origin_name,
-    sqlite3_column_table_name, sqlite3_column_text, sqlite3_column_type, sqlite3_errmsg,
-    sqlite3_finalize, sqlite3_open, sqlite3_prepare_v2, sqlite3_step, sqlite3_stmt,
+    sqlite3_close, sqlite3_close_v2, sqlite3_column_bytes, sqlite3_column_count,
+    sqlite3_column_double, sqlite3_column_int64, sqlite3_column_name, sqlite3_column_origin_name,
+    sqlite3_column_table_name, sqlite3_column_text, sqlite3_column_type, sqlite3_destructor_type,
+    sqlite3_errmsg, sqlite3_exec, sqlite3_finalize, sqlite3_get_autocommit, sqlite3_interrupt,
+    sqlite3_next_stmt, sqlite3_open_v2, sqlite3_prepare_v2, sqlite3_sql, sqlite3_step,
+    sqlite3_stmt, sqlite3_stmt_readonly, sqlite3_threadsafe, SQLITE_OPEN_CREATE,
+    SQLITE_OPEN_NOMUTEX, SQLITE_OPEN_READONLY, SQLITE_OPEN_READWRITE, SQLITE_OPEN_URI,
 };
 
 use std::{
-    ffi::{c_char, c_int, CStr, CString, NulError},
+    ffi::{c_char, c_int, c_void, CStr, CString, NulError},
     marker::PhantomData,
     num::TryFromIntError,
     ops::Deref,
     str::Utf8Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
 const SQLITE_ROW: i32 = static_sqlite_ffi::SQLITE_ROW as i32;
 const SQLITE_DONE: i32 = static_sqlite_ffi::SQLITE_DONE as i32;
 
+/// `SQLITE_TRANSIENT`, sqlite copies the value before the bind returns
+fn transient() -> sqlite3_destructor_type {
+    Some(unsafe { std::mem::transmute::<isize, unsafe extern "C" fn(*mut c_void)>(-1) })
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("io error: {0}")]
@@ -32,42 +45,212 @@ pub enum Error {
     UniqueConstraint(String),
     #[error("sqlite file closed")]
     ConnectionClosed,
+    #[error("timed out after waiting {queued:?} in the queue")]
+    QueueTimeout { queued: Duration },
+    #[error("timed out after waiting {queued:?} in the queue and running for {running:?}")]
+    Timeout { queued: Duration, running: Duration },
+    #[error("timed out after waiting {waited:?} for the connection to close")]
+    CloseTimeout { waited: Duration },
+    #[error("call panicked: {0}")]
+    Panicked(String),
     #[error("sqlite row not found")]
     RowNotFound,
+    #[error("no such column: {0}")]
+    ColumnNotFound(String),
     #[error("sqlite returned too many rows in result")]
     TooManyRowsInResult,
+    #[error("migration {version} ({name}) was changed after it was applied")]
+    MigrationChanged { version: i64, name: String },
+    #[error("migration {version} ({name}) was applied but is missing")]
+    MigrationMissing { version: i64, name: String },
+    #[error("migration {version} ({name}) comes before the applied migration {applied}")]
+    MigrationOutOfOrder {
+        version: i64,
+        name: String,
+        applied: i64,
+    },
+    #[error("no migration is named {0}")]
+    MigrationNotFound(String),
+    #[error("migration {0} has no rust code, give it some with Migrations::with_step")]
+    MigrationStepMissing(String),
+    #[error("migration {version} ({name}) leaves rows that violate foreign keys")]
+    MigrationForeignKeys { version: i64, name: String },
+    #[error("migration {version} ({name}) has no down sql")]
+    MigrationIrreversible { version: i64, name: String },
+    #[error("migration {version} ({name}) has more than one -- migrate:down")]
+    MigrationDownRepeated { version: i64, name: String },
     #[error(transparent)]
     Utf8Error(#[from] Utf8Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Clone)]
+impl Error {
+    /// A short name of the variant, for metrics and logs
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Io(_) => "io",
+            Error::Null(_) => "null",
+            Error::TryFromInt(_) => "try_from_int",
+            Error::Sqlite(_) => "sqlite",
+            Error::UniqueConstraint(_) => "unique_constraint",
+            Error::ConnectionClosed => "connection_closed",
+            Error::QueueTimeout { .. } => "queue_timeout",
+            Error::Timeout { .. } => "timeout",
+            Error::CloseTimeout { .. } => "close_timeout",
+            Error::Panicked(_) => "panicked",
+            Error::RowNotFound => "row_not_found",
+            Error::ColumnNotFound(_) => "column_not_found",
+            Error::TooManyRowsInResult => "too_many_rows",
+            Error::MigrationChanged { .. } => "migration_changed",
+            Error::MigrationMissing { .. } => "migration_missing",
+            Error::MigrationOutOfOrder { .. } => "migration_out_of_order",
+            Error::MigrationNotFound(_) => "migration_not_found",
+            Error::MigrationStepMissing(_) => "migration_step_missing",
+            Error::MigrationForeignKeys { .. } => "migration_foreign_keys",
+            Error::MigrationIrreversible { .. } => "migration_irreversible",
+            Error::MigrationDownRepeated { .. } => "migration_down_repeated",
+            Error::Utf8Error(_) => "utf8",
+        }
+    }
+}
+
+/// An open connection, closed when dropped or with `close`
+#[derive(Debug)]
 pub struct Sqlite {
-    db: *mut static_sqlite_ffi::sqlite3,
+    pub(crate) db: *mut static_sqlite_ffi::sqlite3,
+    interrupt: Arc<Mutex<RawDb>>,
+    /// Set by `InterruptHandle::interrupt` until `clear_interrupt`
+    interrupted: Arc<AtomicBool>,
 }
 
-unsafe impl Sync for Sqlite {}
+// connections are opened in multi-thread mode (`SQLITE_OPEN_NOMUTEX`),
+// so one can move between threads but never be used by two at once
 unsafe impl Send for Sqlite {}
 
+/// How `Sqlite::open_with` opens a database
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    pub read_only: bool,
+    /// Create the database if it does not exist, ignored when `read_only`
+    pub create: bool,
+    /// Interpret the path as a `file:` uri
+    pub uri: bool,
+    /// The name of a vfs registered with `register_vfs`, the default vfs if `None`
+    pub vfs: Option<String>,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            read_only: false,
+            create: true,
+            uri: false,
+            vfs: None,
+        }
+    }
+}
+
+impl OpenOptions {
+    fn flags(&self) -> c_int {
+        let mut flags = SQLITE_OPEN_NOMUTEX;
+        flags |= match (self.read_only, self.create) {
+            (true, _) => SQLITE_OPEN_READONLY,
+            (false, true) => SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE,
+            (false, false) => SQLITE_OPEN_READWRITE,
+        };
+        if self.uri {
+            flags |= SQLITE_OPEN_URI;
+        }
+        flags as c_int
+    }
+}
+
 impl Sqlite {
     pub fn open(path: &str) -> Result<Self> {
+        Self::open_with(path, &OpenOptions::default())
+    }
+
+    pub fn open_with(path: &str, options: &OpenOptions) -> Result<Self> {
         let c_path = CString::new(path)?;
+        let vfs = options.vfs.as_deref().map(CString::new).transpose()?;
         let mut db: *mut sqlite3 = core::ptr::null_mut();
 
+        if unsafe { sqlite3_threadsafe() } == 0 {
+            return Err(Error::Sqlite(
+                "sqlite was compiled without thread safety".into(),
+            ));
+        }
+
         unsafe {
-            if sqlite3_open(c_path.as_ptr(), &mut db) != 0 {
-                let error = CStr::from_ptr(static_sqlite_ffi::sqlite3_errmsg(db))
-                    .to_string_lossy()
-                    .into_owned();
+            let vfs = vfs.as_ref().map_or(std::ptr::null(), |vfs| vfs.as_ptr());
+            if sqlite3_open_v2(c_path.as_ptr(), &mut db, options.flags(), vfs) != 0 {
+                let error = match db.is_null() {
+                    true => "out of memory".to_string(),
+                    false => CStr::from_ptr(static_sqlite_ffi::sqlite3_errmsg(db))
+                        .to_string_lossy()
+                        .into_owned(),
+                };
+                sqlite3_close(db);
                 return Err(Error::Sqlite(error));
             }
         }
 
-        Ok(Sqlite { db })
+        let sqlite = Sqlite {
+            db,
+            interrupt: Arc::new(Mutex::new(RawDb(db))),
+            interrupted: Arc::default(),
+        };
+        sqlite.create_array_module()?;
+
+        Ok(sqlite)
     }
 
-    pub fn prepare(&self, sql: &str, params: &[Value]) -> Result<*mut sqlite3_stmt> {
+    /// Closes the connection, handing it back if sqlite refuses to because
+    /// statements or other handles are still open
+    pub fn close(mut self) -> std::result::Result<(), (Sqlite, Error)> {
+        let mut interrupt = self.interrupt.lock().unwrap();
+        let rc = unsafe { sqlite3_close(self.db) };
+        if rc == static_sqlite_ffi::SQLITE_OK as c_int {
+            interrupt.0 = std::ptr::null_mut();
+            drop(interrupt);
+            // closing null in Drop does nothing
+            self.db = std::ptr::null_mut();
+            return Ok(());
+        }
+        drop(interrupt);
+        let error = match rc == static_sqlite_ffi::SQLITE_BUSY as c_int {
+            true => Error::Sqlite(format!(
+                "unable to close, statements are not finalized: {}",
+                self.unfinalized().join("; ")
+            )),
+            false => self.last_error(),
+        };
+
+        Err((self, error))
+    }
+
+    /// The sql of every statement of this connection that is not finalized yet
+    pub fn unfinalized(&self) -> Vec<String> {
+        let mut statements = vec![];
+        unsafe {
+            let mut stmt = sqlite3_next_stmt(self.db, std::ptr::null_mut());
+            while !stmt.is_null() {
+                let sql = sqlite3_sql(stmt);
+                if !sql.is_null() {
+                    statements.push(CStr::from_ptr(sql).to_string_lossy().into_owned());
+                }
+                stmt = sqlite3_next_stmt(self.db, stmt);
+            }
+        }
+
+        statements
+    }
+
+    /// Prepares `sql` with `params` bound, text and blobs are copied since the
+    /// statement outlives the borrow of `params`
+    pub fn prepare(&self, sql: &str, params: &[Value]) -> Result<Statement<'_>> {
+        self.check_interrupt()?;
         let c_sql = CString::new(sql)?;
         let mut stmt: *mut sqlite3_stmt = core::ptr::null_mut();
         unsafe {
@@ -76,8 +259,12 @@ impl Sqlite {
                 let error = CStr::from_ptr(sqlite3_errmsg(self.db))
                     .to_string_lossy()
                     .into_owned();
-                return Err(Error::Sqlite(error));
+                Err(Error::Sqlite(error))
             } else {
+                let statement = Statement {
+                    raw: stmt,
+                    db: self,
+                };
                 for (i, param) in params.iter().enumerate() {
                     match param {
                         Value::Text(s) => {
@@ -87,7 +274,7 @@ impl Sqlite {
                                 (i + 1) as i32,
                                 s.as_ptr() as *const _,
                                 s.len() as c_int,
-                                None,
+                                transient(),
                             );
                         }
                         Value::Integer(n) => {
@@ -102,44 +289,38 @@ impl Sqlite {
                                 (i + 1) as i32,
                                 b.as_ptr() as *const _,
                                 b.len() as c_int,
-                                None,
+                                transient(),
                             );
                         }
                         Value::Null => {
                             sqlite3_bind_null(stmt, (i + 1) as i32);
                         }
+                        Value::Array(values) => {
+                            crate::array::bind_array(stmt, (i + 1) as i32, values);
+                        }
                     }
                 }
 
-                return Ok(stmt);
+                Ok(statement)
             }
         }
     }
 
     pub fn execute(&self, sql: &str, params: Vec<Value>) -> Result<i32> {
         unsafe {
-            let stmt = self.prepare(&sql, &params)?;
+            let stmt = self.prepare(sql, &params)?;
 
-            loop {
-                match sqlite3_step(stmt) {
-                    SQLITE_ROW | SQLITE_DONE => {
-                        break;
-                    }
-                    _ => {
-                        let error = CStr::from_ptr(sqlite3_errmsg(self.db))
-                            .to_string_lossy()
-                            .into_owned();
-                        return Err(Error::Sqlite(error));
-                    }
+            match sqlite3_step(stmt.raw) {
+                SQLITE_ROW | SQLITE_DONE => {}
+                _ => {
+                    let error = CStr::from_ptr(sqlite3_errmsg(self.db))
+                        .to_string_lossy()
+                        .into_owned();
+                    return Err(Error::Sqlite(error));
                 }
             }
 
-            if sqlite3_finalize(stmt) != 0 {
-                let error = CStr::from_ptr(sqlite3_errmsg(self.db))
-                    .to_string_lossy()
-                    .into_owned();
-                return Err(Error::Sqlite(error));
-            }
+            stmt.finalize()?;
 
             let changes = sqlite3_changes(self.db);
             Ok(changes)
@@ -150,9 +331,29 @@ impl Sqlite {
         self.execute(sql, vec![])
     }
 
-    pub fn query<T: FromRow>(&self, sql: &'static str, params: &[Value]) -> Result<Vec<T>> {
+    /// Runs every statement of `sql`, `execute` only runs the first
+    pub fn execute_batch(&self, sql: &str) -> Result<()> {
+        self.check_interrupt()?;
+        let c_sql = CString::new(sql)?;
+        let rc = unsafe {
+            sqlite3_exec(
+                self.db,
+                c_sql.as_ptr(),
+                None,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        match rc {
+            0 => Ok(()),
+            _ => Err(self.last_error()),
+        }
+    }
+
+    pub fn query<T: FromRow>(&self, sql: &str, params: &[Value]) -> Result<Vec<T>> {
         unsafe {
-            let stmt = self.prepare(sql, params)?;
+            let statement = self.prepare(sql, params)?;
+            let stmt = statement.raw;
             let mut rows = Vec::new();
             while sqlite3_step(stmt) == SQLITE_ROW {
                 let column_count = sqlite3_column_count(stmt);
@@ -171,17 +372,13 @@ impl Sqlite {
                 rows.push(row);
             }
 
-            Self::finalize_statement(self.db, stmt)?;
+            statement.finalize()?;
 
             Ok(rows)
         }
     }
 
-    pub fn query_first<T: FromRow>(
-        &self,
-        sql: &'static str,
-        params: &[Value],
-    ) -> Result<Option<T>> {
+    pub fn query_first<T: FromRow>(&self, sql: &str, params: &[Value]) -> Result<Option<T>> {
         match self.query(sql, params) {
             Ok(rows) => Ok(match rows.len() {
                 0 => None,
@@ -261,12 +458,13 @@ impl Sqlite {
         params: &[Value],
     ) -> Result<impl Iterator<Item = Result<T>> + 'a> {
         let stmt = self.prepare(sql, params)?;
-        Ok(SqliteIterator::new(self, stmt))
+        Ok(SqliteIterator::new(stmt))
     }
 
     pub fn rows(&self, sql: &str, params: &[Value]) -> Result<Vec<Vec<(String, Value)>>> {
         unsafe {
-            let stmt = self.prepare(sql, params)?;
+            let statement = self.prepare(sql, params)?;
+            let stmt = statement.raw;
             let mut rows = Vec::new();
             while sqlite3_step(stmt) == SQLITE_ROW {
                 let column_count = sqlite3_column_count(stmt);
@@ -284,20 +482,63 @@ impl Sqlite {
                 rows.push(values);
             }
 
-            Self::finalize_statement(self.db, stmt)?;
+            statement.finalize()?;
 
             Ok(rows)
         }
     }
 
-    pub fn savepoint<'a>(&'a self, sqlite: &'a Sqlite, name: &'a str) -> Result<Savepoint<'a>> {
-        Savepoint::new(sqlite, name)
+    pub fn savepoint<'a>(&'a self, name: &'a str) -> Result<Savepoint<'a>> {
+        Savepoint::new(self, name)
+    }
+
+    /// Runs `f` in a transaction, committing when it returns `Ok`
+    /// and rolling back when it returns `Err` or the commit fails
+    pub fn transaction<R>(
+        &self,
+        mode: TransactionMode,
+        f: impl FnOnce(&Sqlite) -> Result<R>,
+    ) -> Result<R> {
+        self.execute_all(mode.begin())?;
+        let result = f(self).and_then(|value| self.execute_all("commit").map(|_| value));
+        if result.is_err() && !self.is_autocommit() {
+            let _ = self.execute_all("rollback");
+        }
+
+        result
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            db: self.interrupt.clone(),
+            interrupted: self.interrupted.clone(),
+        }
+    }
+
+    /// Lets statements run again after `InterruptHandle::interrupt`
+    pub fn clear_interrupt(&self) {
+        self.interrupted.store(false, Ordering::SeqCst);
+    }
+
+    /// sqlite forgets an interrupt once no statement runs, the flag fails
+    /// the statements started between two others until it is cleared
+    fn check_interrupt(&self) -> Result<()> {
+        match self.interrupted.load(Ordering::SeqCst) {
+            true => Err(Error::Sqlite("interrupted".into())),
+            false => Ok(()),
+        }
+    }
+
+    /// Whether no transaction is open, see `sqlite3_get_autocommit`
+    pub fn is_autocommit(&self) -> bool {
+        unsafe { sqlite3_get_autocommit(self.db) != 0 }
     }
 
     pub fn column_names(&self, sql: &str) -> Result<Vec<String>> {
         let mut columns = Vec::new();
         unsafe {
-            let stmt = self.prepare(sql, &[])?;
+            let statement = self.prepare(sql, &[])?;
+            let stmt = statement.raw;
             let count = sqlite3_column_count(stmt);
             for i in 0..count {
                 let name_ptr = sqlite3_column_origin_name(stmt, i);
@@ -315,7 +556,8 @@ impl Sqlite {
     pub fn aliased_column_names(&self, sql: &str) -> Result<Vec<String>> {
         let mut columns = Vec::new();
         unsafe {
-            let stmt = self.prepare(sql, &[])?;
+            let statement = self.prepare(sql, &[])?;
+            let stmt = statement.raw;
             let count = sqlite3_column_count(stmt);
             for i in 0..count {
                 let name_ptr = sqlite3_column_name(stmt, i);
@@ -333,7 +575,8 @@ impl Sqlite {
     pub fn table_names(&self, sql: &str) -> Result<Vec<String>> {
         let mut tables = Vec::new();
         unsafe {
-            let stmt = self.prepare(sql, &[])?;
+            let statement = self.prepare(sql, &[])?;
+            let stmt = statement.raw;
             let count = sqlite3_column_count(stmt);
             for i in 0..count {
                 let name_ptr = sqlite3_column_table_name(stmt, i);
@@ -352,7 +595,8 @@ impl Sqlite {
         let mut params = Vec::new();
 
         unsafe {
-            let stmt = self.prepare(sql, &[])?;
+            let statement = self.prepare(sql, &[])?;
+            let stmt = statement.raw;
             let param_count = sqlite3_bind_parameter_count(stmt);
 
             for i in 1..param_count + 1 {
@@ -362,49 +606,136 @@ impl Sqlite {
                     params.push(name);
                 }
             }
-            self.finalize(stmt)?;
+            statement.finalize()?;
         }
 
         Ok(params)
     }
 
-    fn finalize(&self, stmt: *mut sqlite3_stmt) -> Result<()> {
+    pub(crate) fn last_error(&self) -> Error {
+        let error = unsafe {
+            CStr::from_ptr(sqlite3_errmsg(self.db))
+                .to_string_lossy()
+                .into_owned()
+        };
+        Error::Sqlite(error)
+    }
+}
+
+impl Drop for Sqlite {
+    fn drop(&mut self) {
+        let mut interrupt = self.interrupt.lock().unwrap();
+        interrupt.0 = std::ptr::null_mut();
+        // close_v2 defers the close until leaked statements are finalized
         unsafe {
-            if sqlite3_finalize(stmt) != 0 {
-                let error = CStr::from_ptr(sqlite3_errmsg(self.db))
-                    .to_string_lossy()
-                    .into_owned();
-                return Err(Error::Sqlite(error));
-            }
+            sqlite3_close_v2(self.db);
         }
-        Ok(())
     }
 }
 
-impl Drop for Sqlite {
+/// Interrupts whatever statement its connection is running, from any thread.
+/// Does nothing once the connection is closed
+#[derive(Debug, Clone)]
+pub struct InterruptHandle {
+    db: Arc<Mutex<RawDb>>,
+    interrupted: Arc<AtomicBool>,
+}
+
+/// The connection pointer, null once it is closed
+#[derive(Debug)]
+struct RawDb(*mut sqlite3);
+
+// only ever passed to sqlite3_interrupt, which is safe from any thread
+unsafe impl Send for RawDb {}
+
+impl InterruptHandle {
+    /// See `sqlite3_interrupt`, the statement fails with `interrupted` and
+    /// so does every one started afterwards, until `Sqlite::clear_interrupt`
+    pub fn interrupt(&self) {
+        let db = self.db.lock().unwrap();
+        if !db.0.is_null() {
+            self.interrupted.store(true, Ordering::SeqCst);
+            unsafe { sqlite3_interrupt(db.0) };
+        }
+    }
+}
+
+/// A prepared statement borrowing its connection, finalized when dropped
+#[derive(Debug)]
+pub struct Statement<'a> {
+    raw: *mut sqlite3_stmt,
+    db: &'a Sqlite,
+}
+
+impl Statement<'_> {
+    pub fn as_ptr(&self) -> *mut sqlite3_stmt {
+        self.raw
+    }
+
+    /// Whether the statement leaves the database unchanged, see `sqlite3_stmt_readonly`
+    pub fn readonly(&self) -> bool {
+        unsafe { sqlite3_stmt_readonly(self.raw) != 0 }
+    }
+
+    /// How many columns the rows of the statement have, 0 when it returns none
+    pub fn column_count(&self) -> usize {
+        unsafe { sqlite3_column_count(self.raw) as usize }
+    }
+
+    /// Finalizes the statement, returning the error of its last step if any
+    pub fn finalize(mut self) -> Result<()> {
+        let raw = std::mem::replace(&mut self.raw, std::ptr::null_mut());
+        unsafe { Sqlite::finalize_statement(self.db.db, raw) }
+    }
+}
+
+impl Drop for Statement<'_> {
     fn drop(&mut self) {
         unsafe {
-            sqlite3_close(self.db);
+            sqlite3_finalize(self.raw);
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransactionMode {
+    #[default]
+    Deferred,
+    Immediate,
+    Exclusive,
+}
+
+impl TransactionMode {
+    pub fn begin(self) -> &'static str {
+        match self {
+            TransactionMode::Deferred => "begin deferred",
+            TransactionMode::Immediate => "begin immediate",
+            TransactionMode::Exclusive => "begin exclusive",
+        }
+    }
+}
+
+/// Rolled back when dropped without `release`
 #[derive(Debug)]
 pub struct Savepoint<'a> {
     sqlite: &'a Sqlite,
     name: &'a str,
+    released: bool,
 }
 
 impl<'a> Savepoint<'a> {
     pub fn new(sqlite: &'a Sqlite, name: &'a str) -> Result<Savepoint<'a>> {
-        let sql = format!("savepoint {}", name);
-        let _stmt = sqlite.prepare(&sql, &[])?;
-        Ok(Self { sqlite, name })
+        sqlite.execute_all(&format!("savepoint {}", name))?;
+        Ok(Self {
+            sqlite,
+            name,
+            released: false,
+        })
     }
 
-    pub fn release(&self) -> Result<()> {
-        let sql = format!("release savepoint {}", self.name);
-        let _stmt = self.prepare(&sql, &[])?;
+    pub fn release(mut self) -> Result<()> {
+        self.released = true;
+        self.execute_all(&format!("release savepoint {}", self.name))?;
         Ok(())
     }
 }
@@ -419,17 +750,22 @@ impl<'a> Deref for Savepoint<'a> {
 
 impl<'a> Drop for Savepoint<'a> {
     fn drop(&mut self) {
-        self.release().expect("release savepoint failed")
+        if !self.released {
+            let _ = self.execute_all(&format!("rollback to savepoint {}", self.name));
+            let _ = self.execute_all(&format!("release savepoint {}", self.name));
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Text(String),
     Integer(i64),
     Real(f64),
     Blob(Vec<u8>),
     Null,
+    /// Only usable as the argument of `rarray(?)`
+    Array(Arc<Vec<Value>>),
 }
 
 #[derive(Debug, Clone)]
@@ -616,16 +952,14 @@ impl From<()> for Value {
 
 #[derive(Debug)]
 pub struct SqliteIterator<'a, T: FromRow> {
-    db: &'a Sqlite,
-    stmt: *mut sqlite3_stmt,
+    stmt: Statement<'a>,
     finished: bool,
     _marker: PhantomData<T>,
 }
 
 impl<'a, T: FromRow> SqliteIterator<'a, T> {
-    fn new(db: &'a Sqlite, stmt: *mut sqlite3_stmt) -> Self {
+    fn new(stmt: Statement<'a>) -> Self {
         SqliteIterator {
-            db,
             stmt,
             finished: false,
             _marker: PhantomData,
@@ -642,13 +976,13 @@ impl<'a, T: FromRow> Iterator for SqliteIterator<'a, T> {
         }
 
         unsafe {
-            match sqlite3_step(self.stmt) {
+            match sqlite3_step(self.stmt.raw) {
                 SQLITE_ROW => {
-                    let column_count = sqlite3_column_count(self.stmt);
+                    let column_count = sqlite3_column_count(self.stmt.raw);
                     let mut values: Vec<(String, Value)> = vec![];
 
                     for i in 0..column_count {
-                        let name_ptr = sqlite3_column_name(self.stmt, i);
+                        let name_ptr = sqlite3_column_name(self.stmt.raw, i);
                         let name = if name_ptr.is_null() {
                             format!("column_{}", i)
                         } else {
@@ -658,7 +992,7 @@ impl<'a, T: FromRow> Iterator for SqliteIterator<'a, T> {
                             }
                         };
 
-                        match Sqlite::get_column_value(self.stmt, i) {
+                        match Sqlite::get_column_value(self.stmt.raw, i) {
                             Ok(value) => values.push((name, value)),
                             Err(e) => {
                                 self.finished = true;
@@ -681,7 +1015,7 @@ impl<'a, T: FromRow> Iterator for SqliteIterator<'a, T> {
                 }
                 _ => {
                     self.finished = true;
-                    let error = CStr::from_ptr(sqlite3_errmsg(self.db.db))
+                    let error = CStr::from_ptr(sqlite3_errmsg(self.stmt.db.db))
                         .to_string_lossy()
                         .into_owned();
                     Some(Err(Error::Sqlite(error)))
@@ -691,10 +1025,91 @@ impl<'a, T: FromRow> Iterator for SqliteIterator<'a, T> {
     }
 }
 
-impl<'a, T: FromRow> Drop for SqliteIterator<'a, T> {
-    fn drop(&mut self) {
-        unsafe {
-            let _ = Sqlite::finalize_statement(self.db.db, self.stmt);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn close_hands_back_busy_connection() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        let stmt = db.prepare("select 1", &[])?;
+        let raw = stmt.as_ptr();
+        std::mem::forget(stmt);
+
+        let (db, err) = db.close().unwrap_err();
+        assert!(err.to_string().contains("select 1"), "{err}");
+        assert_eq!(db.unfinalized(), vec!["select 1".to_string()]);
+
+        unsafe { sqlite3_finalize(raw) };
+        assert!(db.close().is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn params_can_drop_before_the_statement_steps() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        let params = vec![Value::Text("text".repeat(64)), Value::Blob(vec![7; 256])];
+        let rows = db.iter::<crate::Row>("select ? as text, ? as blob", &params)?;
+        drop(params);
+        let rows = rows.collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(rows[0].get::<String>("text")?, "text".repeat(64));
+        assert_eq!(rows[0].get::<Vec<u8>>("blob")?, vec![7; 256]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn interrupts_last_until_cleared() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        db.interrupt_handle().interrupt();
+
+        let err = db.execute_all("select 1").unwrap_err();
+        assert_eq!(err.to_string(), "sqlite error: interrupted");
+        assert!(db.execute_batch("select 1").is_err());
+        db.clear_interrupt();
+        db.execute_all("select 1")?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn savepoint_rolls_back_unless_released() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        db.execute_all("create table Row (id integer primary key)")?;
+
+        let sp = db.savepoint("rolled_back")?;
+        sp.execute_all("insert into Row default values")?;
+        drop(sp);
+        assert!(db.rows("select id from Row", &[])?.is_empty());
+
+        let sp = db.savepoint("released")?;
+        sp.execute_all("insert into Row default values")?;
+        sp.release()?;
+        assert_eq!(db.rows("select id from Row", &[])?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn transaction_rolls_back_on_err() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        db.execute_all("create table Row (id integer primary key)")?;
+
+        let result: Result<()> = db.transaction(TransactionMode::Immediate, |tx| {
+            tx.execute_all("insert into Row default values")?;
+            Err(Error::RowNotFound)
+        });
+        assert!(matches!(result, Err(Error::RowNotFound)));
+        assert!(db.is_autocommit());
+
+        let rows = db.transaction(TransactionMode::Deferred, |tx| {
+            tx.execute_all("insert into Row default values")?;
+            tx.rows("select id from Row", &[])
+        })?;
+        assert_eq!(rows, vec![vec![("id".into(), Value::Integer(1))]]);
+
+        Ok(())
     }
 }
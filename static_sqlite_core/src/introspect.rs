@@ -0,0 +1,400 @@
+use crate::{Error, FromRow, Result, Sqlite, Value};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    pub tables: Vec<Table>,
+    pub views: Vec<View>,
+    pub triggers: Vec<Trigger>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table {
+    pub name: String,
+    pub sql: Option<String>,
+    pub columns: Vec<Column>,
+    pub indexes: Vec<Index>,
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+impl Table {
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|col| col.name == name)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Column {
+    pub cid: i64,
+    pub name: String,
+    pub column_type: String,
+    pub not_null: bool,
+    pub default_value: Option<String>,
+    pub pk: i64,
+    pub hidden: Hidden,
+}
+
+/// The `hidden` column of `pragma_table_xinfo`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Hidden {
+    #[default]
+    Visible,
+    VirtualTable,
+    GeneratedVirtual,
+    GeneratedStored,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index {
+    pub name: String,
+    pub unique: bool,
+    pub origin: IndexOrigin,
+    pub partial: bool,
+    pub columns: Vec<IndexColumn>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexOrigin {
+    CreateIndex,
+    Unique,
+    PrimaryKey,
+}
+
+/// One row of `pragma_index_xinfo`, `key` is false for the
+/// auxiliary columns (the rowid or primary key) sqlite appends to every index
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexColumn {
+    pub seqno: i64,
+    pub cid: i64,
+    pub name: Option<String>,
+    pub desc: bool,
+    pub collation: Option<String>,
+    pub key: bool,
+}
+
+/// A foreign key constraint, `to` is `None` for columns that
+/// implicitly reference the primary key of the parent table
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ForeignKey {
+    pub id: i64,
+    pub table: String,
+    pub from: Vec<String>,
+    pub to: Vec<Option<String>>,
+    pub on_update: String,
+    pub on_delete: String,
+    pub match_: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct View {
+    pub name: String,
+    pub sql: Option<String>,
+    pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trigger {
+    pub name: String,
+    pub table: String,
+    pub sql: Option<String>,
+}
+
+impl Sqlite {
+    pub fn schema(&self) -> Result<Schema> {
+        Ok(Schema {
+            tables: self.tables()?,
+            views: self.views()?,
+            triggers: self.triggers()?,
+        })
+    }
+
+    pub fn tables(&self) -> Result<Vec<Table>> {
+        let rows: Vec<MasterRow> = self.query(
+            r#"
+            select name, tbl_name, sql
+            from sqlite_master
+            where type = 'table'
+                and name not like 'sqlite_%'
+            order by name"#,
+            &[],
+        )?;
+
+        rows.into_iter().map(|row| self.table_from(row)).collect()
+    }
+
+    pub fn table(&self, name: &str) -> Result<Option<Table>> {
+        let row: Option<MasterRow> = self.query_first(
+            r#"
+            select name, tbl_name, sql
+            from sqlite_master
+            where type = 'table'
+                and name = ?"#,
+            &[name.into()],
+        )?;
+
+        row.map(|row| self.table_from(row)).transpose()
+    }
+
+    pub fn views(&self) -> Result<Vec<View>> {
+        let rows: Vec<MasterRow> = self.query(
+            r#"
+            select name, tbl_name, sql
+            from sqlite_master
+            where type = 'view'
+            order by name"#,
+            &[],
+        )?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(View {
+                    columns: self.columns(&row.name)?,
+                    name: row.name,
+                    sql: row.sql,
+                })
+            })
+            .collect()
+    }
+
+    pub fn triggers(&self) -> Result<Vec<Trigger>> {
+        let rows: Vec<MasterRow> = self.query(
+            r#"
+            select name, tbl_name, sql
+            from sqlite_master
+            where type = 'trigger'
+            order by name"#,
+            &[],
+        )?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Trigger {
+                name: row.name,
+                table: row.tbl_name,
+                sql: row.sql,
+            })
+            .collect())
+    }
+
+    pub fn columns(&self, table: &str) -> Result<Vec<Column>> {
+        self.query(
+            r#"
+            select cid, name, type, "notnull", dflt_value, pk, hidden
+            from pragma_table_xinfo(?)
+            order by cid"#,
+            &[table.into()],
+        )
+    }
+
+    pub fn indexes(&self, table: &str) -> Result<Vec<Index>> {
+        let rows: Vec<IndexRow> = self.query(
+            r#"
+            select name, "unique", origin, partial
+            from pragma_index_list(?)
+            order by seq"#,
+            &[table.into()],
+        )?;
+
+        rows.into_iter()
+            .map(|row| {
+                let columns = self.query(
+                    r#"
+                    select seqno, cid, name, "desc", coll, key
+                    from pragma_index_xinfo(?)
+                    order by seqno"#,
+                    &[row.name.as_str().into()],
+                )?;
+                Ok(Index {
+                    name: row.name,
+                    unique: row.unique,
+                    origin: row.origin,
+                    partial: row.partial,
+                    columns,
+                })
+            })
+            .collect()
+    }
+
+    pub fn foreign_keys(&self, table: &str) -> Result<Vec<ForeignKey>> {
+        let rows: Vec<ForeignKeyRow> = self.query(
+            r#"
+            select id, "table", "from", "to", on_update, on_delete, "match"
+            from pragma_foreign_key_list(?)
+            order by id, seq"#,
+            &[table.into()],
+        )?;
+
+        Ok(rows
+            .into_iter()
+            .fold(Vec::<ForeignKey>::new(), |mut acc, row| {
+                match acc.last_mut() {
+                    Some(fk) if fk.id == row.id => {
+                        fk.from.push(row.from);
+                        fk.to.push(row.to);
+                    }
+                    _ => acc.push(ForeignKey {
+                        id: row.id,
+                        table: row.table,
+                        from: vec![row.from],
+                        to: vec![row.to],
+                        on_update: row.on_update,
+                        on_delete: row.on_delete,
+                        match_: row.match_,
+                    }),
+                }
+                acc
+            }))
+    }
+
+    fn table_from(&self, row: MasterRow) -> Result<Table> {
+        Ok(Table {
+            columns: self.columns(&row.name)?,
+            indexes: self.indexes(&row.name)?,
+            foreign_keys: self.foreign_keys(&row.name)?,
+            name: row.name,
+            sql: row.sql,
+        })
+    }
+}
+
+#[derive(Default)]
+struct MasterRow {
+    name: String,
+    tbl_name: String,
+    sql: Option<String>,
+}
+
+impl FromRow for MasterRow {
+    fn from_row(columns: Vec<(String, Value)>) -> Result<Self> {
+        let mut row = MasterRow::default();
+        for (name, value) in columns {
+            match name.as_str() {
+                "name" => row.name = value.try_into()?,
+                "tbl_name" => row.tbl_name = value.try_into()?,
+                "sql" => row.sql = value.try_into()?,
+                _ => {}
+            }
+        }
+
+        Ok(row)
+    }
+}
+
+impl FromRow for Column {
+    fn from_row(columns: Vec<(String, Value)>) -> Result<Self> {
+        let mut row = Column::default();
+        for (name, value) in columns {
+            match name.as_str() {
+                "cid" => row.cid = value.try_into()?,
+                "name" => row.name = value.try_into()?,
+                "type" => row.column_type = value.try_into()?,
+                "notnull" => row.not_null = flag(value)?,
+                "dflt_value" => row.default_value = value.try_into()?,
+                "pk" => row.pk = value.try_into()?,
+                "hidden" => {
+                    row.hidden = match i64::try_from(value)? {
+                        0 => Hidden::Visible,
+                        1 => Hidden::VirtualTable,
+                        2 => Hidden::GeneratedVirtual,
+                        3 => Hidden::GeneratedStored,
+                        n => return Err(Error::Sqlite(format!("unknown hidden column kind {n}"))),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(row)
+    }
+}
+
+struct IndexRow {
+    name: String,
+    unique: bool,
+    origin: IndexOrigin,
+    partial: bool,
+}
+
+impl FromRow for IndexRow {
+    fn from_row(columns: Vec<(String, Value)>) -> Result<Self> {
+        let mut row = IndexRow {
+            name: String::new(),
+            unique: false,
+            origin: IndexOrigin::CreateIndex,
+            partial: false,
+        };
+        for (name, value) in columns {
+            match name.as_str() {
+                "name" => row.name = value.try_into()?,
+                "unique" => row.unique = flag(value)?,
+                "origin" => {
+                    row.origin = match String::try_from(value)?.as_str() {
+                        "c" => IndexOrigin::CreateIndex,
+                        "u" => IndexOrigin::Unique,
+                        "pk" => IndexOrigin::PrimaryKey,
+                        origin => {
+                            return Err(Error::Sqlite(format!("unknown index origin {origin}")))
+                        }
+                    }
+                }
+                "partial" => row.partial = flag(value)?,
+                _ => {}
+            }
+        }
+
+        Ok(row)
+    }
+}
+
+impl FromRow for IndexColumn {
+    fn from_row(columns: Vec<(String, Value)>) -> Result<Self> {
+        let mut row = IndexColumn::default();
+        for (name, value) in columns {
+            match name.as_str() {
+                "seqno" => row.seqno = value.try_into()?,
+                "cid" => row.cid = value.try_into()?,
+                "name" => row.name = value.try_into()?,
+                "desc" => row.desc = flag(value)?,
+                "coll" => row.collation = value.try_into()?,
+                "key" => row.key = flag(value)?,
+                _ => {}
+            }
+        }
+
+        Ok(row)
+    }
+}
+
+#[derive(Default)]
+struct ForeignKeyRow {
+    id: i64,
+    table: String,
+    from: String,
+    to: Option<String>,
+    on_update: String,
+    on_delete: String,
+    match_: String,
+}
+
+impl FromRow for ForeignKeyRow {
+    fn from_row(columns: Vec<(String, Value)>) -> Result<Self> {
+        let mut row = ForeignKeyRow::default();
+        for (name, value) in columns {
+            match name.as_str() {
+                "id" => row.id = value.try_into()?,
+                "table" => row.table = value.try_into()?,
+                "from" => row.from = value.try_into()?,
+                "to" => row.to = value.try_into()?,
+                "on_update" => row.on_update = value.try_into()?,
+                "on_delete" => row.on_delete = value.try_into()?,
+                "match" => row.match_ = value.try_into()?,
+                _ => {}
+            }
+        }
+
+        Ok(row)
+    }
+}
+
+fn flag(value: Value) -> Result<bool> {
+    Ok(i64::try_from(value)? != 0)
+}
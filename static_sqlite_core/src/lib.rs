@@ -1,5 +1,36 @@
+mod array;
 mod ffi;
-pub use ffi::{DataType, Error, FromRow, Result, Savepoint, Sqlite, Value};
+mod introspect;
+mod maintenance;
+mod migrations;
+mod row;
+mod status;
+mod vfs;
+mod vtab;
+pub use ffi::{
+    DataType, Error, FromRow, InterruptHandle, OpenOptions, Result, Savepoint, Sqlite,
+    SqliteIterator, Statement, TransactionMode, Value,
+};
+pub use introspect::{
+    Column, ForeignKey, Hidden, Index, IndexColumn, IndexOrigin, Schema, Table, Trigger, View,
+};
+pub use maintenance::{Checkpoint, CheckpointMode, ForeignKeyViolation, IntegrityProblem};
+pub use migrations::{
+    split_statements, AppliedMigration, Migration, MigrationReport, MigrationStatus, MigrationStep,
+    Migrations,
+};
+pub use row::{Row, RowIndex};
+pub use status::{
+    hard_heap_limit, memory_status, soft_heap_limit, Counter, DbStatus, Limit, Limits,
+    MemoryStatus, Status,
+};
+pub use vfs::{
+    register_vfs, DefaultFile, DefaultVfs, FaultFile, FaultKind, FaultOp, FaultVfs, Faults,
+    LockLevel, MemoryFile, MemoryVfs, OpenFlags, Vfs, VfsFile, VfsPath,
+};
+pub use vtab::{
+    ConstraintOp, IndexConstraint, IndexInfo, OrderBy, Update, VTab, VTabRow, VecRows, VecTab,
+};
 
 pub fn open(path: &str) -> Result<Sqlite> {
     Sqlite::open(path)
@@ -15,7 +46,7 @@ pub fn execute_all(conn: &Sqlite, sql: &str) -> Result<i32> {
 
 pub fn query<T: FromRow + Send + 'static>(
     conn: &Sqlite,
-    sql: &'static str,
+    sql: &str,
     params: &[Value],
 ) -> Result<Vec<T>> {
     conn.query(sql, params)
@@ -23,7 +54,7 @@ pub fn query<T: FromRow + Send + 'static>(
 
 pub fn query_first<T: FromRow + Send + 'static>(
     conn: &Sqlite,
-    sql: &'static str,
+    sql: &str,
     params: &[Value],
 ) -> Result<Option<T>> {
     conn.query_first(sql, params)
@@ -33,39 +64,12 @@ pub fn rows(conn: &Sqlite, sql: &str, params: &[Value]) -> Result<Vec<Vec<(Strin
     conn.rows(sql, params)
 }
 
-pub fn savepoint<'a>(conn: &'a Sqlite, name: &'a str) -> Result<Savepoint<'a>> {
-    conn.savepoint(conn, name)
-}
-
-pub(crate) fn user_version(db: &Sqlite) -> Result<i64> {
-    let rws = rows(&db, "PRAGMA user_version", &[])?;
-    match rws.into_iter().nth(0) {
-        Some(cols) => match cols.into_iter().nth(0) {
-            Some(pair) => pair.1.try_into(),
-            None => Ok(0),
-        },
-        None => Ok(0),
-    }
-}
-
-pub(crate) fn set_user_version(db: &Sqlite, version: usize) -> Result<()> {
-    let _ = execute(&db, &format!("PRAGMA user_version = {version}"), vec![])?;
-    Ok(())
+pub fn schema(conn: &Sqlite) -> Result<Schema> {
+    conn.schema()
 }
 
-pub fn migrate<F>(db: &Sqlite, migrations: &[F]) -> Result<()>
-where
-    F: Fn(&Sqlite) -> Result<()>,
-{
-    let sp = savepoint(db, "migrate")?;
-    let version = user_version(&sp)?;
-    let pending_migrations = &migrations[(version as usize)..];
-    for migration in pending_migrations {
-        migration(&sp)?;
-    }
-    set_user_version(&sp, migrations.len())?;
-
-    Ok(())
+pub fn savepoint<'a>(conn: &'a Sqlite, name: &'a str) -> Result<Savepoint<'a>> {
+    conn.savepoint(name)
 }
 
 impl FromRow for () {
@@ -0,0 +1,271 @@
+use static_sqlite_ffi::{sqlite3_wal_checkpoint_v2, SQLITE_BUSY, SQLITE_OK};
+
+use crate::{FromRow, Result, Sqlite, Value};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CheckpointMode {
+    #[default]
+    Passive,
+    Full,
+    Restart,
+    Truncate,
+}
+
+impl CheckpointMode {
+    fn as_raw(self) -> i32 {
+        (match self {
+            CheckpointMode::Passive => static_sqlite_ffi::SQLITE_CHECKPOINT_PASSIVE,
+            CheckpointMode::Full => static_sqlite_ffi::SQLITE_CHECKPOINT_FULL,
+            CheckpointMode::Restart => static_sqlite_ffi::SQLITE_CHECKPOINT_RESTART,
+            CheckpointMode::Truncate => static_sqlite_ffi::SQLITE_CHECKPOINT_TRUNCATE,
+        }) as i32
+    }
+}
+
+/// The outcome of a wal checkpoint, `busy` is set when a full, restart
+/// or truncate checkpoint could not finish because of other connections.
+/// Both frame counts are -1 when the database is not in wal mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub busy: bool,
+    pub log_frames: i32,
+    pub checkpointed_frames: i32,
+}
+
+/// One line of `pragma integrity_check` or `pragma quick_check`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IntegrityProblem {
+    pub message: String,
+    pub table: Option<String>,
+    pub index: Option<String>,
+    pub page: Option<i64>,
+    pub rowid: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    pub rowid: Option<i64>,
+    pub parent: String,
+    pub fkid: i64,
+}
+
+impl Sqlite {
+    pub fn wal_checkpoint(&self, mode: CheckpointMode) -> Result<Checkpoint> {
+        let mut log_frames = 0;
+        let mut checkpointed_frames = 0;
+        let rc = unsafe {
+            sqlite3_wal_checkpoint_v2(
+                self.db,
+                std::ptr::null(),
+                mode.as_raw(),
+                &mut log_frames,
+                &mut checkpointed_frames,
+            )
+        };
+
+        match rc {
+            rc if rc == SQLITE_OK as i32 || rc == SQLITE_BUSY as i32 => Ok(Checkpoint {
+                busy: rc == SQLITE_BUSY as i32,
+                log_frames,
+                checkpointed_frames,
+            }),
+            _ => Err(self.last_error()),
+        }
+    }
+
+    pub fn integrity_check(&self) -> Result<Vec<IntegrityProblem>> {
+        self.check("pragma integrity_check")
+    }
+
+    pub fn quick_check(&self) -> Result<Vec<IntegrityProblem>> {
+        self.check("pragma quick_check")
+    }
+
+    pub fn foreign_key_check(&self) -> Result<Vec<ForeignKeyViolation>> {
+        self.query(
+            r#"select "table", rowid, parent, fkid from pragma_foreign_key_check"#,
+            &[],
+        )
+    }
+
+    pub fn optimize(&self) -> Result<()> {
+        self.execute_all("pragma optimize")?;
+        Ok(())
+    }
+
+    pub fn analyze(&self) -> Result<()> {
+        self.execute_all("analyze")?;
+        Ok(())
+    }
+
+    /// Frees up to `pages` pages from the freelist, 0 frees all of them.
+    /// Only does something when `auto_vacuum = incremental`. The pragma
+    /// frees one page per step, so it runs to completion
+    pub fn incremental_vacuum(&self, pages: u32) -> Result<()> {
+        self.execute_batch(&format!("pragma incremental_vacuum({pages})"))
+    }
+
+    pub fn vacuum_into(&self, path: &str) -> Result<()> {
+        self.execute("vacuum into ?", vec![path.into()])?;
+        Ok(())
+    }
+
+    fn check(&self, sql: &str) -> Result<Vec<IntegrityProblem>> {
+        let rows = self.rows(sql, &[])?;
+        let mut problems = vec![];
+        for row in rows {
+            for (_, value) in row {
+                let message: String = value.try_into()?;
+                if message != "ok" {
+                    problems.push(IntegrityProblem::parse(message));
+                }
+            }
+        }
+
+        Ok(problems)
+    }
+}
+
+impl IntegrityProblem {
+    fn parse(message: String) -> Self {
+        let mut problem = IntegrityProblem::default();
+        let words: Vec<&str> = message.split_whitespace().collect();
+        match words.as_slice() {
+            ["row", rowid, "missing", "from", "index", index, ..] => {
+                problem.rowid = rowid.parse().ok();
+                problem.index = Some(index.to_string());
+            }
+            ["wrong", "#", "of", "entries", "in", "index", index, ..]
+            | ["non-unique", "entry", "in", "index", index, ..] => {
+                problem.index = Some(index.to_string());
+            }
+            ["NULL", "value", "in", column, ..] => {
+                problem.table = column.split('.').next().map(|t| t.to_string());
+            }
+            ["CHECK", "constraint", "failed", "in", table, ..] => {
+                problem.table = Some(table.to_string());
+            }
+            ["On", "tree", "page", page, ..] | ["Page", page, ..] => {
+                problem.page = page.trim_end_matches(':').parse().ok();
+            }
+            _ => {}
+        }
+        problem.message = message;
+
+        problem
+    }
+}
+
+impl FromRow for ForeignKeyViolation {
+    fn from_row(columns: Vec<(String, Value)>) -> Result<Self> {
+        let mut row = ForeignKeyViolation::default();
+        for (name, value) in columns {
+            match name.as_str() {
+                "table" => row.table = value.try_into()?,
+                "rowid" => row.rowid = value.try_into()?,
+                "parent" => row.parent = value.try_into()?,
+                "fkid" => row.fkid = value.try_into()?,
+                _ => {}
+            }
+        }
+
+        Ok(row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integrity_problem_parse_works() {
+        let problem = IntegrityProblem::parse("row 5 missing from index User_email".into());
+        assert_eq!(problem.rowid, Some(5));
+        assert_eq!(problem.index, Some("User_email".into()));
+
+        let problem = IntegrityProblem::parse("NULL value in User.email".into());
+        assert_eq!(problem.table, Some("User".into()));
+
+        let problem =
+            IntegrityProblem::parse("On tree page 2 cell 0: invalid page number 7".into());
+        assert_eq!(problem.page, Some(2));
+        assert_eq!(
+            problem.message,
+            "On tree page 2 cell 0: invalid page number 7"
+        );
+    }
+
+    #[test]
+    fn maintenance_works() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        db.execute_all("pragma foreign_keys = off")?;
+        db.execute_all("create table User (id integer primary key)")?;
+        db.execute_all(
+            "create table Post (id integer primary key, user_id integer references User(id))",
+        )?;
+        db.execute("insert into Post (user_id) values (?)", vec![42.into()])?;
+
+        assert_eq!(db.integrity_check()?, vec![]);
+        assert_eq!(db.quick_check()?, vec![]);
+        assert_eq!(
+            db.foreign_key_check()?,
+            vec![ForeignKeyViolation {
+                table: "Post".into(),
+                rowid: Some(1),
+                parent: "User".into(),
+                fkid: 0,
+            }]
+        );
+        db.optimize()?;
+        db.analyze()?;
+        db.incremental_vacuum(0)?;
+
+        let path = std::env::temp_dir().join(format!(
+            "static_sqlite_{}_vacuum_into.sqlite3",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        db.vacuum_into(path.to_str().unwrap())?;
+        let copy = Sqlite::open(path.to_str().unwrap())?;
+        assert_eq!(copy.tables()?.len(), 2);
+        std::fs::remove_file(&path)?;
+
+        let checkpoint = db.wal_checkpoint(CheckpointMode::Passive)?;
+        assert_eq!(checkpoint.log_frames, -1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn incremental_vacuum_frees_every_page() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "static_sqlite_{}_incremental_vacuum.sqlite3",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let db = Sqlite::open(path.to_str().unwrap())?;
+        db.execute_all("pragma auto_vacuum = incremental")?;
+        db.execute_all("create table Blob (data blob)")?;
+        for _ in 0..20 {
+            db.execute(
+                "insert into Blob values (?)",
+                vec![Value::Blob(vec![0; 8192])],
+            )?;
+        }
+        db.execute_all("delete from Blob")?;
+        let freelist = || -> Result<i64> {
+            db.query_first::<crate::Row>("pragma freelist_count", &[])?
+                .unwrap()
+                .get(0)
+        };
+        assert!(freelist()? > 1);
+
+        db.incremental_vacuum(0)?;
+        assert_eq!(freelist()?, 0);
+        drop(db);
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,850 @@
+use std::{borrow::Cow, ffi::CString, sync::Arc};
+
+use crate::{Error, FromRow, Result, Sqlite, Value};
+
+const CREATE_TABLE: &str = "create table if not exists _static_sqlite_migrations (
+    version integer primary key not null,
+    name text not null,
+    checksum text not null,
+    applied_at integer not null
+)";
+
+/// One step of a schema. Applied migrations are recorded by version
+/// with a checksum of their sql, so editing one later is an error
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    pub version: i64,
+    pub name: Cow<'static, str>,
+    pub sql: Cow<'static, str>,
+    /// The sql as written when `sql` is what `Migrations::with_sql` expanded
+    /// it to, the checksum is over this one
+    pub source: Option<Cow<'static, str>>,
+    /// Undoes `sql`, run by `Migrations::run_down`
+    pub down: Option<Cow<'static, str>>,
+    /// Rust code run after `sql`, in the same savepoint
+    pub step: Option<MigrationStep>,
+}
+
+type StepFn = dyn Fn(&Sqlite) -> Result<()> + Send + Sync;
+
+/// The Rust code of a migration, for what is too much for sql. A
+/// `-- migrate:rust <name>` line declares one, `Migrations::with_step` gives it
+/// its code. Only its name is checksummed, changing the code is not noticed
+#[derive(Clone)]
+pub struct MigrationStep(Option<Arc<StepFn>>);
+
+impl MigrationStep {
+    pub fn new(f: impl Fn(&Sqlite) -> Result<()> + Send + Sync + 'static) -> Self {
+        Self(Some(Arc::new(f)))
+    }
+
+    /// Declared, with no code yet
+    fn declared() -> Self {
+        Self(None)
+    }
+}
+
+impl std::fmt::Debug for MigrationStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("MigrationStep(..)"),
+            None => f.write_str("MigrationStep(declared)"),
+        }
+    }
+}
+
+impl PartialEq for MigrationStep {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+}
+
+impl Migration {
+    pub fn new(
+        version: i64,
+        name: impl Into<Cow<'static, str>>,
+        sql: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            version,
+            name: name.into(),
+            sql: sql.into(),
+            source: None,
+            down: None,
+            step: None,
+        }
+    }
+
+    /// A migration that only runs `f`
+    pub fn rust(
+        version: i64,
+        name: impl Into<Cow<'static, str>>,
+        f: impl Fn(&Sqlite) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(version, name, "").with_step(MigrationStep::new(f))
+    }
+
+    pub fn with_step(mut self, step: MigrationStep) -> Self {
+        self.step = Some(step);
+        self
+    }
+
+    pub fn with_down(mut self, down: impl Into<Cow<'static, str>>) -> Self {
+        self.down = Some(down.into());
+        self
+    }
+
+    /// FNV-1a of the sql with its whitespace collapsed, reformatting is not an edit.
+    /// The name of a `step` is part of it
+    pub fn checksum(&self) -> String {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let sql = self.source.as_deref().unwrap_or(&self.sql);
+        let mut words = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+        words.truncate(words.trim_end_matches(';').trim_end().len());
+        if self.step.is_some() {
+            words = format!("{words}\0rust:{}", self.name);
+        }
+        for byte in words.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+
+        format!("{hash:016x}")
+    }
+}
+
+/// A migration as recorded in the database
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub checksum: String,
+    /// Unix seconds
+    pub applied_at: i64,
+}
+
+impl FromRow for AppliedMigration {
+    fn from_row(columns: Vec<(String, Value)>) -> Result<Self> {
+        let mut columns = columns.into_iter().map(|(_, value)| value);
+        let mut next = || columns.next().ok_or(Error::RowNotFound);
+
+        Ok(AppliedMigration {
+            version: next()?.try_into()?,
+            name: next()?.try_into()?,
+            checksum: next()?.try_into()?,
+            applied_at: next()?.try_into()?,
+        })
+    }
+}
+
+/// What `Migrations::run` did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MigrationReport {
+    /// Applied by this run, in order
+    pub applied: Vec<AppliedMigration>,
+    /// Reverted by `run_down`, newest first
+    pub reverted: Vec<AppliedMigration>,
+    /// Found in the `__migrations__` table older versions of `sql!` kept,
+    /// recorded without running them again
+    pub adopted: Vec<AppliedMigration>,
+    /// The latest applied version after the run, `user_version` is set to it too
+    pub version: i64,
+}
+
+/// Where a database is, see `Migrations::status`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MigrationStatus {
+    /// Recorded in the database, by version
+    pub applied: Vec<AppliedMigration>,
+    /// Listed in the old `__migrations__` table, `run` records them
+    /// without running them
+    pub legacy: Vec<Migration>,
+    /// Not applied yet, in the order `run` applies them
+    pub pending: Vec<Migration>,
+}
+
+/// An ordered set of migrations, run with `run`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Migrations {
+    migrations: Vec<Migration>,
+}
+
+impl Migrations {
+    pub fn new(mut migrations: Vec<Migration>) -> Self {
+        migrations.sort_by_key(|migration| migration.version);
+        Self { migrations }
+    }
+
+    /// The migrations of `sql`, versioned from 1. This is what the `migrate`
+    /// functions of `sql!` run.
+    ///
+    /// A `-- migrate:up` line starts a migration that runs until the next marker,
+    /// the words after it name the migration. A `-- migrate:down` line starts the
+    /// sql that undoes it, until the next `-- migrate:up`. Statements before the
+    /// first `-- migrate:up` are a migration each, named after the statement, a
+    /// `-- migrate:down` among them undoes the statement before it with the one
+    /// statement after it.
+    /// `-- migrate:rust <name>` starts a migration like `-- migrate:up` that also
+    /// runs the Rust code `with_step` gives it.
+    ///
+    /// Fails when a marked migration has more than one `-- migrate:down`
+    pub fn from_sql(sql: &str) -> Result<Self> {
+        let mut migrations: Vec<Migration> = vec![];
+        let mut up = String::new();
+        let mut down: Option<String> = None;
+        let mut name: Option<String> = None;
+        let mut marked = false;
+        let mut rust = false;
+        for line in sql.lines().chain(["-- migrate:up"]) {
+            let trimmed = line.trim();
+            let marker = match trimmed.strip_prefix("-- migrate:up") {
+                Some(rest) => Some((rest, false)),
+                None => trimmed
+                    .strip_prefix("-- migrate:rust")
+                    .map(|rest| (rest, true)),
+            };
+            if let Some((rest, next_rust)) = marker {
+                let up = std::mem::take(&mut up);
+                let down = down.take();
+                match marked {
+                    true if rust || !up.trim().is_empty() => {
+                        let version = migrations.len() as i64 + 1;
+                        let name = name.take().unwrap_or_else(|| statement_name(&up));
+                        let mut migration = Migration::new(version, name, up.trim().to_string());
+                        migration.down = down.map(|down| down.trim().to_string().into());
+                        if rust {
+                            migration.step = Some(MigrationStep::declared());
+                        }
+                        migrations.push(migration);
+                    }
+                    true => {}
+                    false => {
+                        attach_down(&mut migrations, down);
+                        push_statements(&mut migrations, &up);
+                    }
+                }
+                name = Some(rest.trim().to_string()).filter(|name| !name.is_empty());
+                marked = true;
+                rust = next_rust;
+                continue;
+            }
+            if trimmed == "-- migrate:down" {
+                if marked && down.is_some() {
+                    return Err(Error::MigrationDownRepeated {
+                        version: migrations.len() as i64 + 1,
+                        name: name.clone().unwrap_or_else(|| statement_name(&up)),
+                    });
+                }
+                if !marked {
+                    attach_down(&mut migrations, down.take());
+                    push_statements(&mut migrations, &std::mem::take(&mut up));
+                }
+                down = Some(String::new());
+                continue;
+            }
+            let section = down.as_mut().unwrap_or(&mut up);
+            section.push_str(line);
+            section.push('\n');
+            if !marked && down.as_deref().is_some_and(is_complete) {
+                attach_down(&mut migrations, down.take());
+            }
+        }
+
+        Ok(Self { migrations })
+    }
+
+    /// Gives the migration named `name` the code `f`, `run` fails when a
+    /// migration it applies has a declared step without code
+    pub fn with_step(
+        mut self,
+        name: &str,
+        f: impl Fn(&Sqlite) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        if let Some(migration) = self.migrations.iter_mut().find(|m| m.name == name) {
+            migration.step = Some(MigrationStep::new(f));
+        }
+        self
+    }
+
+    /// Replaces the sql of the migration `version`. `sql!` runs the statements
+    /// sqlite can not, like `alter column`, this way once it expanded them.
+    /// The checksum stays the one of the sql as written
+    pub fn with_sql(
+        mut self,
+        version: i64,
+        sql: impl Into<Cow<'static, str>>,
+        down: Option<Cow<'static, str>>,
+    ) -> Self {
+        if let Some(migration) = self.migrations.iter_mut().find(|m| m.version == version) {
+            let source = std::mem::replace(&mut migration.sql, sql.into());
+            migration.source.get_or_insert(source);
+            migration.down = down;
+        }
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Migration> {
+        self.migrations.iter()
+    }
+
+    /// The migration named `target`, or whose version it is
+    pub fn find(&self, target: &str) -> Result<&Migration> {
+        self.migrations
+            .iter()
+            .find(|migration| migration.name == target || migration.version.to_string() == target)
+            .ok_or_else(|| Error::MigrationNotFound(target.to_string()))
+    }
+
+    /// The migrations recorded in `db`, by version
+    pub fn applied(db: &Sqlite) -> Result<Vec<AppliedMigration>> {
+        if !table_exists(db, "_static_sqlite_migrations")? {
+            return Ok(vec![]);
+        }
+        db.query(
+            "select version, name, checksum, applied_at from _static_sqlite_migrations order by version",
+            &[],
+        )
+    }
+
+    /// What `run` would do, without writing anything. Fails the way `run`
+    /// would when an applied migration was changed or removed, or a pending
+    /// one comes before an applied one
+    pub fn status(&self, db: &Sqlite) -> Result<MigrationStatus> {
+        if let Some(pair) = self
+            .migrations
+            .windows(2)
+            .find(|pair| pair[0].version == pair[1].version)
+        {
+            return Err(Error::Sqlite(format!(
+                "duplicate migration version {}",
+                pair[0].version
+            )));
+        }
+
+        let applied = Self::applied(db)?;
+        let legacy = match applied.is_empty() {
+            true => self.legacy(db)?,
+            false => vec![],
+        };
+        self.check(&applied)?;
+
+        let latest = applied
+            .last()
+            .map(|applied| applied.version)
+            .max(legacy.last().map(|migration| migration.version))
+            .unwrap_or(0);
+        let pending = self
+            .migrations
+            .iter()
+            .filter(|migration| {
+                !applied.iter().any(|a| a.version == migration.version)
+                    && !legacy.iter().any(|l| l.version == migration.version)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if let Some(migration) = pending.iter().find(|migration| migration.version < latest) {
+            return Err(Error::MigrationOutOfOrder {
+                version: migration.version,
+                name: migration.name.to_string(),
+                applied: latest,
+            });
+        }
+
+        Ok(MigrationStatus {
+            applied,
+            legacy,
+            pending,
+        })
+    }
+
+    /// The migrations `run` would apply, with their sql
+    pub fn plan(&self, db: &Sqlite) -> Result<Vec<Migration>> {
+        Ok(self.status(db)?.pending)
+    }
+
+    /// Checks the applied migrations against these, then applies the pending
+    /// ones in order, each in its own savepoint together with its record
+    pub fn run(&self, db: &Sqlite) -> Result<MigrationReport> {
+        self.run_until(db, None)
+    }
+
+    /// Same as `run`, stopping after the migration `find` finds for `target`
+    pub fn run_to(&self, db: &Sqlite, target: &str) -> Result<MigrationReport> {
+        let version = self.find(target)?.version;
+        self.run_until(db, Some(version))
+    }
+
+    fn run_until(&self, db: &Sqlite, target: Option<i64>) -> Result<MigrationReport> {
+        db.execute_batch(CREATE_TABLE)?;
+        let status = self.status(db)?;
+
+        let pending = status
+            .pending
+            .iter()
+            .filter(|migration| target.is_none_or(|target| migration.version <= target))
+            .collect::<Vec<_>>();
+        if let Some(migration) = pending
+            .iter()
+            .find(|migration| migration.step.as_ref().is_some_and(|step| step.0.is_none()))
+        {
+            return Err(Error::MigrationStepMissing(migration.name.to_string()));
+        }
+
+        // the legacy rows are recorded in the savepoint of the first migration,
+        // a failed run leaves them to be adopted by the next one
+        let mut report = MigrationReport::default();
+        let mut legacy = status.legacy.as_slice();
+        if pending.is_empty() && !legacy.is_empty() {
+            report.adopted = adopt(db, legacy)?;
+        }
+        for migration in pending {
+            let (adopted, applied) = apply(db, migration, std::mem::take(&mut legacy))?;
+            report.adopted.extend(adopted);
+            report.applied.push(applied);
+        }
+        report.version = report
+            .applied
+            .last()
+            .or(report.adopted.last())
+            .or(status.applied.last())
+            .map_or(0, |applied| applied.version);
+
+        Ok(report)
+    }
+
+    /// Reverts the applied migrations after the one `find` finds for `target`,
+    /// or all of them for `"0"`, newest first. Each one runs its down sql in its
+    /// own savepoint together with the removal of its record. Nothing is
+    /// reverted if one of them has no down sql
+    pub fn run_down(&self, db: &Sqlite, target: &str) -> Result<MigrationReport> {
+        let target = match target {
+            "0" => 0,
+            _ => self.find(target)?.version,
+        };
+        let status = self.status(db)?;
+        let (kept, reverted) = status
+            .applied
+            .iter()
+            .partition::<Vec<_>, _>(|applied| applied.version <= target);
+        let mut reverts = vec![];
+        for applied in reverted.into_iter().rev() {
+            let migration = self
+                .migrations
+                .iter()
+                .find(|migration| migration.version == applied.version)
+                .ok_or_else(|| Error::MigrationMissing {
+                    version: applied.version,
+                    name: applied.name.clone(),
+                })?;
+            match &migration.down {
+                Some(down) => reverts.push((applied, down)),
+                None => {
+                    return Err(Error::MigrationIrreversible {
+                        version: migration.version,
+                        name: migration.name.to_string(),
+                    })
+                }
+            }
+        }
+
+        let mut report = MigrationReport::default();
+        for (i, (applied, down)) in reverts.iter().enumerate() {
+            let previous = reverts
+                .get(i + 1)
+                .map(|(applied, _)| applied.version)
+                .or(kept.last().map(|applied| applied.version))
+                .unwrap_or(0);
+            revert(db, applied, down, previous)?;
+            report.reverted.push((*applied).clone());
+        }
+        report.version = kept.last().map_or(0, |applied| applied.version);
+
+        Ok(report)
+    }
+
+    /// Every applied migration has to be here, with the same checksum
+    fn check(&self, applied: &[AppliedMigration]) -> Result<()> {
+        for applied in applied {
+            match self
+                .migrations
+                .iter()
+                .find(|m| m.version == applied.version)
+            {
+                None => {
+                    return Err(Error::MigrationMissing {
+                        version: applied.version,
+                        name: applied.name.clone(),
+                    })
+                }
+                Some(migration) if migration.checksum() != applied.checksum => {
+                    return Err(Error::MigrationChanged {
+                        version: migration.version,
+                        name: migration.name.to_string(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The migrations the old `__migrations__` table lists, it kept every
+    /// statement with its whitespace removed
+    fn legacy(&self, db: &Sqlite) -> Result<Vec<Migration>> {
+        if !table_exists(db, "__migrations__")? {
+            return Ok(vec![]);
+        }
+
+        let mut legacy = vec![];
+        for migration in &self.migrations {
+            let stripped: String = migration
+                .source
+                .as_deref()
+                .unwrap_or(&migration.sql)
+                .trim_end()
+                .trim_end_matches(';')
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            let found = db.query::<crate::Row>(
+                "select 1 from __migrations__ where sql = ?",
+                &[Value::Text(stripped)],
+            )?;
+            if !found.is_empty() {
+                legacy.push(migration.clone());
+            }
+        }
+
+        Ok(legacy)
+    }
+}
+
+fn table_exists(db: &Sqlite, name: &str) -> Result<bool> {
+    let tables = db.query::<crate::Row>(
+        "select name from sqlite_master where type = 'table' and name = ?",
+        &[Value::Text(name.to_string())],
+    )?;
+
+    Ok(!tables.is_empty())
+}
+
+/// Applies `migration`, recording the `legacy` ones in the same savepoint
+fn apply(
+    db: &Sqlite,
+    migration: &Migration,
+    legacy: &[Migration],
+) -> Result<(Vec<AppliedMigration>, AppliedMigration)> {
+    savepoint(db, migration.version, &migration.name, |sp| {
+        let adopted = legacy
+            .iter()
+            .map(|legacy| record(sp, legacy))
+            .collect::<Result<Vec<_>>>()?;
+        sp.execute_batch(&migration.sql)?;
+        if let Some(MigrationStep(Some(f))) = &migration.step {
+            f(sp)?;
+        }
+        Ok((adopted, record(sp, migration)?))
+    })
+}
+
+/// Records the `legacy` migrations in one savepoint, when there is nothing to apply
+fn adopt(db: &Sqlite, legacy: &[Migration]) -> Result<Vec<AppliedMigration>> {
+    let Some(last) = legacy.last() else {
+        return Ok(vec![]);
+    };
+    savepoint(db, last.version, &last.name, |sp| {
+        legacy.iter().map(|legacy| record(sp, legacy)).collect()
+    })
+}
+
+fn revert(db: &Sqlite, applied: &AppliedMigration, down: &str, previous: i64) -> Result<()> {
+    savepoint(db, applied.version, &applied.name, |sp| {
+        sp.execute_batch(down)?;
+        sp.execute(
+            "delete from _static_sqlite_migrations where version = ?",
+            vec![Value::Integer(applied.version)],
+        )?;
+        sp.execute_all(&format!("pragma user_version = {previous}"))?;
+        Ok(())
+    })
+}
+
+/// Runs `f` in a savepoint with foreign keys off, the way sqlite wants schema
+/// changes made, so rebuilding a table does not cascade into the ones pointing
+/// at it. When they were on they are checked before the savepoint is released.
+/// Inside a transaction sqlite ignores turning them off
+fn savepoint<T>(
+    db: &Sqlite,
+    version: i64,
+    name: &str,
+    f: impl FnOnce(&Sqlite) -> Result<T>,
+) -> Result<T> {
+    let foreign_keys = db
+        .query_first::<crate::Row>("pragma foreign_keys", &[])?
+        .map_or(Ok(0), |row| row.get::<i64>(0))?
+        == 1;
+    if foreign_keys {
+        db.execute_all("pragma foreign_keys = off")?;
+    }
+    let result = (|| {
+        let sp = db.savepoint("static_sqlite_migration")?;
+        let value = f(&sp)?;
+        if foreign_keys && !sp.foreign_key_check()?.is_empty() {
+            return Err(Error::MigrationForeignKeys {
+                version,
+                name: name.to_string(),
+            });
+        }
+        sp.release()?;
+        Ok(value)
+    })();
+    if foreign_keys {
+        db.execute_all("pragma foreign_keys = on")?;
+    }
+
+    result
+}
+
+fn statement_name(statement: &str) -> String {
+    let name = statement.split_whitespace().collect::<Vec<_>>().join(" ");
+    match name.char_indices().nth(60) {
+        Some((end, _)) => format!("{}...", &name[..end]),
+        None => name,
+    }
+}
+
+fn record(db: &Sqlite, migration: &Migration) -> Result<AppliedMigration> {
+    let applied = db
+        .query_first::<AppliedMigration>(
+            "insert into _static_sqlite_migrations (version, name, checksum, applied_at)
+             values (?, ?, ?, cast(strftime('%s', 'now') as integer))
+             returning version, name, checksum, applied_at",
+            &[
+                Value::Integer(migration.version),
+                Value::Text(migration.name.to_string()),
+                Value::Text(migration.checksum()),
+            ],
+        )?
+        .ok_or(Error::RowNotFound)?;
+    db.execute_all(&format!("pragma user_version = {}", migration.version))?;
+
+    Ok(applied)
+}
+
+/// Splits `sql` after every `;` that ends a complete statement, so the ones
+/// inside strings and trigger bodies do not split it
+pub fn split_statements(sql: &str) -> Vec<String> {
+    let mut statements = vec![];
+    let mut current = String::new();
+    for part in sql.split_inclusive(';') {
+        current.push_str(part);
+        if is_complete(&current) {
+            statements.push(std::mem::take(&mut current));
+        }
+    }
+    statements.push(current);
+
+    statements
+        .into_iter()
+        .map(|statement| statement.trim().to_string())
+        .filter(|statement| !statement.trim_end_matches(';').trim().is_empty())
+        .collect()
+}
+
+/// Whether `sql` ends with a complete statement
+fn is_complete(sql: &str) -> bool {
+    CString::new(sql)
+        .map(|c_sql| unsafe { static_sqlite_ffi::sqlite3_complete(c_sql.as_ptr()) } != 0)
+        .unwrap_or(false)
+}
+
+/// Pushes the statements of `sql` as a migration each, see `Migrations::from_sql`
+fn push_statements(migrations: &mut Vec<Migration>, sql: &str) {
+    for statement in split_statements(sql) {
+        let version = migrations.len() as i64 + 1;
+        migrations.push(Migration::new(
+            version,
+            statement_name(&statement),
+            statement,
+        ));
+    }
+}
+
+/// Makes `down` undo the last migration, when there is one
+fn attach_down(migrations: &mut [Migration], down: Option<String>) {
+    let down = down.filter(|down| !down.trim().is_empty());
+    if let (Some(last), Some(down)) = (migrations.last_mut(), down) {
+        last.down = Some(down.trim().to_string().into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_checks_and_adopts() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        let migrations = Migrations::from_sql(
+            "create table A (id integer primary key);
+             create trigger t after insert on A begin select 1; end;",
+        )?;
+        assert_eq!(migrations.iter().count(), 2);
+        let expanded = migrations
+            .clone()
+            .with_sql(1, "create table A (id integer)", None);
+        assert_eq!(
+            expanded.iter().next().unwrap().checksum(),
+            migrations.iter().next().unwrap().checksum()
+        );
+
+        let report = migrations.run(&db)?;
+        assert_eq!(report.applied.len(), 2);
+        assert_eq!(report.version, 2);
+        assert!(migrations.run(&db)?.applied.is_empty());
+
+        let reformatted = Migrations::new(vec![
+            Migration::new(1, "a", "create table A\n  (id integer primary key);"),
+            migrations.iter().nth(1).unwrap().clone(),
+        ]);
+        assert!(reformatted.run(&db).is_ok());
+        let edited = Migrations::new(vec![Migration::new(1, "a", "create table A (id text)")]);
+        assert!(matches!(
+            edited.run(&db),
+            Err(Error::MigrationChanged { version: 1, .. })
+        ));
+        let missing = Migrations::new(vec![migrations.iter().next().unwrap().clone()]);
+        assert!(matches!(
+            missing.run(&db),
+            Err(Error::MigrationMissing { version: 2, .. })
+        ));
+
+        let legacy = Sqlite::open(":memory:")?;
+        legacy.execute_batch(
+            "create table __migrations__ (sql text primary key not null);
+             insert into __migrations__ values ('createtableA(idintegerprimarykey)');
+             create table A (id integer primary key);",
+        )?;
+        let broken = Migrations::new(vec![
+            migrations.iter().next().unwrap().clone(),
+            Migration::new(2, "b", "create table A (id)"),
+        ]);
+        assert!(broken.run(&legacy).is_err());
+        assert!(Migrations::applied(&legacy)?.is_empty());
+        let report = migrations.run(&legacy)?;
+        assert_eq!(report.adopted.len(), 1);
+        assert_eq!(report.applied.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reverts_with_down_sql() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        let migrations = Migrations::from_sql(
+            "create table A (id integer primary key);
+
+             -- migrate:up add_b
+             create table B (id integer primary key);
+             create index b_id on B (id);
+             -- migrate:down
+             drop table B;
+
+             -- migrate:up
+             alter table A add column name text;
+             -- migrate:down
+             alter table A drop column name;",
+        )?;
+        let names = migrations
+            .iter()
+            .map(|m| m.name.as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names[..2],
+            ["create table A (id integer primary key);", "add_b"]
+        );
+        assert!(migrations.iter().next().unwrap().down.is_none());
+
+        migrations.run(&db)?;
+        let report = migrations.run_down(&db, "add_b")?;
+        assert_eq!(report.reverted.len(), 1);
+        assert_eq!(report.version, 2);
+        assert!(db.table("A")?.unwrap().column("name").is_none());
+
+        assert!(matches!(
+            migrations.run_down(&db, "0"),
+            Err(Error::MigrationIrreversible { version: 1, .. })
+        ));
+        assert!(db.table("B")?.is_some());
+        assert_eq!(migrations.run_down(&db, "1")?.version, 1);
+        assert!(db.table("B")?.is_none());
+        assert_eq!(migrations.run(&db)?.applied.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn attaches_each_down_to_its_statement() -> Result<()> {
+        let migrations = Migrations::from_sql(
+            "create table a (x);
+             -- migrate:down
+             drop table a;
+             create table b (x);
+             -- migrate:down
+             drop table b;
+             create table c (x);",
+        )?;
+        let migrations = migrations
+            .iter()
+            .map(|m| (m.sql.as_ref(), m.down.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            migrations,
+            [
+                ("create table a (x);", Some("drop table a;")),
+                ("create table b (x);", Some("drop table b;")),
+                ("create table c (x);", None),
+            ]
+        );
+
+        assert!(matches!(
+            Migrations::from_sql(
+                "-- migrate:up a
+                 create table a (x);
+                 -- migrate:down
+                 drop table a;
+                 -- migrate:down
+                 select 1;",
+            ),
+            Err(Error::MigrationDownRepeated { version: 1, name }) if name == "a"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn runs_rust_steps() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        let migrations = Migrations::from_sql(
+            "create table A (id integer primary key);
+             -- migrate:rust seed",
+        )?;
+        assert!(matches!(
+            migrations.run(&db),
+            Err(Error::MigrationStepMissing(name)) if name == "seed"
+        ));
+
+        let migrations = migrations.with_step("seed", |db| {
+            db.execute_all("insert into A (id) values (1)")?;
+            Ok(())
+        });
+        assert_eq!(migrations.run(&db)?.version, 2);
+        assert_eq!(db.query::<crate::Row>("select * from A", &[])?.len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{Error, FromRow, Result, Value};
+
+/// A row whose columns are only known at runtime, for the queries
+/// `sql!` can not check. Columns are looked up by index or by name
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Row {
+    columns: Vec<(String, Value)>,
+}
+
+/// A column of a `Row`, its position or its name
+pub trait RowIndex {
+    fn position(&self, row: &Row) -> Option<usize>;
+}
+
+impl RowIndex for usize {
+    fn position(&self, row: &Row) -> Option<usize> {
+        (*self < row.columns.len()).then_some(*self)
+    }
+}
+
+impl RowIndex for &str {
+    fn position(&self, row: &Row) -> Option<usize> {
+        row.columns.iter().position(|(name, _)| name == self)
+    }
+}
+
+impl Row {
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.columns.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn value(&self, index: impl RowIndex) -> Option<&Value> {
+        index
+            .position(self)
+            .map(|position| &self.columns[position].1)
+    }
+
+    /// Converts the column to `T`, fails if there is no such column
+    pub fn get<T>(&self, index: impl RowIndex + std::fmt::Display) -> Result<T>
+    where
+        T: TryFrom<Value, Error = Error>,
+    {
+        match self.value(&index) {
+            Some(value) => value.clone().try_into(),
+            None => Err(Error::ColumnNotFound(index.to_string())),
+        }
+    }
+
+    pub fn into_columns(self) -> Vec<(String, Value)> {
+        self.columns
+    }
+}
+
+impl<I: RowIndex> RowIndex for &I {
+    fn position(&self, row: &Row) -> Option<usize> {
+        (*self).position(row)
+    }
+}
+
+impl FromRow for Row {
+    fn from_row(columns: Vec<(String, Value)>) -> Result<Self> {
+        Ok(Row { columns })
+    }
+}
+
+impl From<Vec<(String, Value)>> for Row {
+    fn from(columns: Vec<(String, Value)>) -> Self {
+        Row { columns }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_by_index_and_name() -> Result<()> {
+        let row = Row::from(vec![
+            ("id".to_string(), Value::Integer(1)),
+            ("name".to_string(), Value::Text("a".into())),
+            ("note".to_string(), Value::Null),
+        ]);
+
+        assert_eq!(row.get::<i64>(0)?, 1);
+        assert_eq!(row.get::<String>("name")?, "a");
+        assert_eq!(row.get::<Option<String>>("note")?, None);
+        assert!(matches!(
+            row.get::<i64>("missing"),
+            Err(Error::ColumnNotFound(name)) if name == "missing"
+        ));
+        assert!(row.get::<i64>("name").is_err());
+        assert_eq!(row.names().collect::<Vec<_>>(), vec!["id", "name", "note"]);
+
+        Ok(())
+    }
+}
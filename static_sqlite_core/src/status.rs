@@ -0,0 +1,252 @@
+use static_sqlite_ffi::{
+    sqlite3_db_status, sqlite3_hard_heap_limit64, sqlite3_limit, sqlite3_soft_heap_limit64,
+    sqlite3_status64, SQLITE_OK,
+};
+
+use crate::{Error, Result, Sqlite};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Length,
+    SqlLength,
+    Column,
+    ExprDepth,
+    CompoundSelect,
+    VdbeOp,
+    FunctionArg,
+    Attached,
+    LikePatternLength,
+    VariableNumber,
+    TriggerDepth,
+    WorkerThreads,
+}
+
+impl Limit {
+    fn as_raw(self) -> i32 {
+        (match self {
+            Limit::Length => static_sqlite_ffi::SQLITE_LIMIT_LENGTH,
+            Limit::SqlLength => static_sqlite_ffi::SQLITE_LIMIT_SQL_LENGTH,
+            Limit::Column => static_sqlite_ffi::SQLITE_LIMIT_COLUMN,
+            Limit::ExprDepth => static_sqlite_ffi::SQLITE_LIMIT_EXPR_DEPTH,
+            Limit::CompoundSelect => static_sqlite_ffi::SQLITE_LIMIT_COMPOUND_SELECT,
+            Limit::VdbeOp => static_sqlite_ffi::SQLITE_LIMIT_VDBE_OP,
+            Limit::FunctionArg => static_sqlite_ffi::SQLITE_LIMIT_FUNCTION_ARG,
+            Limit::Attached => static_sqlite_ffi::SQLITE_LIMIT_ATTACHED,
+            Limit::LikePatternLength => static_sqlite_ffi::SQLITE_LIMIT_LIKE_PATTERN_LENGTH,
+            Limit::VariableNumber => static_sqlite_ffi::SQLITE_LIMIT_VARIABLE_NUMBER,
+            Limit::TriggerDepth => static_sqlite_ffi::SQLITE_LIMIT_TRIGGER_DEPTH,
+            Limit::WorkerThreads => static_sqlite_ffi::SQLITE_LIMIT_WORKER_THREADS,
+        }) as i32
+    }
+}
+
+/// Every `sqlite3_limit` of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub length: i32,
+    pub sql_length: i32,
+    pub column: i32,
+    pub expr_depth: i32,
+    pub compound_select: i32,
+    pub vdbe_op: i32,
+    pub function_arg: i32,
+    pub attached: i32,
+    pub like_pattern_length: i32,
+    pub variable_number: i32,
+    pub trigger_depth: i32,
+    pub worker_threads: i32,
+}
+
+/// A `current` value and the highest value it reached since the last reset
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counter {
+    pub current: i64,
+    pub highwater: i64,
+}
+
+/// `sqlite3_db_status` of a connection, memory sizes are in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DbStatus {
+    pub lookaside_used: Counter,
+    pub lookaside_hit: i64,
+    pub lookaside_miss_size: i64,
+    pub lookaside_miss_full: i64,
+    pub cache_used: i64,
+    pub cache_used_shared: i64,
+    pub cache_hit: i64,
+    pub cache_miss: i64,
+    pub cache_write: i64,
+    pub cache_spill: i64,
+    pub schema_used: i64,
+    pub stmt_used: i64,
+    pub deferred_fks: i64,
+}
+
+/// Process wide `sqlite3_status64`, memory sizes are in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStatus {
+    pub memory_used: Counter,
+    pub malloc_size: Counter,
+    pub malloc_count: Counter,
+    pub pagecache_used: Counter,
+    pub pagecache_overflow: Counter,
+    pub pagecache_size: Counter,
+    pub parser_stack: Counter,
+}
+
+/// Everything a metrics exporter wants from one connection at one point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status {
+    pub db: DbStatus,
+    pub memory: MemoryStatus,
+    pub limits: Limits,
+    pub soft_heap_limit: i64,
+    pub hard_heap_limit: i64,
+}
+
+impl Sqlite {
+    pub fn limit(&self, limit: Limit) -> i32 {
+        unsafe { sqlite3_limit(self.db, limit.as_raw(), -1) }
+    }
+
+    /// Sets a limit and returns the previous value. Values above the
+    /// compile time maximum are silently truncated by sqlite
+    pub fn set_limit(&self, limit: Limit, value: i32) -> i32 {
+        unsafe { sqlite3_limit(self.db, limit.as_raw(), value.max(0)) }
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            length: self.limit(Limit::Length),
+            sql_length: self.limit(Limit::SqlLength),
+            column: self.limit(Limit::Column),
+            expr_depth: self.limit(Limit::ExprDepth),
+            compound_select: self.limit(Limit::CompoundSelect),
+            vdbe_op: self.limit(Limit::VdbeOp),
+            function_arg: self.limit(Limit::FunctionArg),
+            attached: self.limit(Limit::Attached),
+            like_pattern_length: self.limit(Limit::LikePatternLength),
+            variable_number: self.limit(Limit::VariableNumber),
+            trigger_depth: self.limit(Limit::TriggerDepth),
+            worker_threads: self.limit(Limit::WorkerThreads),
+        }
+    }
+
+    /// Reads every `sqlite3_db_status` counter, `reset` resets the
+    /// highwater marks and the hit/miss counters after reading them
+    pub fn db_status(&self, reset: bool) -> Result<DbStatus> {
+        let counter = |op: u32| -> Result<Counter> {
+            let mut current = 0;
+            let mut highwater = 0;
+            let rc = unsafe {
+                sqlite3_db_status(
+                    self.db,
+                    op as i32,
+                    &mut current,
+                    &mut highwater,
+                    reset as i32,
+                )
+            };
+            match rc == SQLITE_OK as i32 {
+                true => Ok(Counter {
+                    current: current as i64,
+                    highwater: highwater as i64,
+                }),
+                false => Err(Error::Sqlite(format!("sqlite3_db_status({op}) failed"))),
+            }
+        };
+
+        Ok(DbStatus {
+            lookaside_used: counter(static_sqlite_ffi::SQLITE_DBSTATUS_LOOKASIDE_USED)?,
+            lookaside_hit: counter(static_sqlite_ffi::SQLITE_DBSTATUS_LOOKASIDE_HIT)?.highwater,
+            lookaside_miss_size: counter(static_sqlite_ffi::SQLITE_DBSTATUS_LOOKASIDE_MISS_SIZE)?
+                .highwater,
+            lookaside_miss_full: counter(static_sqlite_ffi::SQLITE_DBSTATUS_LOOKASIDE_MISS_FULL)?
+                .highwater,
+            cache_used: counter(static_sqlite_ffi::SQLITE_DBSTATUS_CACHE_USED)?.current,
+            cache_used_shared: counter(static_sqlite_ffi::SQLITE_DBSTATUS_CACHE_USED_SHARED)?
+                .current,
+            cache_hit: counter(static_sqlite_ffi::SQLITE_DBSTATUS_CACHE_HIT)?.current,
+            cache_miss: counter(static_sqlite_ffi::SQLITE_DBSTATUS_CACHE_MISS)?.current,
+            cache_write: counter(static_sqlite_ffi::SQLITE_DBSTATUS_CACHE_WRITE)?.current,
+            cache_spill: counter(static_sqlite_ffi::SQLITE_DBSTATUS_CACHE_SPILL)?.current,
+            schema_used: counter(static_sqlite_ffi::SQLITE_DBSTATUS_SCHEMA_USED)?.current,
+            stmt_used: counter(static_sqlite_ffi::SQLITE_DBSTATUS_STMT_USED)?.current,
+            deferred_fks: counter(static_sqlite_ffi::SQLITE_DBSTATUS_DEFERRED_FKS)?.current,
+        })
+    }
+
+    pub fn status(&self) -> Result<Status> {
+        Ok(Status {
+            db: self.db_status(false)?,
+            memory: memory_status(false)?,
+            limits: self.limits(),
+            soft_heap_limit: soft_heap_limit(-1),
+            hard_heap_limit: hard_heap_limit(-1),
+        })
+    }
+}
+
+/// Reads the process wide `sqlite3_status64` counters
+pub fn memory_status(reset: bool) -> Result<MemoryStatus> {
+    let counter = |op: u32| -> Result<Counter> {
+        let mut current = 0;
+        let mut highwater = 0;
+        let rc = unsafe { sqlite3_status64(op as i32, &mut current, &mut highwater, reset as i32) };
+        match rc == SQLITE_OK as i32 {
+            true => Ok(Counter { current, highwater }),
+            false => Err(Error::Sqlite(format!("sqlite3_status64({op}) failed"))),
+        }
+    };
+
+    Ok(MemoryStatus {
+        memory_used: counter(static_sqlite_ffi::SQLITE_STATUS_MEMORY_USED)?,
+        malloc_size: counter(static_sqlite_ffi::SQLITE_STATUS_MALLOC_SIZE)?,
+        malloc_count: counter(static_sqlite_ffi::SQLITE_STATUS_MALLOC_COUNT)?,
+        pagecache_used: counter(static_sqlite_ffi::SQLITE_STATUS_PAGECACHE_USED)?,
+        pagecache_overflow: counter(static_sqlite_ffi::SQLITE_STATUS_PAGECACHE_OVERFLOW)?,
+        pagecache_size: counter(static_sqlite_ffi::SQLITE_STATUS_PAGECACHE_SIZE)?,
+        parser_stack: counter(static_sqlite_ffi::SQLITE_STATUS_PARSER_STACK)?,
+    })
+}
+
+/// Sets the process wide soft heap limit in bytes and returns the previous
+/// one. A negative `bytes` only reads it, 0 disables the limit
+pub fn soft_heap_limit(bytes: i64) -> i64 {
+    unsafe { sqlite3_soft_heap_limit64(bytes) }
+}
+
+/// Sets the process wide hard heap limit in bytes and returns the previous
+/// one. A negative `bytes` only reads it, 0 disables the limit
+pub fn hard_heap_limit(bytes: i64) -> i64 {
+    unsafe { sqlite3_hard_heap_limit64(bytes) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_work() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        let previous = db.set_limit(Limit::SqlLength, 20);
+        assert_eq!(db.limit(Limit::SqlLength), 20);
+        assert!(db.execute_all("select 1 as a_rather_long_column").is_err());
+        assert_eq!(db.set_limit(Limit::SqlLength, previous), 20);
+        assert!(db.execute_all("select 1 as a_rather_long_column").is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn status_works() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        db.execute_all("create table Row (id integer primary key)")?;
+        let status = db.status()?;
+        assert!(status.db.schema_used > 0);
+        assert!(status.memory.memory_used.current > 0);
+        assert_eq!(status.limits.sql_length, db.limit(Limit::SqlLength));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1374 @@
+use static_sqlite_ffi::{
+    sqlite3_file, sqlite3_free, sqlite3_int64, sqlite3_io_methods, sqlite3_malloc64, sqlite3_vfs,
+    sqlite3_vfs_find, sqlite3_vfs_register, SQLITE_BUSY, SQLITE_CANTOPEN, SQLITE_FULL,
+    SQLITE_IOERR, SQLITE_IOERR_ACCESS, SQLITE_IOERR_CHECKRESERVEDLOCK, SQLITE_IOERR_DELETE,
+    SQLITE_IOERR_DELETE_NOENT, SQLITE_IOERR_FSTAT, SQLITE_IOERR_FSYNC, SQLITE_IOERR_LOCK,
+    SQLITE_IOERR_READ, SQLITE_IOERR_SHMLOCK, SQLITE_IOERR_SHMMAP, SQLITE_IOERR_SHORT_READ,
+    SQLITE_IOERR_TRUNCATE, SQLITE_IOERR_UNLOCK, SQLITE_IOERR_WRITE, SQLITE_NOTFOUND, SQLITE_OK,
+};
+
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    io::{self, ErrorKind},
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+};
+
+use crate::{Error, Result};
+
+/// The `SQLITE_OPEN_*` flags sqlite passes when it opens a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenFlags(pub i32);
+
+impl OpenFlags {
+    pub fn read_only(self) -> bool {
+        self.has(static_sqlite_ffi::SQLITE_OPEN_READONLY)
+    }
+
+    pub fn create(self) -> bool {
+        self.has(static_sqlite_ffi::SQLITE_OPEN_CREATE)
+    }
+
+    pub fn delete_on_close(self) -> bool {
+        self.has(static_sqlite_ffi::SQLITE_OPEN_DELETEONCLOSE)
+    }
+
+    pub fn main_db(self) -> bool {
+        self.has(static_sqlite_ffi::SQLITE_OPEN_MAIN_DB)
+    }
+
+    pub fn main_journal(self) -> bool {
+        self.has(static_sqlite_ffi::SQLITE_OPEN_MAIN_JOURNAL)
+    }
+
+    fn has(self, flag: u32) -> bool {
+        self.0 & flag as i32 != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockLevel {
+    None,
+    Shared,
+    Reserved,
+    Pending,
+    Exclusive,
+}
+
+impl LockLevel {
+    fn from_raw(level: c_int) -> Self {
+        match level as u32 {
+            static_sqlite_ffi::SQLITE_LOCK_SHARED => LockLevel::Shared,
+            static_sqlite_ffi::SQLITE_LOCK_RESERVED => LockLevel::Reserved,
+            static_sqlite_ffi::SQLITE_LOCK_PENDING => LockLevel::Pending,
+            static_sqlite_ffi::SQLITE_LOCK_EXCLUSIVE => LockLevel::Exclusive,
+            _ => LockLevel::None,
+        }
+    }
+}
+
+/// A file name handed out by sqlite, it stays valid until the file is closed
+#[derive(Debug, Clone, Copy)]
+pub struct VfsPath<'a>(&'a CStr);
+
+impl VfsPath<'_> {
+    pub fn as_str(&self) -> std::borrow::Cow<'_, str> {
+        self.0.to_string_lossy()
+    }
+
+    pub fn as_c_str(&self) -> &CStr {
+        self.0
+    }
+}
+
+/// A sqlite vfs implemented in Rust, see `register_vfs`.
+///
+/// Randomness, sleeping, the current time and extension loading
+/// are always taken from the default vfs
+pub trait Vfs: Send + Sync + 'static {
+    type File: VfsFile;
+
+    /// `path` is `None` for temporary files sqlite deletes on close
+    fn open(&self, path: Option<VfsPath>, flags: OpenFlags) -> io::Result<Self::File>;
+
+    fn delete(&self, path: &str, sync_dir: bool) -> io::Result<()>;
+
+    fn exists(&self, path: &str) -> io::Result<bool>;
+
+    fn full_pathname(&self, path: &str) -> io::Result<String> {
+        Ok(path.to_string())
+    }
+}
+
+/// A file of a `Vfs`. Errors of kind `StorageFull` become `SQLITE_FULL`,
+/// `WouldBlock` becomes `SQLITE_BUSY` and everything else an `SQLITE_IOERR`.
+///
+/// The wal needs the `shm_*` methods, files that leave `SHM` false only
+/// support it with `pragma locking_mode = exclusive`, sqlite keeps the
+/// wal index on the heap then. `pragma journal_mode = wal` is ignored otherwise
+pub trait VfsFile: Send + 'static {
+    /// Whether the file implements the `shm_*` methods
+    const SHM: bool = false;
+
+    /// Returns how many bytes were read, the rest of `buf` is zero filled
+    fn read(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    fn write(&mut self, buf: &[u8], offset: u64) -> io::Result<()>;
+
+    fn truncate(&mut self, size: u64) -> io::Result<()>;
+
+    fn sync(&mut self) -> io::Result<()>;
+
+    fn size(&self) -> io::Result<u64>;
+
+    fn lock(&mut self, _level: LockLevel) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn unlock(&mut self, _level: LockLevel) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Whether any connection holds a reserved lock on the file
+    fn reserved(&self) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    fn sector_size(&self) -> i32 {
+        4096
+    }
+
+    /// `SQLITE_IOCAP_*` flags
+    fn device_characteristics(&self) -> i32 {
+        0
+    }
+
+    /// Handles the `SQLITE_FCNTL_*` file control `op`. Returns a sqlite
+    /// result code, `SQLITE_NOTFOUND` for the unhandled ones
+    ///
+    /// # Safety
+    ///
+    /// `arg` is the pointer sqlite passed, what it points to depends on `op`
+    unsafe fn file_control(&mut self, _op: i32, _arg: *mut c_void) -> i32 {
+        SQLITE_NOTFOUND as i32
+    }
+
+    /// Maps wal index `region` of `size` bytes, allocating it when `extend`
+    /// is set. A null pointer when it does not exist and `extend` is not set
+    fn shm_map(&mut self, _region: i32, _size: i32, _extend: bool) -> io::Result<*mut c_void> {
+        Err(ErrorKind::Unsupported.into())
+    }
+
+    /// Takes or releases the wal index locks `offset..offset + n`,
+    /// `flags` are the `SQLITE_SHM_*` flags
+    fn shm_lock(&mut self, _offset: i32, _n: i32, _flags: i32) -> io::Result<()> {
+        Err(ErrorKind::Unsupported.into())
+    }
+
+    fn shm_barrier(&mut self) {
+        std::sync::atomic::fence(std::sync::atomic::Ordering::SeqCst);
+    }
+
+    fn shm_unmap(&mut self, _delete: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Registers `vfs` under `name` for the whole process. Registering a name
+/// twice shadows the earlier vfs, which stays alive as connections may still use it
+pub fn register_vfs<V: Vfs>(name: &str, vfs: V, make_default: bool) -> Result<()> {
+    let name = CString::new(name)?;
+    let parent = unsafe { sqlite3_vfs_find(std::ptr::null()) };
+    if parent.is_null() {
+        return Err(Error::Sqlite("there is no default vfs".into()));
+    }
+    let data = Box::into_raw(Box::new(VfsData {
+        base: sqlite3_vfs {
+            iVersion: 2,
+            szOsFile: std::mem::size_of::<FileWrapper<V::File>>() as c_int,
+            mxPathname: 1024,
+            pNext: std::ptr::null_mut(),
+            zName: name.as_ptr(),
+            pAppData: std::ptr::null_mut(),
+            xOpen: Some(x_open::<V>),
+            xDelete: Some(x_delete::<V>),
+            xAccess: Some(x_access::<V>),
+            xFullPathname: Some(x_full_pathname::<V>),
+            xDlOpen: Some(x_dl_open::<V>),
+            xDlError: Some(x_dl_error::<V>),
+            xDlSym: Some(x_dl_sym::<V>),
+            xDlClose: Some(x_dl_close::<V>),
+            xRandomness: Some(x_randomness::<V>),
+            xSleep: Some(x_sleep::<V>),
+            xCurrentTime: Some(x_current_time::<V>),
+            xGetLastError: Some(x_get_last_error::<V>),
+            xCurrentTimeInt64: Some(x_current_time_int64::<V>),
+            xSetSystemCall: None,
+            xGetSystemCall: None,
+            xNextSystemCall: None,
+        },
+        io_methods: io_methods::<V::File>(),
+        _name: name,
+        parent,
+        vfs,
+    }));
+
+    unsafe {
+        (*data).base.pAppData = data as *mut c_void;
+        match sqlite3_vfs_register(&mut (*data).base, make_default as c_int) {
+            rc if rc == SQLITE_OK as c_int => Ok(()),
+            rc => {
+                drop(Box::from_raw(data));
+                Err(Error::Sqlite(format!(
+                    "sqlite3_vfs_register failed with {rc}"
+                )))
+            }
+        }
+    }
+}
+
+struct VfsData<V: Vfs> {
+    base: sqlite3_vfs,
+    io_methods: sqlite3_io_methods,
+    _name: CString,
+    parent: *mut sqlite3_vfs,
+    vfs: V,
+}
+
+#[repr(C)]
+struct FileWrapper<F> {
+    base: sqlite3_file,
+    file: F,
+}
+
+fn io_methods<F: VfsFile>() -> sqlite3_io_methods {
+    sqlite3_io_methods {
+        iVersion: if F::SHM { 2 } else { 1 },
+        xClose: Some(x_close::<F>),
+        xRead: Some(x_read::<F>),
+        xWrite: Some(x_write::<F>),
+        xTruncate: Some(x_truncate::<F>),
+        xSync: Some(x_sync::<F>),
+        xFileSize: Some(x_file_size::<F>),
+        xLock: Some(x_lock::<F>),
+        xUnlock: Some(x_unlock::<F>),
+        xCheckReservedLock: Some(x_check_reserved_lock::<F>),
+        xFileControl: Some(x_file_control::<F>),
+        xSectorSize: Some(x_sector_size::<F>),
+        xDeviceCharacteristics: Some(x_device_characteristics::<F>),
+        xShmMap: F::SHM.then_some(x_shm_map::<F>),
+        xShmLock: F::SHM.then_some(x_shm_lock::<F>),
+        xShmBarrier: F::SHM.then_some(x_shm_barrier::<F>),
+        xShmUnmap: F::SHM.then_some(x_shm_unmap::<F>),
+        xFetch: None,
+        xUnfetch: None,
+    }
+}
+
+/// Runs `f`, turning a panic into an error so it never unwinds into sqlite
+fn io_guard<R>(f: impl FnOnce() -> io::Result<R>) -> io::Result<R> {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| Err(io::Error::other("vfs panicked")))
+}
+
+fn io_code<R>(result: io::Result<R>, ioerr: u32) -> c_int {
+    (match result {
+        Ok(_) => SQLITE_OK,
+        Err(err) => match err.kind() {
+            ErrorKind::StorageFull => SQLITE_FULL,
+            ErrorKind::WouldBlock => SQLITE_BUSY,
+            _ => ioerr,
+        },
+    }) as c_int
+}
+
+unsafe fn data<'a, V: Vfs>(vfs: *mut sqlite3_vfs) -> &'a VfsData<V> {
+    &*((*vfs).pAppData as *const VfsData<V>)
+}
+
+unsafe fn file_mut<'a, F>(file: *mut sqlite3_file) -> &'a mut F {
+    &mut (*(file as *mut FileWrapper<F>)).file
+}
+
+unsafe fn path<'a>(path: *const c_char) -> io::Result<&'a str> {
+    CStr::from_ptr(path)
+        .to_str()
+        .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))
+}
+
+unsafe extern "C" fn x_open<V: Vfs>(
+    vfs: *mut sqlite3_vfs,
+    name: *const c_char,
+    file: *mut sqlite3_file,
+    flags: c_int,
+    out_flags: *mut c_int,
+) -> c_int {
+    let data = data::<V>(vfs);
+    let path = match name.is_null() {
+        true => None,
+        false => Some(VfsPath(CStr::from_ptr(name))),
+    };
+
+    match io_guard(|| data.vfs.open(path, OpenFlags(flags))) {
+        Ok(opened) => {
+            std::ptr::write(
+                file as *mut FileWrapper<V::File>,
+                FileWrapper {
+                    base: sqlite3_file {
+                        pMethods: &data.io_methods,
+                    },
+                    file: opened,
+                },
+            );
+            if !out_flags.is_null() {
+                *out_flags = flags;
+            }
+            SQLITE_OK as c_int
+        }
+        Err(err) => {
+            (*file).pMethods = std::ptr::null();
+            match err.kind() {
+                ErrorKind::StorageFull => SQLITE_FULL as c_int,
+                _ => SQLITE_CANTOPEN as c_int,
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn x_delete<V: Vfs>(
+    vfs: *mut sqlite3_vfs,
+    name: *const c_char,
+    sync_dir: c_int,
+) -> c_int {
+    let data = data::<V>(vfs);
+    match io_guard(|| data.vfs.delete(path(name)?, sync_dir != 0)) {
+        Err(err) if err.kind() == ErrorKind::NotFound => SQLITE_IOERR_DELETE_NOENT as c_int,
+        result => io_code(result, SQLITE_IOERR_DELETE),
+    }
+}
+
+unsafe extern "C" fn x_access<V: Vfs>(
+    vfs: *mut sqlite3_vfs,
+    name: *const c_char,
+    _flags: c_int,
+    out: *mut c_int,
+) -> c_int {
+    let data = data::<V>(vfs);
+    let result = io_guard(|| data.vfs.exists(path(name)?));
+    if let Ok(exists) = result {
+        *out = exists as c_int;
+    }
+    io_code(result, SQLITE_IOERR_ACCESS)
+}
+
+unsafe extern "C" fn x_full_pathname<V: Vfs>(
+    vfs: *mut sqlite3_vfs,
+    name: *const c_char,
+    n_out: c_int,
+    out: *mut c_char,
+) -> c_int {
+    let data = data::<V>(vfs);
+    match io_guard(|| data.vfs.full_pathname(path(name)?)) {
+        Ok(full) if full.len() < n_out as usize && !full.contains('\0') => {
+            std::ptr::copy_nonoverlapping(full.as_ptr() as *const c_char, out, full.len());
+            *out.add(full.len()) = 0;
+            SQLITE_OK as c_int
+        }
+        _ => SQLITE_CANTOPEN as c_int,
+    }
+}
+
+unsafe extern "C" fn x_dl_open<V: Vfs>(vfs: *mut sqlite3_vfs, name: *const c_char) -> *mut c_void {
+    let parent = data::<V>(vfs).parent;
+    match (*parent).xDlOpen {
+        Some(f) => f(parent, name),
+        None => std::ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn x_dl_error<V: Vfs>(vfs: *mut sqlite3_vfs, n: c_int, out: *mut c_char) {
+    let parent = data::<V>(vfs).parent;
+    if let Some(f) = (*parent).xDlError {
+        f(parent, n, out)
+    }
+}
+
+type DlSym = unsafe extern "C" fn(*mut sqlite3_vfs, *mut c_void, *const c_char);
+
+unsafe extern "C" fn x_dl_sym<V: Vfs>(
+    vfs: *mut sqlite3_vfs,
+    handle: *mut c_void,
+    symbol: *const c_char,
+) -> Option<DlSym> {
+    let parent = data::<V>(vfs).parent;
+    match (*parent).xDlSym {
+        Some(f) => f(parent, handle, symbol),
+        None => None,
+    }
+}
+
+unsafe extern "C" fn x_dl_close<V: Vfs>(vfs: *mut sqlite3_vfs, handle: *mut c_void) {
+    let parent = data::<V>(vfs).parent;
+    if let Some(f) = (*parent).xDlClose {
+        f(parent, handle)
+    }
+}
+
+unsafe extern "C" fn x_randomness<V: Vfs>(
+    vfs: *mut sqlite3_vfs,
+    n: c_int,
+    out: *mut c_char,
+) -> c_int {
+    let parent = data::<V>(vfs).parent;
+    match (*parent).xRandomness {
+        Some(f) => f(parent, n, out),
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn x_sleep<V: Vfs>(vfs: *mut sqlite3_vfs, microseconds: c_int) -> c_int {
+    let parent = data::<V>(vfs).parent;
+    match (*parent).xSleep {
+        Some(f) => f(parent, microseconds),
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn x_current_time<V: Vfs>(vfs: *mut sqlite3_vfs, out: *mut f64) -> c_int {
+    let parent = data::<V>(vfs).parent;
+    match (*parent).xCurrentTime {
+        Some(f) => f(parent, out),
+        None => SQLITE_IOERR as c_int,
+    }
+}
+
+unsafe extern "C" fn x_get_last_error<V: Vfs>(
+    vfs: *mut sqlite3_vfs,
+    n: c_int,
+    out: *mut c_char,
+) -> c_int {
+    let parent = data::<V>(vfs).parent;
+    match (*parent).xGetLastError {
+        Some(f) => f(parent, n, out),
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn x_current_time_int64<V: Vfs>(
+    vfs: *mut sqlite3_vfs,
+    out: *mut sqlite3_int64,
+) -> c_int {
+    let parent = data::<V>(vfs).parent;
+    match (*parent).xCurrentTimeInt64 {
+        Some(f) if (*parent).iVersion >= 2 => f(parent, out),
+        _ => SQLITE_IOERR as c_int,
+    }
+}
+
+unsafe extern "C" fn x_close<F: VfsFile>(file: *mut sqlite3_file) -> c_int {
+    let _ = catch_unwind(AssertUnwindSafe(|| {
+        std::ptr::drop_in_place(file as *mut FileWrapper<F>)
+    }));
+    SQLITE_OK as c_int
+}
+
+unsafe extern "C" fn x_read<F: VfsFile>(
+    file: *mut sqlite3_file,
+    buf: *mut c_void,
+    amount: c_int,
+    offset: sqlite3_int64,
+) -> c_int {
+    let buf = std::slice::from_raw_parts_mut(buf as *mut u8, amount as usize);
+    match io_guard(|| file_mut::<F>(file).read(buf, offset as u64)) {
+        Ok(n) if n >= buf.len() => SQLITE_OK as c_int,
+        Ok(n) => {
+            buf[n..].fill(0);
+            SQLITE_IOERR_SHORT_READ as c_int
+        }
+        result => io_code(result, SQLITE_IOERR_READ),
+    }
+}
+
+unsafe extern "C" fn x_write<F: VfsFile>(
+    file: *mut sqlite3_file,
+    buf: *const c_void,
+    amount: c_int,
+    offset: sqlite3_int64,
+) -> c_int {
+    let buf = std::slice::from_raw_parts(buf as *const u8, amount as usize);
+    io_code(
+        io_guard(|| file_mut::<F>(file).write(buf, offset as u64)),
+        SQLITE_IOERR_WRITE,
+    )
+}
+
+unsafe extern "C" fn x_truncate<F: VfsFile>(file: *mut sqlite3_file, size: sqlite3_int64) -> c_int {
+    io_code(
+        io_guard(|| file_mut::<F>(file).truncate(size as u64)),
+        SQLITE_IOERR_TRUNCATE,
+    )
+}
+
+unsafe extern "C" fn x_sync<F: VfsFile>(file: *mut sqlite3_file, _flags: c_int) -> c_int {
+    io_code(io_guard(|| file_mut::<F>(file).sync()), SQLITE_IOERR_FSYNC)
+}
+
+unsafe extern "C" fn x_file_size<F: VfsFile>(
+    file: *mut sqlite3_file,
+    size: *mut sqlite3_int64,
+) -> c_int {
+    let result = io_guard(|| file_mut::<F>(file).size());
+    if let Ok(n) = result {
+        *size = n as sqlite3_int64;
+    }
+    io_code(result, SQLITE_IOERR_FSTAT)
+}
+
+unsafe extern "C" fn x_lock<F: VfsFile>(file: *mut sqlite3_file, level: c_int) -> c_int {
+    io_code(
+        io_guard(|| file_mut::<F>(file).lock(LockLevel::from_raw(level))),
+        SQLITE_IOERR_LOCK,
+    )
+}
+
+unsafe extern "C" fn x_unlock<F: VfsFile>(file: *mut sqlite3_file, level: c_int) -> c_int {
+    io_code(
+        io_guard(|| file_mut::<F>(file).unlock(LockLevel::from_raw(level))),
+        SQLITE_IOERR_UNLOCK,
+    )
+}
+
+unsafe extern "C" fn x_check_reserved_lock<F: VfsFile>(
+    file: *mut sqlite3_file,
+    out: *mut c_int,
+) -> c_int {
+    let result = io_guard(|| file_mut::<F>(file).reserved());
+    if let Ok(reserved) = result {
+        *out = reserved as c_int;
+    }
+    io_code(result, SQLITE_IOERR_CHECKRESERVEDLOCK)
+}
+
+unsafe extern "C" fn x_file_control<F: VfsFile>(
+    file: *mut sqlite3_file,
+    op: c_int,
+    arg: *mut c_void,
+) -> c_int {
+    catch_unwind(AssertUnwindSafe(|| {
+        file_mut::<F>(file).file_control(op, arg)
+    }))
+    .unwrap_or(SQLITE_IOERR as c_int)
+}
+
+unsafe extern "C" fn x_shm_map<F: VfsFile>(
+    file: *mut sqlite3_file,
+    region: c_int,
+    size: c_int,
+    extend: c_int,
+    out: *mut *mut c_void,
+) -> c_int {
+    let result = io_guard(|| file_mut::<F>(file).shm_map(region, size, extend != 0));
+    *out = *result.as_ref().unwrap_or(&std::ptr::null_mut());
+    io_code(result, SQLITE_IOERR_SHMMAP)
+}
+
+unsafe extern "C" fn x_shm_lock<F: VfsFile>(
+    file: *mut sqlite3_file,
+    offset: c_int,
+    n: c_int,
+    flags: c_int,
+) -> c_int {
+    io_code(
+        io_guard(|| file_mut::<F>(file).shm_lock(offset, n, flags)),
+        SQLITE_IOERR_SHMLOCK,
+    )
+}
+
+unsafe extern "C" fn x_shm_barrier<F: VfsFile>(file: *mut sqlite3_file) {
+    let _ = catch_unwind(AssertUnwindSafe(|| file_mut::<F>(file).shm_barrier()));
+}
+
+unsafe extern "C" fn x_shm_unmap<F: VfsFile>(file: *mut sqlite3_file, delete: c_int) -> c_int {
+    io_code(
+        io_guard(|| file_mut::<F>(file).shm_unmap(delete != 0)),
+        SQLITE_IOERR,
+    )
+}
+
+unsafe extern "C" fn x_sector_size<F: VfsFile>(file: *mut sqlite3_file) -> c_int {
+    file_mut::<F>(file).sector_size()
+}
+
+unsafe extern "C" fn x_device_characteristics<F: VfsFile>(file: *mut sqlite3_file) -> c_int {
+    file_mut::<F>(file).device_characteristics()
+}
+
+/// The vfs that was the default when `DefaultVfs::new` was called,
+/// usually the operating system one. Useful to wrap in another `Vfs`
+pub struct DefaultVfs {
+    raw: *mut sqlite3_vfs,
+}
+
+unsafe impl Send for DefaultVfs {}
+unsafe impl Sync for DefaultVfs {}
+
+impl DefaultVfs {
+    pub fn new() -> Result<Self> {
+        Self::find(None)
+    }
+
+    pub fn find(name: Option<&str>) -> Result<Self> {
+        let name = name.map(CString::new).transpose()?;
+        let raw = unsafe {
+            sqlite3_vfs_find(name.as_ref().map_or(std::ptr::null(), |name| name.as_ptr()))
+        };
+        match raw.is_null() {
+            true => Err(Error::Sqlite("vfs not found".into())),
+            false => Ok(DefaultVfs { raw }),
+        }
+    }
+}
+
+fn check(rc: c_int) -> io::Result<()> {
+    match rc as u32 & 0xff {
+        SQLITE_OK => Ok(()),
+        SQLITE_FULL => Err(io::Error::new(
+            ErrorKind::StorageFull,
+            "database or disk is full",
+        )),
+        SQLITE_BUSY => Err(io::Error::new(ErrorKind::WouldBlock, "database is locked")),
+        SQLITE_CANTOPEN => Err(io::Error::new(ErrorKind::NotFound, "unable to open file")),
+        _ => Err(io::Error::other(format!("sqlite io error {rc}"))),
+    }
+}
+
+impl Vfs for DefaultVfs {
+    type File = DefaultFile;
+
+    fn open(&self, path: Option<VfsPath>, flags: OpenFlags) -> io::Result<DefaultFile> {
+        unsafe {
+            let size = (*self.raw).szOsFile as u64;
+            let file = sqlite3_malloc64(size) as *mut sqlite3_file;
+            if file.is_null() {
+                return Err(io::Error::from(ErrorKind::OutOfMemory));
+            }
+            std::ptr::write_bytes(file as *mut u8, 0, size as usize);
+            let name = path.map_or(std::ptr::null(), |path| path.as_c_str().as_ptr());
+            let mut out_flags = 0;
+            let open = (*self.raw).xOpen.ok_or(ErrorKind::Unsupported)?;
+            let rc = open(self.raw, name, file, flags.0, &mut out_flags);
+            let file = DefaultFile { file };
+            check(rc)?;
+
+            Ok(file)
+        }
+    }
+
+    fn delete(&self, path: &str, sync_dir: bool) -> io::Result<()> {
+        let path = CString::new(path)?;
+        unsafe {
+            let delete = (*self.raw).xDelete.ok_or(ErrorKind::Unsupported)?;
+            match delete(self.raw, path.as_ptr(), sync_dir as c_int) as u32 {
+                SQLITE_IOERR_DELETE_NOENT => Err(ErrorKind::NotFound.into()),
+                rc => check(rc as c_int),
+            }
+        }
+    }
+
+    fn exists(&self, path: &str) -> io::Result<bool> {
+        let path = CString::new(path)?;
+        let mut exists = 0;
+        unsafe {
+            let access = (*self.raw).xAccess.ok_or(ErrorKind::Unsupported)?;
+            check(access(
+                self.raw,
+                path.as_ptr(),
+                static_sqlite_ffi::SQLITE_ACCESS_EXISTS as c_int,
+                &mut exists,
+            ))?;
+        }
+        Ok(exists != 0)
+    }
+
+    fn full_pathname(&self, path: &str) -> io::Result<String> {
+        let path = CString::new(path)?;
+        unsafe {
+            let len = (*self.raw).mxPathname as usize + 1;
+            let mut out = vec![0 as c_char; len];
+            let full_pathname = (*self.raw).xFullPathname.ok_or(ErrorKind::Unsupported)?;
+            check(full_pathname(
+                self.raw,
+                path.as_ptr(),
+                len as c_int,
+                out.as_mut_ptr(),
+            ))?;
+            Ok(CStr::from_ptr(out.as_ptr()).to_string_lossy().into_owned())
+        }
+    }
+}
+
+/// A file opened by `DefaultVfs`
+pub struct DefaultFile {
+    file: *mut sqlite3_file,
+}
+
+unsafe impl Send for DefaultFile {}
+
+impl DefaultFile {
+    fn methods(&self) -> &sqlite3_io_methods {
+        unsafe { &*(*self.file).pMethods }
+    }
+
+    /// The methods, if they have the shm ones
+    fn shm(&self) -> io::Result<&sqlite3_io_methods> {
+        let methods = self.methods();
+        match methods.iVersion >= 2 {
+            true => Ok(methods),
+            false => Err(ErrorKind::Unsupported.into()),
+        }
+    }
+}
+
+impl VfsFile for DefaultFile {
+    const SHM: bool = true;
+
+    fn read(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let read = self.methods().xRead.ok_or(ErrorKind::Unsupported)?;
+        let rc = unsafe {
+            read(
+                self.file,
+                buf.as_mut_ptr() as *mut c_void,
+                buf.len() as c_int,
+                offset as sqlite3_int64,
+            )
+        };
+        match rc as u32 {
+            SQLITE_IOERR_SHORT_READ => {
+                Ok((self.size()?.saturating_sub(offset) as usize).min(buf.len()))
+            }
+            _ => check(rc).map(|_| buf.len()),
+        }
+    }
+
+    fn write(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+        let write = self.methods().xWrite.ok_or(ErrorKind::Unsupported)?;
+        check(unsafe {
+            write(
+                self.file,
+                buf.as_ptr() as *const c_void,
+                buf.len() as c_int,
+                offset as sqlite3_int64,
+            )
+        })
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        let truncate = self.methods().xTruncate.ok_or(ErrorKind::Unsupported)?;
+        check(unsafe { truncate(self.file, size as sqlite3_int64) })
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        let sync = self.methods().xSync.ok_or(ErrorKind::Unsupported)?;
+        check(unsafe { sync(self.file, static_sqlite_ffi::SQLITE_SYNC_NORMAL as c_int) })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        let file_size = self.methods().xFileSize.ok_or(ErrorKind::Unsupported)?;
+        let mut size = 0;
+        check(unsafe { file_size(self.file, &mut size) })?;
+        Ok(size as u64)
+    }
+
+    fn lock(&mut self, level: LockLevel) -> io::Result<()> {
+        let lock = self.methods().xLock.ok_or(ErrorKind::Unsupported)?;
+        check(unsafe { lock(self.file, level as c_int) })
+    }
+
+    fn unlock(&mut self, level: LockLevel) -> io::Result<()> {
+        let unlock = self.methods().xUnlock.ok_or(ErrorKind::Unsupported)?;
+        check(unsafe { unlock(self.file, level as c_int) })
+    }
+
+    fn reserved(&self) -> io::Result<bool> {
+        let check_reserved = self
+            .methods()
+            .xCheckReservedLock
+            .ok_or(ErrorKind::Unsupported)?;
+        let mut reserved = 0;
+        check(unsafe { check_reserved(self.file, &mut reserved) })?;
+        Ok(reserved != 0)
+    }
+
+    fn sector_size(&self) -> i32 {
+        match self.methods().xSectorSize {
+            Some(sector_size) => unsafe { sector_size(self.file) },
+            None => 4096,
+        }
+    }
+
+    fn device_characteristics(&self) -> i32 {
+        match self.methods().xDeviceCharacteristics {
+            Some(device_characteristics) => unsafe { device_characteristics(self.file) },
+            None => 0,
+        }
+    }
+
+    unsafe fn file_control(&mut self, op: i32, arg: *mut c_void) -> i32 {
+        match self.methods().xFileControl {
+            Some(file_control) => file_control(self.file, op, arg),
+            None => SQLITE_NOTFOUND as i32,
+        }
+    }
+
+    fn shm_map(&mut self, region: i32, size: i32, extend: bool) -> io::Result<*mut c_void> {
+        let shm_map = self.shm()?.xShmMap.ok_or(ErrorKind::Unsupported)?;
+        let mut out = std::ptr::null_mut();
+        check(unsafe { shm_map(self.file, region, size, extend as c_int, &mut out) })?;
+        Ok(out)
+    }
+
+    fn shm_lock(&mut self, offset: i32, n: i32, flags: i32) -> io::Result<()> {
+        let shm_lock = self.shm()?.xShmLock.ok_or(ErrorKind::Unsupported)?;
+        check(unsafe { shm_lock(self.file, offset, n, flags) })
+    }
+
+    fn shm_barrier(&mut self) {
+        if let Some(shm_barrier) = self.shm().ok().and_then(|methods| methods.xShmBarrier) {
+            unsafe { shm_barrier(self.file) }
+        }
+    }
+
+    fn shm_unmap(&mut self, delete: bool) -> io::Result<()> {
+        match self.shm().ok().and_then(|methods| methods.xShmUnmap) {
+            Some(shm_unmap) => check(unsafe { shm_unmap(self.file, delete as c_int) }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for DefaultFile {
+    fn drop(&mut self) {
+        unsafe {
+            if !(*self.file).pMethods.is_null() {
+                if let Some(close) = self.methods().xClose {
+                    close(self.file);
+                }
+            }
+            sqlite3_free(self.file as *mut c_void);
+        }
+    }
+}
+
+/// Keeps every file in memory, files are shared by every connection
+/// that opens the same name through the same `MemoryVfs`
+#[derive(Clone, Default)]
+pub struct MemoryVfs {
+    files: Arc<Mutex<HashMap<String, Arc<Mutex<MemoryData>>>>>,
+}
+
+#[derive(Default)]
+struct MemoryData {
+    bytes: Vec<u8>,
+    shared: usize,
+    reserved: bool,
+    pending: bool,
+    exclusive: bool,
+}
+
+pub struct MemoryFile {
+    data: Arc<Mutex<MemoryData>>,
+    level: LockLevel,
+    delete: Option<(String, MemoryVfs)>,
+}
+
+impl MemoryVfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Vfs for MemoryVfs {
+    type File = MemoryFile;
+
+    fn open(&self, path: Option<VfsPath>, flags: OpenFlags) -> io::Result<MemoryFile> {
+        let Some(path) = path else {
+            return Ok(MemoryFile {
+                data: Arc::default(),
+                level: LockLevel::None,
+                delete: None,
+            });
+        };
+        let path = path.as_str().into_owned();
+        let mut files = self.files.lock().unwrap();
+        let data = match files.get(&path) {
+            Some(data) => data.clone(),
+            None if flags.create() => files.entry(path.clone()).or_default().clone(),
+            None => return Err(ErrorKind::NotFound.into()),
+        };
+
+        Ok(MemoryFile {
+            data,
+            level: LockLevel::None,
+            delete: flags.delete_on_close().then(|| (path, self.clone())),
+        })
+    }
+
+    fn delete(&self, path: &str, _sync_dir: bool) -> io::Result<()> {
+        match self.files.lock().unwrap().remove(path) {
+            Some(_) => Ok(()),
+            None => Err(ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn exists(&self, path: &str) -> io::Result<bool> {
+        Ok(self.files.lock().unwrap().contains_key(path))
+    }
+}
+
+impl VfsFile for MemoryFile {
+    fn read(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let data = self.data.lock().unwrap();
+        let start = (offset as usize).min(data.bytes.len());
+        let end = (start + buf.len()).min(data.bytes.len());
+        buf[..end - start].copy_from_slice(&data.bytes[start..end]);
+        Ok(end - start)
+    }
+
+    fn write(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        let end = offset as usize + buf.len();
+        if data.bytes.len() < end {
+            data.bytes.resize(end, 0);
+        }
+        data.bytes[offset as usize..end].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        self.data.lock().unwrap().bytes.truncate(size as usize);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.data.lock().unwrap().bytes.len() as u64)
+    }
+
+    fn lock(&mut self, level: LockLevel) -> io::Result<()> {
+        if self.level >= level {
+            return Ok(());
+        }
+        let mut data = self.data.lock().unwrap();
+        match level {
+            LockLevel::Shared if data.pending || data.exclusive => {
+                return Err(ErrorKind::WouldBlock.into())
+            }
+            LockLevel::Shared => data.shared += 1,
+            LockLevel::Reserved if data.reserved => return Err(ErrorKind::WouldBlock.into()),
+            LockLevel::Reserved => data.reserved = true,
+            LockLevel::Pending | LockLevel::Exclusive => {
+                data.pending = true;
+                self.level = LockLevel::Pending;
+                if data.shared > 1 {
+                    return Err(ErrorKind::WouldBlock.into());
+                }
+                data.exclusive = level == LockLevel::Exclusive;
+            }
+            LockLevel::None => {}
+        }
+        self.level = level;
+        Ok(())
+    }
+
+    fn unlock(&mut self, level: LockLevel) -> io::Result<()> {
+        if self.level <= level {
+            return Ok(());
+        }
+        let mut data = self.data.lock().unwrap();
+        if self.level >= LockLevel::Pending {
+            data.pending = false;
+            data.exclusive = false;
+        }
+        if self.level >= LockLevel::Reserved {
+            data.reserved = false;
+        }
+        if level == LockLevel::None && self.level >= LockLevel::Shared {
+            data.shared -= 1;
+        }
+        self.level = level;
+        Ok(())
+    }
+
+    fn reserved(&self) -> io::Result<bool> {
+        Ok(self.data.lock().unwrap().reserved)
+    }
+}
+
+impl Drop for MemoryFile {
+    fn drop(&mut self) {
+        let _ = self.unlock(LockLevel::None);
+        if let Some((path, vfs)) = self.delete.take() {
+            let _ = vfs.delete(&path, false);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultOp {
+    Read,
+    Write,
+    Sync,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// `SQLITE_IOERR`
+    IoError,
+    /// `ENOSPC`, which sqlite reports as `SQLITE_FULL`
+    DiskFull,
+    /// Writes the first half of the buffer, then fails like `IoError`
+    TornWrite,
+}
+
+impl FaultKind {
+    fn error(self) -> io::Error {
+        match self {
+            FaultKind::DiskFull => io::Error::new(ErrorKind::StorageFull, "injected disk full"),
+            FaultKind::IoError | FaultKind::TornWrite => io::Error::other("injected io error"),
+        }
+    }
+}
+
+/// Which reads, writes and syncs a `FaultVfs` fails, shared by every
+/// clone so tests can change it while connections are open
+#[derive(Clone, Default)]
+pub struct Faults {
+    state: Arc<Mutex<FaultState>>,
+}
+
+#[derive(Default)]
+struct FaultState {
+    counts: [u64; 3],
+    planned: Vec<PlannedFault>,
+}
+
+struct PlannedFault {
+    op: FaultOp,
+    at: u64,
+    kind: FaultKind,
+    sticky: bool,
+}
+
+impl Faults {
+    /// Fails the `n`th `op` from now on, 1 being the next one
+    pub fn fail_nth(&self, op: FaultOp, n: u64, kind: FaultKind) {
+        self.plan(op, n, kind, false)
+    }
+
+    /// Fails the `n`th `op` from now on and every one after it until `clear`
+    pub fn fail_from(&self, op: FaultOp, n: u64, kind: FaultKind) {
+        self.plan(op, n, kind, true)
+    }
+
+    pub fn clear(&self) {
+        self.state.lock().unwrap().planned.clear();
+    }
+
+    /// How many times `op` was called so far
+    pub fn count(&self, op: FaultOp) -> u64 {
+        self.state.lock().unwrap().counts[op as usize]
+    }
+
+    fn plan(&self, op: FaultOp, n: u64, kind: FaultKind, sticky: bool) {
+        let mut state = self.state.lock().unwrap();
+        let at = state.counts[op as usize] + n.max(1);
+        state.planned.push(PlannedFault {
+            op,
+            at,
+            kind,
+            sticky,
+        });
+    }
+
+    fn next(&self, op: FaultOp) -> Option<FaultKind> {
+        let mut state = self.state.lock().unwrap();
+        state.counts[op as usize] += 1;
+        let count = state.counts[op as usize];
+        let i = state.planned.iter().position(|fault| {
+            fault.op == op && (fault.at == count || fault.sticky && fault.at <= count)
+        })?;
+        match state.planned[i].sticky {
+            true => Some(state.planned[i].kind),
+            false => Some(state.planned.remove(i).kind),
+        }
+    }
+}
+
+/// Wraps another vfs and fails the reads, writes and syncs planned in its `Faults`
+pub struct FaultVfs<V: Vfs = DefaultVfs> {
+    inner: V,
+    faults: Faults,
+}
+
+impl<V: Vfs> FaultVfs<V> {
+    pub fn new(inner: V, faults: Faults) -> Self {
+        Self { inner, faults }
+    }
+}
+
+pub struct FaultFile<F> {
+    inner: F,
+    faults: Faults,
+}
+
+impl<V: Vfs> Vfs for FaultVfs<V> {
+    type File = FaultFile<V::File>;
+
+    fn open(&self, path: Option<VfsPath>, flags: OpenFlags) -> io::Result<Self::File> {
+        Ok(FaultFile {
+            inner: self.inner.open(path, flags)?,
+            faults: self.faults.clone(),
+        })
+    }
+
+    fn delete(&self, path: &str, sync_dir: bool) -> io::Result<()> {
+        self.inner.delete(path, sync_dir)
+    }
+
+    fn exists(&self, path: &str) -> io::Result<bool> {
+        self.inner.exists(path)
+    }
+
+    fn full_pathname(&self, path: &str) -> io::Result<String> {
+        self.inner.full_pathname(path)
+    }
+}
+
+impl<F: VfsFile> VfsFile for FaultFile<F> {
+    const SHM: bool = F::SHM;
+
+    fn read(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        match self.faults.next(FaultOp::Read) {
+            Some(kind) => Err(kind.error()),
+            None => self.inner.read(buf, offset),
+        }
+    }
+
+    fn write(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+        match self.faults.next(FaultOp::Write) {
+            Some(FaultKind::TornWrite) => {
+                self.inner.write(&buf[..buf.len() / 2], offset)?;
+                Err(FaultKind::TornWrite.error())
+            }
+            Some(kind) => Err(kind.error()),
+            None => self.inner.write(buf, offset),
+        }
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        self.inner.truncate(size)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        match self.faults.next(FaultOp::Sync) {
+            Some(kind) => Err(kind.error()),
+            None => self.inner.sync(),
+        }
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.inner.size()
+    }
+
+    fn lock(&mut self, level: LockLevel) -> io::Result<()> {
+        self.inner.lock(level)
+    }
+
+    fn unlock(&mut self, level: LockLevel) -> io::Result<()> {
+        self.inner.unlock(level)
+    }
+
+    fn reserved(&self) -> io::Result<bool> {
+        self.inner.reserved()
+    }
+
+    fn sector_size(&self) -> i32 {
+        self.inner.sector_size()
+    }
+
+    fn device_characteristics(&self) -> i32 {
+        self.inner.device_characteristics()
+    }
+
+    unsafe fn file_control(&mut self, op: i32, arg: *mut c_void) -> i32 {
+        self.inner.file_control(op, arg)
+    }
+
+    fn shm_map(&mut self, region: i32, size: i32, extend: bool) -> io::Result<*mut c_void> {
+        self.inner.shm_map(region, size, extend)
+    }
+
+    fn shm_lock(&mut self, offset: i32, n: i32, flags: i32) -> io::Result<()> {
+        self.inner.shm_lock(offset, n, flags)
+    }
+
+    fn shm_barrier(&mut self) {
+        self.inner.shm_barrier()
+    }
+
+    fn shm_unmap(&mut self, delete: bool) -> io::Result<()> {
+        self.inner.shm_unmap(delete)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OpenOptions, Sqlite};
+
+    fn open(path: &str, vfs: &str) -> Result<Sqlite> {
+        Sqlite::open_with(
+            path,
+            &OpenOptions {
+                vfs: Some(vfs.into()),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn memory_vfs_works() -> Result<()> {
+        let vfs = MemoryVfs::new();
+        register_vfs("test_memory", vfs.clone(), false)?;
+
+        let db = open("shared.db", "test_memory")?;
+        db.execute_all("create table Row (id integer primary key)")?;
+        db.execute_all("insert into Row default values")?;
+
+        let other = open("shared.db", "test_memory")?;
+        assert_eq!(other.rows("select id from Row", &[])?.len(), 1);
+        assert!(vfs.exists("shared.db")?);
+        assert!(!vfs.exists("shared.db-journal")?);
+        assert!(!std::path::Path::new("shared.db").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn fault_vfs_works() -> Result<()> {
+        let faults = Faults::default();
+        register_vfs(
+            "test_faults",
+            FaultVfs::new(MemoryVfs::new(), faults.clone()),
+            false,
+        )?;
+        let db = open("faults.db", "test_faults")?;
+        db.execute_all("create table Row (id integer primary key)")?;
+
+        faults.fail_nth(FaultOp::Write, 1, FaultKind::DiskFull);
+        let err = db
+            .execute_all("insert into Row default values")
+            .unwrap_err();
+        assert!(err.to_string().contains("full"), "{err}");
+
+        faults.fail_from(FaultOp::Sync, 1, FaultKind::IoError);
+        let err = db
+            .execute_all("insert into Row default values")
+            .unwrap_err();
+        assert!(err.to_string().contains("I/O"), "{err}");
+
+        faults.clear();
+        db.execute_all("insert into Row default values")?;
+        assert_eq!(db.rows("select id from Row", &[])?.len(), 1);
+        assert!(faults.count(FaultOp::Write) > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn default_vfs_can_be_wrapped() -> Result<()> {
+        let faults = Faults::default();
+        register_vfs(
+            "test_default_faults",
+            FaultVfs::new(DefaultVfs::new()?, faults.clone()),
+            false,
+        )?;
+        let path = std::env::temp_dir().join(format!(
+            "static_sqlite_{}_fault_vfs.sqlite3",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let db = open(path, "test_default_faults")?;
+        db.execute_all("create table Row (id integer primary key)")?;
+        faults.fail_nth(FaultOp::Write, 1, FaultKind::TornWrite);
+        assert!(db.execute_all("insert into Row default values").is_err());
+        db.execute_all("insert into Row default values")?;
+        drop(db);
+
+        let db = Sqlite::open(path)?;
+        assert_eq!(db.integrity_check()?, vec![]);
+        assert_eq!(db.rows("select id from Row", &[])?.len(), 1);
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn wrapped_default_vfs_supports_wal() -> Result<()> {
+        register_vfs(
+            "test_default_wal",
+            FaultVfs::new(DefaultVfs::new()?, Faults::default()),
+            false,
+        )?;
+        let path = std::env::temp_dir().join(format!(
+            "static_sqlite_{}_wal_vfs.sqlite3",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let db = open(path, "test_default_wal")?;
+        let mode = db.query_first::<crate::Row>("pragma journal_mode = wal", &[])?;
+        assert_eq!(mode.unwrap().get::<String>(0)?, "wal");
+        db.execute_all("create table Row (id integer primary key)")?;
+        db.execute_all("insert into Row default values")?;
+        let other = open(path, "test_default_wal")?;
+        assert_eq!(other.rows("select id from Row", &[])?.len(), 1);
+
+        let mut name: *mut c_char = std::ptr::null_mut();
+        let rc = unsafe {
+            static_sqlite_ffi::sqlite3_file_control(
+                db.db,
+                c"main".as_ptr(),
+                static_sqlite_ffi::SQLITE_FCNTL_VFSNAME as c_int,
+                &mut name as *mut _ as *mut c_void,
+            )
+        };
+        assert_eq!(rc, SQLITE_OK as c_int);
+        assert!(!name.is_null(), "the file control was not forwarded");
+        unsafe { sqlite3_free(name as *mut c_void) };
+
+        drop((db, other));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn memory_vfs_ignores_wal() -> Result<()> {
+        register_vfs("test_memory_wal", MemoryVfs::new(), false)?;
+        let db = open("wal.db", "test_memory_wal")?;
+        db.execute_all("create table Row (id integer primary key)")?;
+        let mode = db.query_first::<crate::Row>("pragma journal_mode = wal", &[])?;
+        assert_eq!(mode.unwrap().get::<String>(0)?, "delete");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,840 @@
+use static_sqlite_ffi::{
+    sqlite3, sqlite3_context, sqlite3_create_module_v2, sqlite3_declare_vtab, sqlite3_free,
+    sqlite3_index_info, sqlite3_int64, sqlite3_malloc64, sqlite3_module, sqlite3_result_blob64,
+    sqlite3_result_double, sqlite3_result_int64, sqlite3_result_null, sqlite3_result_pointer,
+    sqlite3_result_text64, sqlite3_value, sqlite3_value_blob, sqlite3_value_bytes,
+    sqlite3_value_double, sqlite3_value_int64, sqlite3_value_text, sqlite3_value_type,
+    sqlite3_vtab, sqlite3_vtab_cursor, SQLITE_ERROR, SQLITE_OK,
+};
+
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    ops::Range,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Arc,
+};
+
+use crate::{Error, Result, Sqlite, Value};
+
+/// A virtual table implemented in Rust.
+///
+/// `connect` returns the `create table` statement sqlite should see
+/// along with the table itself, `filter` returns the rows of one scan
+pub trait VTab: Sized + Send + 'static {
+    /// Shared with every table of the module, passed to `create_module`
+    type Aux: Send + 'static;
+    type Rows: Iterator<Item = Result<VTabRow>> + 'static;
+
+    /// `args` are the module name, the database name, the table name
+    /// and then the arguments of `create virtual table .. using module(..)`
+    fn connect(aux: &Self::Aux, args: &[String]) -> Result<(String, Self)>;
+
+    /// Called instead of `connect` by `create virtual table`
+    fn create(aux: &Self::Aux, args: &[String]) -> Result<(String, Self)> {
+        Self::connect(aux, args)
+    }
+
+    fn best_index(&self, _info: &mut IndexInfo) -> Result<()> {
+        Ok(())
+    }
+
+    /// `idx_num` and `idx_str` are whatever `best_index` chose, `args`
+    /// are the constraint values in the order of their `argv_index`
+    fn filter(&self, idx_num: i32, idx_str: Option<&str>, args: &[Value]) -> Result<Self::Rows>;
+
+    /// Returns the rowid of an inserted row
+    fn update(&mut self, _update: Update) -> Result<Option<i64>> {
+        Err(Error::Sqlite("virtual table is read only".into()))
+    }
+
+    /// Called by `drop table`
+    fn destroy(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VTabRow {
+    pub rowid: i64,
+    pub values: Vec<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Update {
+    Delete {
+        rowid: i64,
+    },
+    Insert {
+        rowid: Option<i64>,
+        values: Vec<Value>,
+    },
+    Update {
+        old_rowid: i64,
+        new_rowid: i64,
+        values: Vec<Value>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstraintOp {
+    Eq,
+    Gt,
+    Le,
+    Lt,
+    Ge,
+    Match,
+    Like,
+    Glob,
+    Regexp,
+    Ne,
+    IsNot,
+    IsNotNull,
+    IsNull,
+    Is,
+    Limit,
+    Offset,
+    Function(u8),
+}
+
+impl ConstraintOp {
+    fn from_raw(op: u8) -> Self {
+        match op as u32 {
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_EQ => ConstraintOp::Eq,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_GT => ConstraintOp::Gt,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_LE => ConstraintOp::Le,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_LT => ConstraintOp::Lt,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_GE => ConstraintOp::Ge,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_MATCH => ConstraintOp::Match,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_LIKE => ConstraintOp::Like,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_GLOB => ConstraintOp::Glob,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_REGEXP => ConstraintOp::Regexp,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_NE => ConstraintOp::Ne,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_ISNOT => ConstraintOp::IsNot,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_ISNOTNULL => ConstraintOp::IsNotNull,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_ISNULL => ConstraintOp::IsNull,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_IS => ConstraintOp::Is,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_LIMIT => ConstraintOp::Limit,
+            static_sqlite_ffi::SQLITE_INDEX_CONSTRAINT_OFFSET => ConstraintOp::Offset,
+            _ => ConstraintOp::Function(op),
+        }
+    }
+}
+
+/// A `where` term sqlite offers to `best_index`, `column` is -1 for the rowid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexConstraint {
+    pub column: i32,
+    pub op: ConstraintOp,
+    pub usable: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderBy {
+    pub column: i32,
+    pub desc: bool,
+}
+
+/// The `sqlite3_index_info` of one `best_index` call
+pub struct IndexInfo<'a> {
+    raw: &'a mut sqlite3_index_info,
+}
+
+impl IndexInfo<'_> {
+    pub fn constraints(&self) -> Vec<IndexConstraint> {
+        (0..self.raw.nConstraint as usize)
+            .map(|i| {
+                let constraint = unsafe { *self.raw.aConstraint.add(i) };
+                IndexConstraint {
+                    column: constraint.iColumn,
+                    op: ConstraintOp::from_raw(constraint.op),
+                    usable: constraint.usable != 0,
+                }
+            })
+            .collect()
+    }
+
+    pub fn order_by(&self) -> Vec<OrderBy> {
+        (0..self.raw.nOrderBy as usize)
+            .map(|i| {
+                let order_by = unsafe { *self.raw.aOrderBy.add(i) };
+                OrderBy {
+                    column: order_by.iColumn,
+                    desc: order_by.desc != 0,
+                }
+            })
+            .collect()
+    }
+
+    /// Passes the value of constraint `index` to `filter` as `args[argv_index - 1]`.
+    /// With `omit` sqlite trusts the table to enforce the constraint itself
+    pub fn use_constraint(&mut self, index: usize, argv_index: i32, omit: bool) {
+        assert!(index < self.raw.nConstraint as usize);
+        let usage = unsafe { &mut *self.raw.aConstraintUsage.add(index) };
+        usage.argvIndex = argv_index;
+        usage.omit = omit as u8;
+    }
+
+    pub fn set_idx_num(&mut self, idx_num: i32) {
+        self.raw.idxNum = idx_num;
+    }
+
+    pub fn set_idx_str(&mut self, idx_str: &str) {
+        unsafe {
+            if self.raw.needToFreeIdxStr != 0 {
+                sqlite3_free(self.raw.idxStr as *mut c_void);
+            }
+            self.raw.idxStr = sqlite_string(idx_str);
+            self.raw.needToFreeIdxStr = 1;
+        }
+    }
+
+    /// Tells sqlite the rows already come out in `order_by` order
+    pub fn set_order_by_consumed(&mut self, consumed: bool) {
+        self.raw.orderByConsumed = consumed as c_int;
+    }
+
+    pub fn set_estimated_cost(&mut self, cost: f64) {
+        self.raw.estimatedCost = cost;
+    }
+
+    pub fn set_estimated_rows(&mut self, rows: i64) {
+        self.raw.estimatedRows = rows;
+    }
+
+    /// Tells sqlite the scan returns at most one row
+    pub fn set_unique(&mut self, unique: bool) {
+        match unique {
+            true => self.raw.idxFlags |= static_sqlite_ffi::SQLITE_INDEX_SCAN_UNIQUE as c_int,
+            false => self.raw.idxFlags &= !(static_sqlite_ffi::SQLITE_INDEX_SCAN_UNIQUE as c_int),
+        }
+    }
+}
+
+impl Sqlite {
+    /// Registers a module for `create virtual table .. using name(..)`
+    pub fn create_module<T: VTab>(&self, name: &str, aux: T::Aux) -> Result<()> {
+        self.register_module::<T>(name, aux, false)
+    }
+
+    /// Registers a module that is only usable as an eponymous table,
+    /// `select * from name` or `select * from name(..)` for table valued functions
+    pub fn create_eponymous_module<T: VTab>(&self, name: &str, aux: T::Aux) -> Result<()> {
+        self.register_module::<T>(name, aux, true)
+    }
+
+    /// Exposes `values` as the eponymous table `name(value)`,
+    /// the rowid of each value is its index in `values`
+    pub fn create_vec_module(&self, name: &str, values: Vec<Value>) -> Result<()> {
+        self.create_eponymous_module::<VecTab>(name, Arc::new(values))
+    }
+
+    fn register_module<T: VTab>(&self, name: &str, aux: T::Aux, eponymous: bool) -> Result<()> {
+        let name = CString::new(name)?;
+        let data = Box::into_raw(Box::new(ModuleData::<T> {
+            module: module::<T>(eponymous),
+            aux,
+        }));
+        let rc = unsafe {
+            sqlite3_create_module_v2(
+                self.db,
+                name.as_ptr(),
+                &(*data).module,
+                data as *mut c_void,
+                Some(drop_module::<T>),
+            )
+        };
+
+        match rc == SQLITE_OK as c_int {
+            true => Ok(()),
+            false => Err(self.last_error()),
+        }
+    }
+}
+
+struct ModuleData<T: VTab> {
+    module: sqlite3_module,
+    aux: T::Aux,
+}
+
+#[repr(C)]
+struct Table<T> {
+    base: sqlite3_vtab,
+    table: T,
+}
+
+#[repr(C)]
+struct Cursor<T: VTab> {
+    base: sqlite3_vtab_cursor,
+    rows: Option<T::Rows>,
+    current: Option<VTabRow>,
+}
+
+fn module<T: VTab>(eponymous: bool) -> sqlite3_module {
+    sqlite3_module {
+        iVersion: 1,
+        xCreate: match eponymous {
+            true => None,
+            false => Some(x_create::<T>),
+        },
+        xConnect: Some(x_connect::<T>),
+        xBestIndex: Some(x_best_index::<T>),
+        xDisconnect: Some(x_disconnect::<T>),
+        xDestroy: Some(x_destroy::<T>),
+        xOpen: Some(x_open::<T>),
+        xClose: Some(x_close::<T>),
+        xFilter: Some(x_filter::<T>),
+        xNext: Some(x_next::<T>),
+        xEof: Some(x_eof::<T>),
+        xColumn: Some(x_column::<T>),
+        xRowid: Some(x_rowid::<T>),
+        xUpdate: Some(x_update::<T>),
+        xBegin: None,
+        xSync: None,
+        xCommit: None,
+        xRollback: None,
+        xFindFunction: None,
+        xRename: None,
+        xSavepoint: None,
+        xRelease: None,
+        xRollbackTo: None,
+        xShadowName: None,
+        xIntegrity: None,
+    }
+}
+
+/// Runs `f`, turning a panic into an error so it never unwinds into sqlite
+fn guard<R>(f: impl FnOnce() -> Result<R>) -> Result<R> {
+    catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(Error::Sqlite("virtual table panicked".into())))
+}
+
+/// Copies `s` into memory sqlite is allowed to free
+unsafe fn sqlite_string(s: &str) -> *mut c_char {
+    let s = s.replace('\0', "");
+    let ptr = sqlite3_malloc64(s.len() as u64 + 1) as *mut c_char;
+    if !ptr.is_null() {
+        std::ptr::copy_nonoverlapping(s.as_ptr() as *const c_char, ptr, s.len());
+        *ptr.add(s.len()) = 0;
+    }
+    ptr
+}
+
+unsafe fn set_error(vtab: *mut sqlite3_vtab, error: Error) -> c_int {
+    sqlite3_free((*vtab).zErrMsg as *mut c_void);
+    (*vtab).zErrMsg = sqlite_string(&error.to_string());
+    SQLITE_ERROR as c_int
+}
+
+unsafe fn value_from_raw(value: *mut sqlite3_value) -> Result<Value> {
+    match sqlite3_value_type(value) as u32 {
+        static_sqlite_ffi::SQLITE_INTEGER => Ok(Value::Integer(sqlite3_value_int64(value))),
+        static_sqlite_ffi::SQLITE_FLOAT => Ok(Value::Real(sqlite3_value_double(value))),
+        static_sqlite_ffi::SQLITE_TEXT => {
+            let ptr = sqlite3_value_text(value);
+            let len = sqlite3_value_bytes(value) as usize;
+            match ptr.is_null() {
+                true => Ok(Value::Text(String::new())),
+                false => Ok(Value::Text(
+                    std::str::from_utf8(std::slice::from_raw_parts(ptr, len))?.to_owned(),
+                )),
+            }
+        }
+        static_sqlite_ffi::SQLITE_BLOB => {
+            let ptr = sqlite3_value_blob(value) as *const u8;
+            let len = sqlite3_value_bytes(value) as usize;
+            match ptr.is_null() {
+                true => Ok(Value::Blob(vec![])),
+                false => Ok(Value::Blob(std::slice::from_raw_parts(ptr, len).to_vec())),
+            }
+        }
+        _ => match crate::array::array_from_raw(value) {
+            Some(values) => Ok(Value::Array(values)),
+            None => Ok(Value::Null),
+        },
+    }
+}
+
+unsafe fn values_from_raw(argc: c_int, argv: *mut *mut sqlite3_value) -> Result<Vec<Value>> {
+    (0..argc as usize)
+        .map(|i| value_from_raw(*argv.add(i)))
+        .collect()
+}
+
+unsafe fn set_result(ctx: *mut sqlite3_context, value: &Value) {
+    let transient = std::mem::transmute::<isize, unsafe extern "C" fn(*mut c_void)>(-1);
+    match value {
+        Value::Text(s) => sqlite3_result_text64(
+            ctx,
+            s.as_ptr() as *const c_char,
+            s.len() as u64,
+            Some(transient),
+            static_sqlite_ffi::SQLITE_UTF8 as u8,
+        ),
+        Value::Integer(n) => sqlite3_result_int64(ctx, *n),
+        Value::Real(f) => sqlite3_result_double(ctx, *f),
+        Value::Blob(b) => sqlite3_result_blob64(
+            ctx,
+            b.as_ptr() as *const c_void,
+            b.len() as u64,
+            Some(transient),
+        ),
+        Value::Null => sqlite3_result_null(ctx),
+        Value::Array(values) => sqlite3_result_pointer(
+            ctx,
+            Arc::into_raw(values.clone()) as *mut c_void,
+            crate::array::POINTER_TYPE.as_ptr(),
+            Some(crate::array::drop_array),
+        ),
+    }
+}
+
+unsafe extern "C" fn drop_module<T: VTab>(data: *mut c_void) {
+    drop(Box::from_raw(data as *mut ModuleData<T>));
+}
+
+unsafe extern "C" fn x_create<T: VTab>(
+    db: *mut sqlite3,
+    aux: *mut c_void,
+    argc: c_int,
+    argv: *const *const c_char,
+    vtab: *mut *mut sqlite3_vtab,
+    err: *mut *mut c_char,
+) -> c_int {
+    init::<T>(db, aux, argc, argv, vtab, err, T::create)
+}
+
+unsafe extern "C" fn x_connect<T: VTab>(
+    db: *mut sqlite3,
+    aux: *mut c_void,
+    argc: c_int,
+    argv: *const *const c_char,
+    vtab: *mut *mut sqlite3_vtab,
+    err: *mut *mut c_char,
+) -> c_int {
+    init::<T>(db, aux, argc, argv, vtab, err, T::connect)
+}
+
+type Init<T> = fn(&<T as VTab>::Aux, &[String]) -> Result<(String, T)>;
+
+unsafe fn init<T: VTab>(
+    db: *mut sqlite3,
+    aux: *mut c_void,
+    argc: c_int,
+    argv: *const *const c_char,
+    vtab: *mut *mut sqlite3_vtab,
+    err: *mut *mut c_char,
+    f: Init<T>,
+) -> c_int {
+    let result = guard(|| {
+        let data = &*(aux as *const ModuleData<T>);
+        let args = (0..argc as usize)
+            .map(|i| Ok(CStr::from_ptr(*argv.add(i)).to_str()?.to_owned()))
+            .collect::<Result<Vec<_>>>()?;
+        let (sql, table) = f(&data.aux, &args)?;
+        let sql = CString::new(sql)?;
+        if sqlite3_declare_vtab(db, sql.as_ptr()) != SQLITE_OK as c_int {
+            let error = CStr::from_ptr(static_sqlite_ffi::sqlite3_errmsg(db))
+                .to_string_lossy()
+                .into_owned();
+            return Err(Error::Sqlite(error));
+        }
+        Ok(table)
+    });
+
+    match result {
+        Ok(table) => {
+            let table = Box::new(Table {
+                base: std::mem::zeroed(),
+                table,
+            });
+            *vtab = Box::into_raw(table) as *mut sqlite3_vtab;
+            SQLITE_OK as c_int
+        }
+        Err(error) => {
+            *err = sqlite_string(&error.to_string());
+            SQLITE_ERROR as c_int
+        }
+    }
+}
+
+unsafe extern "C" fn x_best_index<T: VTab>(
+    vtab: *mut sqlite3_vtab,
+    info: *mut sqlite3_index_info,
+) -> c_int {
+    let table = &*(vtab as *const Table<T>);
+    let mut info = IndexInfo { raw: &mut *info };
+    match guard(|| table.table.best_index(&mut info)) {
+        Ok(()) => SQLITE_OK as c_int,
+        Err(error) => set_error(vtab, error),
+    }
+}
+
+unsafe extern "C" fn x_disconnect<T: VTab>(vtab: *mut sqlite3_vtab) -> c_int {
+    drop(Box::from_raw(vtab as *mut Table<T>));
+    SQLITE_OK as c_int
+}
+
+unsafe extern "C" fn x_destroy<T: VTab>(vtab: *mut sqlite3_vtab) -> c_int {
+    let table = &mut *(vtab as *mut Table<T>);
+    match guard(|| table.table.destroy()) {
+        Ok(()) => x_disconnect::<T>(vtab),
+        Err(error) => set_error(vtab, error),
+    }
+}
+
+unsafe extern "C" fn x_open<T: VTab>(
+    _vtab: *mut sqlite3_vtab,
+    cursor: *mut *mut sqlite3_vtab_cursor,
+) -> c_int {
+    let boxed = Box::new(Cursor::<T> {
+        base: std::mem::zeroed(),
+        rows: None,
+        current: None,
+    });
+    *cursor = Box::into_raw(boxed) as *mut sqlite3_vtab_cursor;
+    SQLITE_OK as c_int
+}
+
+unsafe extern "C" fn x_close<T: VTab>(cursor: *mut sqlite3_vtab_cursor) -> c_int {
+    drop(Box::from_raw(cursor as *mut Cursor<T>));
+    SQLITE_OK as c_int
+}
+
+unsafe extern "C" fn x_filter<T: VTab>(
+    cursor: *mut sqlite3_vtab_cursor,
+    idx_num: c_int,
+    idx_str: *const c_char,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) -> c_int {
+    let vtab = (*cursor).pVtab;
+    let table = &*(vtab as *const Table<T>);
+    let cursor = &mut *(cursor as *mut Cursor<T>);
+    let result = guard(|| {
+        let idx_str = match idx_str.is_null() {
+            true => None,
+            false => Some(CStr::from_ptr(idx_str).to_str()?),
+        };
+        let args = values_from_raw(argc, argv)?;
+        table.table.filter(idx_num, idx_str, &args)
+    });
+
+    match result {
+        Ok(rows) => {
+            cursor.rows = Some(rows);
+            cursor.current = None;
+            x_next::<T>(cursor as *mut Cursor<T> as *mut sqlite3_vtab_cursor)
+        }
+        Err(error) => set_error(vtab, error),
+    }
+}
+
+unsafe extern "C" fn x_next<T: VTab>(cursor: *mut sqlite3_vtab_cursor) -> c_int {
+    let vtab = (*cursor).pVtab;
+    let cursor = &mut *(cursor as *mut Cursor<T>);
+    let result = guard(|| {
+        cursor
+            .rows
+            .as_mut()
+            .and_then(|rows| rows.next())
+            .transpose()
+    });
+
+    match result {
+        Ok(row) => {
+            cursor.current = row;
+            SQLITE_OK as c_int
+        }
+        Err(error) => {
+            cursor.current = None;
+            set_error(vtab, error)
+        }
+    }
+}
+
+unsafe extern "C" fn x_eof<T: VTab>(cursor: *mut sqlite3_vtab_cursor) -> c_int {
+    let cursor = &*(cursor as *const Cursor<T>);
+    cursor.current.is_none() as c_int
+}
+
+unsafe extern "C" fn x_column<T: VTab>(
+    cursor: *mut sqlite3_vtab_cursor,
+    ctx: *mut sqlite3_context,
+    i: c_int,
+) -> c_int {
+    let cursor = &*(cursor as *const Cursor<T>);
+    match cursor
+        .current
+        .as_ref()
+        .and_then(|row| row.values.get(i as usize))
+    {
+        Some(value) => set_result(ctx, value),
+        None => sqlite3_result_null(ctx),
+    }
+    SQLITE_OK as c_int
+}
+
+unsafe extern "C" fn x_rowid<T: VTab>(
+    cursor: *mut sqlite3_vtab_cursor,
+    rowid: *mut sqlite3_int64,
+) -> c_int {
+    let cursor = &*(cursor as *const Cursor<T>);
+    *rowid = cursor.current.as_ref().map(|row| row.rowid).unwrap_or(0);
+    SQLITE_OK as c_int
+}
+
+unsafe extern "C" fn x_update<T: VTab>(
+    vtab: *mut sqlite3_vtab,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+    rowid: *mut sqlite3_int64,
+) -> c_int {
+    let table = &mut *(vtab as *mut Table<T>);
+    let result = guard(|| {
+        let mut args = values_from_raw(argc, argv)?.into_iter();
+        let first = args.next().unwrap_or(Value::Null);
+        let update = match (argc, first) {
+            (1, first) => Update::Delete {
+                rowid: first.try_into()?,
+            },
+            (_, Value::Null) => Update::Insert {
+                rowid: args.next().unwrap_or(Value::Null).try_into()?,
+                values: args.collect(),
+            },
+            (_, first) => Update::Update {
+                old_rowid: first.try_into()?,
+                new_rowid: args.next().unwrap_or(Value::Null).try_into()?,
+                values: args.collect(),
+            },
+        };
+        table.table.update(update)
+    });
+
+    match result {
+        Ok(inserted) => {
+            if let Some(inserted) = inserted {
+                *rowid = inserted;
+            }
+            SQLITE_OK as c_int
+        }
+        Err(error) => set_error(vtab, error),
+    }
+}
+
+/// The eponymous table behind `create_vec_module`
+pub struct VecTab {
+    values: Arc<Vec<Value>>,
+}
+
+pub struct VecRows {
+    values: Arc<Vec<Value>>,
+    range: Range<usize>,
+    desc: bool,
+}
+
+impl Iterator for VecRows {
+    type Item = Result<VTabRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let i = match self.desc {
+            true => self.range.next_back()?,
+            false => self.range.next()?,
+        };
+        Some(Ok(VTabRow {
+            rowid: i as i64,
+            values: vec![self.values[i].clone()],
+        }))
+    }
+}
+
+impl VTab for VecTab {
+    type Aux = Arc<Vec<Value>>;
+    type Rows = VecRows;
+
+    fn connect(aux: &Self::Aux, _args: &[String]) -> Result<(String, Self)> {
+        Ok((
+            "create table x(value)".into(),
+            VecTab {
+                values: aux.clone(),
+            },
+        ))
+    }
+
+    fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+        // rowid bounds narrow the scanned range, sqlite still checks
+        // them on every row so a loose bound is never wrong
+        let mut ops = vec![];
+        for (i, constraint) in info.constraints().into_iter().enumerate() {
+            let op = match constraint.op {
+                ConstraintOp::Eq => "eq",
+                ConstraintOp::Gt => "gt",
+                ConstraintOp::Ge => "ge",
+                ConstraintOp::Lt => "lt",
+                ConstraintOp::Le => "le",
+                _ => continue,
+            };
+            if constraint.column == -1 && constraint.usable {
+                ops.push(op);
+                info.use_constraint(i, ops.len() as i32, false);
+            }
+        }
+        info.set_idx_str(&ops.join(","));
+
+        if let [order_by] = info.order_by().as_slice() {
+            if order_by.column == -1 {
+                info.set_order_by_consumed(true);
+                info.set_idx_num(order_by.desc as i32);
+            }
+        }
+
+        let len = self.values.len() as f64;
+        match ops.as_slice() {
+            [] => info.set_estimated_cost(len),
+            ops if ops.contains(&"eq") => {
+                info.set_estimated_cost(1.0);
+                info.set_estimated_rows(1);
+                info.set_unique(true);
+            }
+            _ => info.set_estimated_cost(len / 4.0),
+        }
+
+        Ok(())
+    }
+
+    fn filter(&self, idx_num: i32, idx_str: Option<&str>, args: &[Value]) -> Result<VecRows> {
+        let len = self.values.len() as i64;
+        let (mut start, mut end) = (0, len);
+        let ops = idx_str
+            .unwrap_or_default()
+            .split(',')
+            .filter(|op| !op.is_empty());
+        for (op, arg) in ops.zip(args) {
+            let (low, high) = match arg {
+                Value::Integer(n) => (*n, *n),
+                Value::Real(f) => (f.floor() as i64, f.ceil() as i64),
+                _ => continue,
+            };
+            match op {
+                "eq" => {
+                    start = start.max(low);
+                    end = end.min(high.saturating_add(1));
+                }
+                "gt" | "ge" => start = start.max(low),
+                "lt" | "le" => end = end.min(high.saturating_add(1)),
+                _ => {}
+            }
+        }
+        let start = start.clamp(0, len) as usize;
+        let end = end.clamp(0, len) as usize;
+
+        Ok(VecRows {
+            values: self.values.clone(),
+            range: start..end.max(start),
+            desc: idx_num == 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn vec_module_works() -> Result<()> {
+        let db = Sqlite::open(":memory:")?;
+        db.create_vec_module("names", vec!["a".into(), "b".into(), "c".into()])?;
+        db.execute_all("create table Row (id integer primary key, name text)")?;
+        db.execute("insert into Row (name) values (?)", vec!["b".into()])?;
+
+        let rows = db.rows("select rowid, value from names", &[])?;
+        assert_eq!(rows.len(), 3);
+
+        let rows = db.rows(
+            "select value from names where rowid >= 1 and rowid < 3 order by rowid desc",
+            &[],
+        )?;
+        let values: Vec<String> = rows
+            .into_iter()
+            .map(|row| row[0].1.clone().try_into())
+            .collect::<Result<_>>()?;
+        assert_eq!(values, vec!["c", "b"]);
+
+        let rows = db.rows(
+            "select Row.id from Row join names on names.value = Row.name",
+            &[],
+        )?;
+        assert_eq!(rows.len(), 1);
+
+        assert!(db.execute_all("delete from names").is_err());
+
+        Ok(())
+    }
+
+    type Store = Arc<Mutex<Vec<(i64, String)>>>;
+
+    struct Log {
+        store: Store,
+    }
+
+    impl VTab for Log {
+        type Aux = Store;
+        type Rows = std::vec::IntoIter<Result<VTabRow>>;
+
+        fn connect(aux: &Store, args: &[String]) -> Result<(String, Self)> {
+            assert_eq!(args[3], "message");
+            Ok((
+                "create table x(message text)".into(),
+                Log { store: aux.clone() },
+            ))
+        }
+
+        fn filter(&self, _: i32, _: Option<&str>, _: &[Value]) -> Result<Self::Rows> {
+            let store = self.store.lock().unwrap();
+            let rows: Vec<_> = store
+                .iter()
+                .map(|(rowid, message)| {
+                    Ok(VTabRow {
+                        rowid: *rowid,
+                        values: vec![message.as_str().into()],
+                    })
+                })
+                .collect();
+            Ok(rows.into_iter())
+        }
+
+        fn update(&mut self, update: Update) -> Result<Option<i64>> {
+            let mut store = self.store.lock().unwrap();
+            match update {
+                Update::Insert { rowid, values } => {
+                    let rowid = rowid.unwrap_or(store.len() as i64 + 1);
+                    let message = values.into_iter().next().unwrap_or(Value::Null);
+                    store.push((rowid, message.try_into()?));
+                    Ok(Some(rowid))
+                }
+                Update::Delete { rowid } => {
+                    store.retain(|(id, _)| *id != rowid);
+                    Ok(None)
+                }
+                Update::Update { .. } => Err(Error::Sqlite("logs are append only".into())),
+            }
+        }
+    }
+
+    #[test]
+    fn writable_module_works() -> Result<()> {
+        let store = Store::default();
+        let db = Sqlite::open(":memory:")?;
+        db.create_module::<Log>("log", store.clone())?;
+        db.execute_all("create virtual table logs using log(message)")?;
+        db.execute("insert into logs (message) values (?)", vec!["one".into()])?;
+        db.execute("insert into logs (message) values (?)", vec!["two".into()])?;
+        db.execute_all("delete from logs where rowid = 1")?;
+        assert_eq!(*store.lock().unwrap(), vec![(2, "two".to_string())]);
+        assert!(db.execute_all("update logs set message = 'three'").is_err());
+        assert_eq!(db.rows("select message from logs", &[])?.len(), 1);
+        assert!(db.rows("select * from log", &[]).is_err());
+
+        Ok(())
+    }
+}
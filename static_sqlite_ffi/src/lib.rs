@@ -1,3 +1,4 @@
+#![allow(clippy::type_complexity)]
 #![allow(non_upper_case_globals)]
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
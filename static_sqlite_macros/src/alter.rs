@@ -0,0 +1,251 @@
+use sqlparser::{
+    ast::{
+        AlterColumnOperation, AlterTableOperation, ColumnOption, ColumnOptionDef, CreateTable,
+        Ident, Statement,
+    },
+    dialect::SQLiteDialect,
+    parser::Parser,
+};
+use static_sqlite_core::{self as sqlite, Row, Sqlite, Value};
+
+type Result<T> = std::result::Result<T, String>;
+
+/// The columns an `alter table` statement alters, with what it does to each
+type AlterColumns = Vec<(Ident, AlterColumnOperation)>;
+
+/// Runs `sql` on `db`, rebuilding the table of every `alter table .. alter column ..`
+/// statement since sqlite can not alter a column in place. Returns the sql
+/// that ran when it rebuilt a table, the rebuilds in place of those statements
+pub fn run(db: &Sqlite, sql: &str) -> Result<Option<String>> {
+    let mut ran = vec![];
+    let mut rebuilt = false;
+    for statement in sqlite::split_statements(sql) {
+        let statement = match alter_columns(&statement)? {
+            Some((table, columns)) => {
+                rebuilt = true;
+                rebuild(db, &table, &columns)?
+            }
+            None => statement,
+        };
+        db.execute_batch(&statement)
+            .map_err(|err| err.to_string())?;
+        ran.push(match statement.trim_end().ends_with(';') {
+            true => statement,
+            false => format!("{statement};"),
+        });
+    }
+
+    Ok(rebuilt.then(|| ran.join("\n")))
+}
+
+/// The table and the column changes of an `alter column` statement
+fn alter_columns(statement: &str) -> Result<Option<(String, AlterColumns)>> {
+    let Ok(mut statements) = Parser::parse_sql(&SQLiteDialect {}, statement) else {
+        return Ok(None);
+    };
+    let Some(Statement::AlterTable {
+        name, operations, ..
+    }) = statements.pop()
+    else {
+        return Ok(None);
+    };
+    let mut columns = vec![];
+    for operation in &operations {
+        match operation {
+            AlterTableOperation::AlterColumn { column_name, op } => {
+                columns.push((column_name.clone(), op.clone()))
+            }
+            _ if columns.is_empty() => return Ok(None),
+            _ => return Err("alter column can not be combined with other changes".into()),
+        }
+    }
+    let table = name.0.last().map(|ident| ident.value.clone());
+
+    Ok(table.map(|table| (table, columns)))
+}
+
+/// The 12 step rebuild of https://www.sqlite.org/lang_altertable.html#otheralter,
+/// with the old table renamed out of the way instead of the new one. It needs
+/// foreign keys off, dropping the old table would cascade otherwise, the
+/// migrations turn them off and check them afterwards
+fn rebuild(db: &Sqlite, table: &str, columns: &AlterColumns) -> Result<String> {
+    let sql = |sql: &str, table: &str| -> Result<Vec<String>> {
+        let rows = db
+            .query::<Row>(sql, &[Value::Text(table.to_string())])
+            .map_err(|err| err.to_string())?;
+        rows.iter()
+            .map(|row| row.get::<String>(0).map_err(|err| err.to_string()))
+            .collect()
+    };
+    let create = sql(
+        "select sql from sqlite_master where type = 'table' and name = ? collate nocase",
+        table,
+    )?
+    .pop()
+    .ok_or_else(|| format!("no such table: {table}"))?;
+    let Ok(Some(Statement::CreateTable(mut create_table))) =
+        Parser::parse_sql(&SQLiteDialect {}, &create).map(|mut statements| statements.pop())
+    else {
+        return Err(format!("can not rebuild {table}, its sql does not parse"));
+    };
+    let mut copies = create_table
+        .columns
+        .iter()
+        .filter(|column| {
+            !column
+                .options
+                .iter()
+                .any(|option| matches!(option.option, ColumnOption::Generated { .. }))
+        })
+        .map(|column| (column.name.value.clone(), quote(&column.name.value)))
+        .collect::<Vec<_>>();
+    for (column, op) in columns {
+        alter_column(&mut create_table, &mut copies, column, op)?;
+    }
+    let dependents = sql(
+        "select sql from sqlite_master where type in ('index', 'trigger') and tbl_name = ? collate nocase and sql is not null order by type, rowid",
+        table,
+    )?;
+
+    let name = &create_table.name.0.last().unwrap().value;
+    let old = quote(&format!("_static_sqlite_old_{name}"));
+    let (names, values): (Vec<_>, Vec<_>) = copies
+        .into_iter()
+        .map(|(name, value)| (quote(&name), value))
+        .unzip();
+    let mut statements = vec![
+        "create temp table _static_sqlite_rebuild (foreign_keys constraint rebuild_needs_foreign_keys_off check (foreign_keys is null))".to_string(),
+        "insert into temp._static_sqlite_rebuild select foreign_keys from pragma_foreign_keys where foreign_keys".to_string(),
+        "drop table temp._static_sqlite_rebuild".to_string(),
+        "pragma legacy_alter_table = on".to_string(),
+        format!("alter table {} rename to {old}", quote(name)),
+        Statement::CreateTable(create_table.clone()).to_string(),
+        format!(
+            "insert into {} ({}) select {} from {old}",
+            quote(name),
+            names.join(", "),
+            values.join(", ")
+        ),
+        format!("drop table {old}"),
+    ];
+    statements.extend(dependents);
+    statements.push("pragma legacy_alter_table = off".to_string());
+
+    Ok(statements
+        .into_iter()
+        .map(|statement| format!("{statement};"))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn alter_column(
+    create_table: &mut CreateTable,
+    copies: &mut [(String, String)],
+    column: &Ident,
+    op: &AlterColumnOperation,
+) -> Result<()> {
+    let table = &create_table.name;
+    let def = create_table
+        .columns
+        .iter_mut()
+        .find(|def| def.name.value.eq_ignore_ascii_case(&column.value))
+        .ok_or_else(|| format!("no such column: {table}.{column}"))?;
+    let option = |option| ColumnOptionDef { name: None, option };
+    match op {
+        AlterColumnOperation::SetNotNull => {
+            def.options
+                .retain(|o| !matches!(o.option, ColumnOption::Null | ColumnOption::NotNull));
+            def.options.push(option(ColumnOption::NotNull));
+        }
+        AlterColumnOperation::DropNotNull => {
+            def.options
+                .retain(|o| !matches!(o.option, ColumnOption::NotNull));
+        }
+        AlterColumnOperation::SetDefault { value } => {
+            def.options
+                .retain(|o| !matches!(o.option, ColumnOption::Default(_)));
+            def.options
+                .push(option(ColumnOption::Default(value.clone())));
+        }
+        AlterColumnOperation::DropDefault => {
+            def.options
+                .retain(|o| !matches!(o.option, ColumnOption::Default(_)));
+        }
+        AlterColumnOperation::SetDataType { data_type, using } => {
+            def.data_type = data_type.clone();
+            if let (Some(using), Some(copy)) = (
+                using,
+                copies
+                    .iter_mut()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(&column.value)),
+            ) {
+                copy.1 = using.to_string();
+            }
+        }
+        AlterColumnOperation::AddGenerated { .. } => {
+            return Err("sqlite has no identity columns".into());
+        }
+    }
+
+    Ok(())
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebuilds_altered_tables() {
+        let db = sqlite::open(":memory:").unwrap();
+        db.execute_batch(
+            "pragma foreign_keys = on;
+             create table Author (id integer primary key autoincrement, name text);
+             create table Book (id integer primary key, author_id integer references Author(id) on delete cascade);
+             create index author_name on Author (name);
+             insert into Author (name) values ('a');
+             insert into Book (author_id) values (1);",
+        )
+        .unwrap();
+
+        assert_eq!(
+            run(&db, "create table Tag (id integer primary key);"),
+            Ok(None)
+        );
+        db.execute_all("pragma foreign_keys = off").unwrap();
+        let sql = run(
+            &db,
+            "alter table Author alter column name set not null;
+             alter table Author alter column name set default 'anonymous';",
+        )
+        .unwrap()
+        .unwrap();
+        db.execute_all("pragma foreign_keys = on").unwrap();
+        assert!(sql.contains("rename to \"_static_sqlite_old_Author\""));
+
+        let author = db.table("Author").unwrap().unwrap();
+        let name = author.column("name").unwrap();
+        assert!(name.not_null);
+        assert_eq!(name.default_value.as_deref(), Some("'anonymous'"));
+        assert_eq!(author.indexes.len(), 1);
+        assert_eq!(
+            db.query::<Row>("select * from Book", &[]).unwrap().len(),
+            1,
+            "the cascade did not fire"
+        );
+        assert_eq!(
+            db.table("Book").unwrap().unwrap().foreign_keys[0].table,
+            "Author"
+        );
+
+        assert!(run(&db, "alter table Author alter column missing drop not null").is_err());
+        assert!(
+            run(&db, "alter table Author alter column name drop not null")
+                .unwrap_err()
+                .contains("rebuild_needs_foreign_keys_off")
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use sqlparser::ast::{
+    Expr, FunctionArg, FunctionArgExpr, Query, SetExpr, Statement, TableFactor, TableFunctionArgs,
+    TableWithJoins, Value, Visit, Visitor,
+};
+use std::{collections::HashMap, ops::ControlFlow};
+
+/// sqlparser does not know sqlite's `x in rarray(:ids)`, so it
+/// gets to see `x in (select value from rarray(:ids))` instead
+pub fn expand_in_rarray(sql: &str) -> String {
+    let lower = sql.to_ascii_lowercase();
+    let mut output = String::with_capacity(sql.len());
+    let mut rest = 0;
+    for (start, _) in lower.match_indices("rarray(") {
+        let last_word = lower[..start]
+            .trim_end()
+            .rsplit(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .next();
+        let follows_in = last_word == Some("in");
+        let Some(end) = closing_paren(&lower, start + "rarray(".len()) else {
+            continue;
+        };
+        if !follows_in || start < rest {
+            continue;
+        }
+        output.push_str(&sql[rest..start]);
+        output.push_str("(select value from ");
+        output.push_str(&sql[start..=end]);
+        output.push(')');
+        rest = end + 1;
+    }
+    output.push_str(&sql[rest..]);
+
+    output
+}
+
+fn closing_paren(sql: &str, from: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, ch) in sql[from..].char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(from + i),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The parameters passed to `rarray(..)`, without their leading `:`,
+/// along with the column they are compared to in `column in rarray(:param)`
+pub fn array_params(statements: &[Statement]) -> HashMap<String, Option<String>> {
+    let mut visitor = ArrayVisitor(HashMap::new());
+    for statement in statements {
+        let _ = statement.visit(&mut visitor);
+    }
+    visitor.0
+}
+
+struct ArrayVisitor(HashMap<String, Option<String>>);
+
+impl Visitor for ArrayVisitor {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<Self::Break> {
+        if let Expr::InSubquery { expr, subquery, .. } = expr {
+            let column = match expr.as_ref() {
+                Expr::Identifier(ident) => Some(ident.value.clone()),
+                Expr::CompoundIdentifier(idents) => idents.last().map(|ident| ident.value.clone()),
+                _ => None,
+            };
+            if let Some(param) = subquery_param(subquery) {
+                self.0.insert(param, column);
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_table_factor(&mut self, table_factor: &TableFactor) -> ControlFlow<Self::Break> {
+        if let Some(param) = rarray_param(table_factor) {
+            self.0.entry(param).or_insert(None);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+fn subquery_param(query: &Query) -> Option<String> {
+    match query.body.as_ref() {
+        SetExpr::Select(select) => match select.from.as_slice() {
+            [TableWithJoins {
+                relation, joins, ..
+            }] if joins.is_empty() => rarray_param(relation),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn rarray_param(table_factor: &TableFactor) -> Option<String> {
+    match table_factor {
+        TableFactor::Table {
+            name,
+            args: Some(TableFunctionArgs { args, .. }),
+            ..
+        } if name.to_string().eq_ignore_ascii_case("rarray") => match args.as_slice() {
+            [FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Value(Value::Placeholder(p))))] => {
+                Some(p.replacen(":", "", 1))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlparser::{dialect::SQLiteDialect, parser::Parser};
+
+    #[test]
+    fn expand_in_rarray_works() {
+        assert_eq!(
+            expand_in_rarray("select * from Row where id IN rarray(:ids) and x = 1"),
+            "select * from Row where id IN (select value from rarray(:ids)) and x = 1"
+        );
+        assert_eq!(
+            expand_in_rarray("select * from rarray(:ids)"),
+            "select * from rarray(:ids)"
+        );
+    }
+
+    #[test]
+    fn array_params_works() {
+        let sql = expand_in_rarray(
+            "select * from Row join rarray(:names) n on n.value = Row.name where Row.id in rarray(:ids)",
+        );
+        let statements = Parser::parse_sql(&SQLiteDialect {}, &sql).unwrap();
+        let params = array_params(&statements);
+        assert_eq!(params.get("ids"), Some(&Some("id".to_string())));
+        assert_eq!(params.get("names"), Some(&None));
+    }
+}
@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::{Error, LitStr, Result};
+
+/// Where the sql of an `include_sql!` query comes from, for error messages
+#[derive(Debug, Clone)]
+pub struct Origin {
+    pub path: PathBuf,
+    /// The line of the file the sql starts on, 1 based
+    pub line: usize,
+}
+
+/// A query loaded from a file, named after its `-- name:` annotation if it has one
+pub struct Included {
+    pub name: Option<String>,
+    pub sql: String,
+    pub origin: Origin,
+}
+
+/// Parses the path of `include_sql!("path")`, relative to the crate root
+pub fn path(tokens: TokenStream) -> Result<(LitStr, PathBuf)> {
+    let lit: LitStr = syn::parse2(tokens)?;
+    let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+
+    Ok((lit.clone(), Path::new(&root).join(lit.value())))
+}
+
+/// The queries of a file, or of every `.sql` file of a directory sorted by name
+pub fn load(lit: &LitStr, path: &Path) -> Result<Vec<Included>> {
+    if !path.is_dir() {
+        return file(lit, path);
+    }
+    let mut files = std::fs::read_dir(path)
+        .map_err(|err| Error::new(lit.span(), format!("{}: {err}", path.display())))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sql"))
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let mut included = vec![];
+    for path in files {
+        let mut queries = file(lit, &path)?;
+        if let [query] = &mut queries[..] {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            query.name.get_or_insert_with(|| stem.into_owned());
+        }
+        included.extend(queries);
+    }
+
+    Ok(included)
+}
+
+fn file(lit: &LitStr, path: &Path) -> Result<Vec<Included>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| Error::new(lit.span(), format!("{}: {err}", path.display())))?;
+
+    Ok(sections(path, &contents))
+}
+
+/// Splits a file at its `-- name: <ident>` lines
+fn sections(path: &Path, contents: &str) -> Vec<Included> {
+    let mut sections: Vec<Included> = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        if let Some(name) = line.trim().strip_prefix("-- name:") {
+            sections.push(Included {
+                name: Some(name.trim().to_string()),
+                sql: String::new(),
+                origin: Origin {
+                    path: path.to_path_buf(),
+                    line: i + 2,
+                },
+            });
+            continue;
+        }
+        if sections.is_empty() {
+            sections.push(Included {
+                name: None,
+                sql: String::new(),
+                origin: Origin {
+                    path: path.to_path_buf(),
+                    line: 1,
+                },
+            });
+        }
+        let section = sections.last_mut().unwrap();
+        section.sql.push_str(line);
+        section.sql.push('\n');
+    }
+    // text before the first annotation is usually a comment
+    if sections.len() > 1 && sections[0].name.is_none() {
+        sections.remove(0);
+    }
+
+    sections
+}
+
+/// The name of a loaded query as a rust ident
+pub fn ident(lit: &LitStr, included: &Included) -> Result<Ident> {
+    let name = included.name.as_deref().unwrap_or_default();
+    syn::parse_str::<Ident>(name)
+        .map(|ident| Ident::new(&ident.to_string(), lit.span()))
+        .map_err(|_| {
+            Error::new(
+                lit.span(),
+                format!(
+                    "{}:{}: `{name}` is not a valid function name, add a `-- name:` annotation",
+                    included.origin.display(),
+                    included.origin.line
+                ),
+            )
+        })
+}
+
+/// Makes cargo rebuild when one of the files changes. Files added to or
+/// removed from an included directory go unnoticed, that needs the unstable
+/// `proc_macro::tracked_path` or a `rerun-if-changed` in the user's build.rs
+pub fn track(origins: &[&Origin]) -> TokenStream {
+    let mut paths = origins
+        .iter()
+        .map(|origin| origin.path.display().to_string())
+        .collect::<Vec<_>>();
+    paths.dedup();
+
+    quote! {
+        #(const _: &str = include_str!(#paths);)*
+    }
+}
+
+impl Origin {
+    /// Prefixes `message` with the file and line, shifting the line
+    /// sqlparser reports by where the sql starts in the file
+    pub fn message(&self, message: &str) -> String {
+        let line = message
+            .split_once("Line: ")
+            .and_then(|(_, rest)| rest.split(',').next())
+            .and_then(|line| line.trim().parse::<usize>().ok())
+            .map_or(self.line, |line| self.line + line.saturating_sub(1));
+
+        format!("{}:{line}: {message}", self.display())
+    }
+
+    /// The path relative to the crate root
+    fn display(&self) -> std::path::Display<'_> {
+        let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+        self.path.strip_prefix(root).unwrap_or(&self.path).display()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_at_name_annotations() {
+        let contents = "-- users\n-- name: get_user\nselect 1;\n\n-- name: all_users\nselect 2;\n";
+        let sections = sections(Path::new("users.sql"), contents);
+
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].name.as_deref(), Some("get_user"));
+        assert_eq!(sections[0].origin.line, 3);
+        assert_eq!(sections[1].sql.trim(), "select 2;");
+        assert_eq!(
+            sections[1].origin.message("error at Line: 1, Column: 3"),
+            "users.sql:6: error at Line: 1, Column: 3"
+        );
+    }
+}
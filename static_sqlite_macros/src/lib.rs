@@ -2,21 +2,24 @@ use std::collections::HashMap;
 use std::ops::ControlFlow;
 
 use proc_macro2::{Ident, Span, TokenStream};
-use quote::{quote, ToTokens};
+use quote::{format_ident, quote, ToTokens};
 use sqlparser::ast::{
     visit_relations, Delete, FromTable, Insert, SelectItem, TableFactor, TableWithJoins,
 };
 use sqlparser::{ast::Statement, dialect::SQLiteDialect, parser::Parser};
 use syn::{parse_macro_input, Error, LitStr, LocalInit, PatIdent, Result};
 
+mod alter;
+mod arrays;
 mod errors;
+mod include;
 mod names;
 
-use static_sqlite_core::{self as sqlite, Sqlite};
+use static_sqlite_core::{self as sqlite, Column, Hidden, Sqlite};
 
 /// Make rust structs and functions from sql
 ///
-/// ```
+/// ```no_run
 /// # use static_sqlite::{sql, Result};
 /// #
 /// sql! {
@@ -27,8 +30,8 @@ use static_sqlite_core::{self as sqlite, Sqlite};
 ///
 /// #[tokio::main]
 /// async fn main() -> Result<()> {
-///   let db = static_sqlite::open("db.sqlite3")?;
-///   let _ = migrate(&db).await?;
+///   let db = static_sqlite::open("db.sqlite3").await?;
+///   migrate(&db).await?;
 ///
 ///   Ok(())
 /// }
@@ -40,11 +43,24 @@ use static_sqlite_core::{self as sqlite, Sqlite};
 /// side effects, no files written. Just tokens. Which is why you need to either
 /// define your sqlite schema in that migrate fn. Each sql statement in that migrate
 /// string is a migration. Migrations are executed top to bottom.
+/// Alongside `migrate` you get `migrate_status` and `migrate_plan`, which show the
+/// applied and pending migrations without running anything, and `migrate_to`, which
+/// stops after a given migration, named after whatever you called the migrate fn.
+/// A `-- migrate:up` line starts a migration spanning several statements and a
+/// `-- migrate:down` line after it the sql that undoes it, which `migrate_down` runs.
+/// The macro checks that the down sql leaves the schema the way it found it.
+/// A `-- migrate:rust backfill` line declares a migration that runs Rust code. The
+/// migrate fns then take a `MigrateSteps { backfill: Box::new(|ctx| ...) }`, where
+/// `ctx` derefs to the connection and `migrate_steps::backfill` has the structs of
+/// the schema at that point.
+/// `alter table User alter column email set not null` (or `drop not null`,
+/// `set default ..`, `drop default`, `type ..`) works too, the macro expands it into
+/// the rebuild of the table sqlite needs, indexes and triggers included.
 /// Each let ident becomes a function and each create/alter table sql statement becomes
 /// a struct.
 ///
-/// ```
-/// # use static_sqlite::sql;
+/// ```no_run
+/// # use static_sqlite::{sql, Result};
 /// #
 /// sql! {
 ///   let migrate = r#"
@@ -54,24 +70,90 @@ use static_sqlite_core::{self as sqlite, Sqlite};
 ///
 ///   let insert_row = r#"
 ///     insert into Row (updated_at)
-///     values (?)
+///     values (:updated_at)
 ///     returning *
 ///   "#;
 /// }
 ///
 /// #[tokio::main]
 /// async fn main() -> Result<()> {
-///   let db = static_sqlite::open("db.sqlite3")?;
-///   let _ = migrate(&db).await?;
-///   let row = insert_row(&db, 0).await?;
+///   let db = static_sqlite::open("db.sqlite3").await?;
+///   migrate(&db).await?;
+///   let row = insert_row(&db, Some(0)).await?;
 ///
 ///   Ok(())
 /// }
 /// ```
+///
+/// Lists are passed with `rarray`, the element type comes from the column
+/// on the left of `in` or from a type hint like `:ids__INTEGER`
+///
+/// ```no_run
+/// # use static_sqlite::{sql, Result};
+/// #
+/// sql! {
+///   let migrate = r#"
+///     create table Row (id integer primary key);
+///   "#;
+///
+///   let rows_by_ids = r#"
+///     select * from Row where id in rarray(:ids)
+///   "#;
+/// }
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<()> {
+/// # let db = static_sqlite::open("db.sqlite3").await?;
+///
+/// let rows = rows_by_ids(&db, &[1, 2, 3]).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Sql can live in files, relative to the crate root. A directory becomes one
+/// function per `.sql` file, named after the file, or per `-- name: <fn>` line in it
+///
+/// ```ignore
+/// sql! {
+///   let migrate = include_sql!("sql/schema.sql");
+///   include_sql!("sql/queries");
+/// }
+/// ```
+///
+/// Editing an included file rebuilds the crate, adding or removing a file of
+/// an included directory does not, stable proc macros can not track a directory.
+/// Until they can, have the crate's build.rs watch it
+///
+/// ```
+/// // build.rs
+/// println!("cargo:rerun-if-changed=sql/queries");
+/// ```
+///
+/// `#![sync]` generates blocking functions on `static_sqlite::sync::Sqlite` instead,
+/// `#![sync(both)]` keeps the async ones and adds the blocking ones in a `blocking` module.
+/// The `_stream` functions return an `Iterator` there
+///
+/// ```no_run
+/// # use static_sqlite::{sql, Result};
+/// #
+/// sql! {
+///   #![sync]
+///
+///   let migrate = r#"
+///     create table Row (id integer primary key);
+///   "#;
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let db = static_sqlite::sync::open("db.sqlite3")?;
+/// migrate(&db)?;
+/// # Ok(())
+/// # }
+/// ```
 #[proc_macro]
 pub fn sql(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let SqlExprs(sql_exprs) = parse_macro_input!(input as SqlExprs);
-    match sql_macro(sql_exprs) {
+    let SqlExprs(mode, sql_exprs) = parse_macro_input!(input as SqlExprs);
+    match sql_macro(mode, sql_exprs) {
         Ok(s) => s.to_token_stream().into(),
         Err(e) => e.to_compile_error().into(),
     }
@@ -82,37 +164,129 @@ pub fn sql(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 // 2. Run the sql against an in memory sqlite db, looking for any sqlite errors
 // 3. Generate the structs from the migrate expr (the one with only ddl sql statements)
 // 4. Generate the fns from the other idents in the sql! macro
-fn sql_macro(exprs: Vec<SqlExpr>) -> syn::Result<TokenStream> {
+fn sql_macro(mode: Mode, exprs: Vec<SqlExpr>) -> syn::Result<TokenStream> {
+    let origins = exprs
+        .iter()
+        .filter_map(|expr| expr.origin.as_ref())
+        .collect::<Vec<_>>();
+    let tracked = include::track(&origins);
     let (migrate_expr, exprs) = split_exprs(&exprs)?;
     let db = match sqlite::open(":memory:") {
         Ok(db) => db,
         Err(err) => return Err(syn::Error::new(Span::call_site(), err)),
     };
+    // validate migrate expr, one migration at a time like migrate runs them,
+    // expanding the alter column statements sqlite can not run
+    let mut steps = Steps::new(migrate_expr);
+    let mut expanded = vec![];
+    let parsed =
+        sqlite::Migrations::from_sql(&migrate_expr.sql).map_err(|err| migrate_expr.error(err))?;
+    for migration in parsed.iter() {
+        if let Some((sql, down)) = validate_migration(&db, migrate_expr, migration)? {
+            let version = migration.version;
+            let down = match down {
+                Some(down) => quote! { Some(#down.into()) },
+                None => quote! { None },
+            };
+            expanded.push(quote! { .with_sql(#version, #sql, #down) });
+        }
+        if migration.step.is_some() {
+            steps.push(&db, migrate_expr, migration)?;
+        }
+    }
+    let sql = &migrate_expr.sql;
+    let migrations = quote! {
+        static_sqlite::Migrations::from_sql(#sql)?
+            #(#expanded)*
+    };
     if let Err(err) = db.execute_all("PRAGMA foreign_keys = ON;") {
         return Err(syn::Error::new(Span::call_site(), err));
     };
-    // validate migrate expr
-    for stmt in &migrate_expr.statements {
-        if let Err(err) = db.execute_all(&stmt.to_string()) {
-            return Err(syn::Error::new(migrate_expr.ident.span(), err));
-        }
-    }
-    let _ = names::validate_migrate_expr(&migrate_expr)?;
-    let migrate_fn = migrate_fn(&migrate_expr);
-    let schema = schema(&db);
+    names::validate_migrate_expr(migrate_expr)?;
+    let schema = schema(&db)?;
     let structs = structs_tokens(migrate_expr.ident.span(), &schema);
     let fns = fn_tokens(&db, &schema, &exprs)?;
     let traits = trait_tokens(&schema, &exprs);
+    let row_structs = fns.iter().map(|fns| &fns.row_struct);
+    let async_fns = fns.iter().map(|fns| &fns.async_fn);
+    let sync_fns = fns.iter().map(|fns| &fns.sync_fn);
+    let functions = match mode {
+        Mode::Async => {
+            let migrate_fn = migrate_fn(migrate_expr, &migrations, &steps);
+            quote! {
+                #(#async_fns)*
+                #migrate_fn
+            }
+        }
+        Mode::Sync => {
+            let migrate_fn = sync_migrate_fn(migrate_expr, &migrations, &steps);
+            quote! {
+                #(#sync_fns)*
+                #migrate_fn
+            }
+        }
+        Mode::Both => {
+            let migrate_fn = migrate_fn(migrate_expr, &migrations, &steps);
+            let sync_migrate_fn = sync_migrate_fn(migrate_expr, &migrations, &steps);
+            quote! {
+                #(#async_fns)*
+                #migrate_fn
+
+                pub mod blocking {
+                    #[allow(unused_imports)]
+                    use super::*;
+
+                    #(#sync_fns)*
+                    #sync_migrate_fn
+                }
+            }
+        }
+    };
+    let steps = steps.tokens(&migrations);
     let output = quote! {
         #(#structs)*
-        #(#fns)*
+        #(#row_structs)*
+        #functions
+        #steps
         #(#traits)*
-        #migrate_fn
+        #tracked
     };
 
     Ok(output)
 }
 
+/// Applies `migration`, making sure its down sql leaves the schema the way it was.
+/// Returns its sql and down sql when either had alter column statements to expand
+fn validate_migration(
+    db: &Sqlite,
+    expr: &SqlExpr,
+    migration: &sqlite::Migration,
+) -> syn::Result<Option<(String, Option<String>)>> {
+    let schema = || db.schema().map_err(|err| expr.error(err));
+    let execute = |sql: &str| alter::run(db, sql).map_err(|err| expr.error(err));
+    let Some(down) = &migration.down else {
+        return Ok(execute(&migration.sql)?.map(|sql| (sql, None)));
+    };
+    let before = schema()?;
+    let sql = execute(&migration.sql)?;
+    let expanded_down = execute(down)?;
+    if schema()? != before {
+        return Err(expr.error(format!(
+            "the down sql of migration {} ({}) does not undo it",
+            migration.version, migration.name
+        )));
+    }
+    execute(&migration.sql)?;
+
+    Ok(match (sql, expanded_down) {
+        (None, None) => None,
+        (sql, expanded_down) => Some((
+            sql.unwrap_or_else(|| migration.sql.to_string()),
+            Some(expanded_down.unwrap_or_else(|| down.to_string())),
+        )),
+    })
+}
+
 fn trait_parts(statement: &Statement) -> Option<(String, Option<&[SelectItem]>)> {
     match &statement {
         Statement::Insert(Insert {
@@ -132,29 +306,24 @@ fn trait_parts(statement: &Statement) -> Option<(String, Option<&[SelectItem]>)>
             _ => None,
         },
         Statement::Delete(Delete {
-            from, returning, ..
-        }) => match from {
-            FromTable::WithFromKeyword(table) => match &table[..] {
-                [TableWithJoins { relation, .. }] => match relation {
-                    TableFactor::Table { name, .. } => {
-                        Some((name.to_string(), returning.as_ref().map(|x| x.as_slice())))
-                    }
-                    _ => None,
-                },
-                _ => None,
-            },
+            from: FromTable::WithFromKeyword(table),
+            returning,
+            ..
+        }) => match &table[..] {
+            [TableWithJoins {
+                relation: TableFactor::Table { name, .. },
+                ..
+            }] => Some((name.to_string(), returning.as_ref().map(|x| x.as_slice()))),
             _ => None,
         },
         Statement::Query(query) => match query.body.as_ref() {
             sqlparser::ast::SetExpr::Select(ref select) => {
                 let table = &select.from;
                 match &table[..] {
-                    [TableWithJoins { relation, .. }] => match relation {
-                        TableFactor::Table { name, .. } => {
-                            Some((name.to_string(), Some(select.projection.as_slice())))
-                        }
-                        _ => None,
-                    },
+                    [TableWithJoins {
+                        relation: TableFactor::Table { name, .. },
+                        ..
+                    }] => Some((name.to_string(), Some(select.projection.as_slice()))),
                     _ => None,
                 }
             }
@@ -164,12 +333,12 @@ fn trait_parts(statement: &Statement) -> Option<(String, Option<&[SelectItem]>)>
     }
 }
 
-fn trait_tokens(schema: &HashMap<String, Vec<SchemaRow>>, exprs: &[&SqlExpr]) -> Vec<TokenStream> {
+fn trait_tokens(schema: &Schema, exprs: &[&SqlExpr]) -> Vec<TokenStream> {
     exprs
         .iter()
         .flat_map(|expr| {
             let ident = &expr.ident;
-            let query_ident = snake_to_pascal_case(&ident);
+            let query_ident = snake_to_pascal_case(ident);
             expr.statements
                 .iter()
                 .filter_map(trait_parts)
@@ -200,7 +369,7 @@ fn trait_tokens(schema: &HashMap<String, Vec<SchemaRow>>, exprs: &[&SqlExpr]) ->
                                 ) => match schema.get(&object_name.to_string()) {
                                     Some(rows) => rows
                                         .iter()
-                                        .map(|row| Ident::new(&row.column_name, expr.ident.span()))
+                                        .map(|column| Ident::new(&column.name, expr.ident.span()))
                                         .collect::<Vec<_>>(),
                                     None => todo!(),
                                 },
@@ -209,7 +378,7 @@ fn trait_tokens(schema: &HashMap<String, Vec<SchemaRow>>, exprs: &[&SqlExpr]) ->
                                 ) => match schema.get(&table_name) {
                                     Some(rows) => rows
                                         .iter()
-                                        .map(|row| Ident::new(&row.column_name, expr.ident.span()))
+                                        .map(|column| Ident::new(&column.name, expr.ident.span()))
                                         .collect::<Vec<_>>(),
                                     None => todo!(),
                                 },
@@ -218,6 +387,7 @@ fn trait_tokens(schema: &HashMap<String, Vec<SchemaRow>>, exprs: &[&SqlExpr]) ->
                         let table_ident = Ident::new(&table_name, expr.ident.span());
                         quote! {
                             impl From<#query_ident> for #table_ident {
+                                #[allow(clippy::needless_update)]
                                 fn from(#query_ident { #(#fields,)* }: #query_ident) -> Self {
                                     Self {
                                         #(#fields,)*
@@ -238,7 +408,7 @@ fn input_column_names(db: &Sqlite, expr: &SqlExpr) -> syn::Result<Vec<String>> {
     let mut output = vec![];
     match db.bind_param_names(&expr.sql) {
         Ok(names) => output.extend(names),
-        Err(err) => return Err(syn::Error::new(expr.ident.span(), err.to_string())),
+        Err(err) => return Err(expr.error(err)),
     }
     Ok(output)
 }
@@ -247,7 +417,7 @@ fn output_column_names(db: &Sqlite, expr: &SqlExpr) -> syn::Result<Vec<String>>
     let mut output = vec![];
     match db.aliased_column_names(&expr.sql) {
         Ok(names) => output.extend(names),
-        Err(err) => return Err(syn::Error::new(expr.ident.span(), err.to_string())),
+        Err(err) => return Err(expr.error(err)),
     }
     Ok(output)
 }
@@ -257,7 +427,7 @@ fn table_names(db: &Sqlite, expr: &SqlExpr) -> syn::Result<Vec<String>> {
     for stmt in &expr.statements {
         match stmt {
             Statement::Insert(Insert { table_name, .. }) => output.push(table_name.to_string()),
-            Statement::Update { table, .. } => output.extend(table_with_joins(&table)),
+            Statement::Update { table, .. } => output.extend(table_with_joins(table)),
             Statement::Delete(Delete { tables, from, .. }) => {
                 output.extend(tables.iter().map(|table| table.to_string()));
                 match from {
@@ -295,67 +465,25 @@ fn table_factor_tables(table_factor: &TableFactor) -> Vec<String> {
     }
 }
 
-#[derive(Debug, Default, Clone)]
-struct SchemaRow {
-    table_name: String,
-    column_name: String,
-    column_type: String,
-    not_null: i64,
-    pk: i64,
-}
-
-impl static_sqlite_core::FromRow for SchemaRow {
-    fn from_row(
-        columns: Vec<(String, static_sqlite_core::Value)>,
-    ) -> static_sqlite_core::Result<Self> {
-        let mut row = SchemaRow::default();
-        for (name, value) in columns {
-            match name.as_str() {
-                "table_name" => row.table_name = value.try_into()?,
-                "column_name" => row.column_name = value.try_into()?,
-                "column_type" => row.column_type = value.try_into()?,
-                "not_null" => row.not_null = value.try_into()?,
-                "pk" => row.pk = value.try_into()?,
-                _ => {}
-            }
-        }
-
-        Ok(row)
-    }
-}
-
-fn schema(db: &Sqlite) -> HashMap<String, Vec<SchemaRow>> {
-    let rows: static_sqlite_core::Result<Vec<SchemaRow>> = db.query(
-        r#"
-        select
-            m.tbl_name as table_name,
-            p.name as column_name,
-            p."notnull" as not_null,
-            p.pk,
-            p.type as column_type
-        from sqlite_master m
-        join pragma_table_info(m.name) p
-        where m.type = 'table'
-            and m.tbl_name not like 'sqlite_%'
-        order by
-            m.tbl_name,
-            p.cid;"#,
-        &[],
-    );
-
-    match rows {
-        Ok(rows) => rows.into_iter().fold(HashMap::new(), |mut acc, row| {
-            acc.entry(row.table_name.clone())
-                .or_insert_with(Vec::new)
-                .push(row);
-            acc
-        }),
-        Err(_) => todo!(),
+fn schema(db: &Sqlite) -> syn::Result<Schema> {
+    match db.tables() {
+        Ok(tables) => Ok(tables
+            .into_iter()
+            .map(|table| {
+                let columns = table
+                    .columns
+                    .into_iter()
+                    .filter(|column| column.hidden != Hidden::VirtualTable)
+                    .collect();
+                (table.name, columns)
+            })
+            .collect()),
+        Err(err) => Err(syn::Error::new(Span::call_site(), err)),
     }
 }
 
 // Splits the SqlExpr into migrate (the first found ddl only one) and the others
-fn split_exprs<'a>(exprs: &'a Vec<SqlExpr>) -> Result<(&'a SqlExpr, Vec<&'a SqlExpr>)> {
+fn split_exprs(exprs: &[SqlExpr]) -> Result<(&SqlExpr, Vec<&SqlExpr>)> {
     // we need to find the one expr that has all ddl statements
     // treat this as the migrate fn
     // this also grabs the db schema
@@ -371,26 +499,185 @@ fn split_exprs<'a>(exprs: &'a Vec<SqlExpr>) -> Result<(&'a SqlExpr, Vec<&'a SqlE
     Ok((migrate_expr, exprs))
 }
 
-/// Generates the migrate fn tokens
+/// Generates the migrate fn tokens, with its `_status`, `_plan`, `_to` and `_down` companions
 ///
-/// It uses a sqlite savepoint to either
-/// migrate everything or rollback the transaction
-/// if something failed
-fn migrate_fn(expr: &SqlExpr) -> TokenStream {
-    let SqlExpr { ident, sql, .. } = expr;
+/// Every statement is a migration, versioned by its position.
+/// Each one runs in its own savepoint, see `Migrations::run`
+fn migrate_fn(expr: &SqlExpr, migrations: &TokenStream, steps: &Steps) -> TokenStream {
+    let ident = &expr.ident;
+    let param = steps.param();
+    let with_steps = steps.migrations(migrations);
+    let status = format_ident!("{}_status", ident);
+    let plan = format_ident!("{}_plan", ident);
+    let to = format_ident!("{}_to", ident);
+    let down = format_ident!("{}_down", ident);
+
+    quote! {
+        pub async fn #ident(sqlite: &static_sqlite::Sqlite, #param) -> static_sqlite::Result<static_sqlite::MigrationReport> {
+            sqlite.migrate(#with_steps).await
+        }
+
+        /// The applied and pending migrations, without applying anything
+        pub async fn #status(sqlite: &static_sqlite::Sqlite) -> static_sqlite::Result<static_sqlite::MigrationStatus> {
+            sqlite.migration_status(#migrations).await
+        }
+
+        /// The pending migrations with the sql they would run
+        pub async fn #plan(sqlite: &static_sqlite::Sqlite) -> static_sqlite::Result<Vec<static_sqlite::Migration>> {
+            Ok(#status(sqlite).await?.pending)
+        }
+
+        /// Applies the pending migrations up to the one named `target` or whose version it is
+        pub async fn #to(sqlite: &static_sqlite::Sqlite, #param target: &str) -> static_sqlite::Result<static_sqlite::MigrationReport> {
+            sqlite.migrate_to(#with_steps, target).await
+        }
+
+        /// Reverts the migrations after the one named `target` or whose version it is, `"0"` reverts all
+        pub async fn #down(sqlite: &static_sqlite::Sqlite, target: &str) -> static_sqlite::Result<static_sqlite::MigrationReport> {
+            sqlite.migrate_down(#migrations, target).await
+        }
+    }
+}
+
+/// Same as `migrate_fn`, blocking
+fn sync_migrate_fn(expr: &SqlExpr, migrations: &TokenStream, steps: &Steps) -> TokenStream {
+    let ident = &expr.ident;
+    let param = steps.param();
+    let with_steps = steps.migrations(migrations);
+    let status = format_ident!("{}_status", ident);
+    let plan = format_ident!("{}_plan", ident);
+    let to = format_ident!("{}_to", ident);
+    let down = format_ident!("{}_down", ident);
 
     quote! {
-        pub async fn #ident(sqlite: &static_sqlite::Sqlite) -> static_sqlite::Result<()> {
-            let sql = #sql.to_string();
-            let _ = static_sqlite::execute_all(&sqlite, "create table if not exists __migrations__ (sql text primary key not null);".into()).await?;
-            for stmt in sql.split(";").filter(|s| !s.trim().is_empty()) {
-                let mig: String = stmt.chars().filter(|c| !c.is_whitespace()).collect();
-                let changed = static_sqlite::execute(&sqlite, "insert into __migrations__ (sql) values (:sql) on conflict (sql) do nothing".into(), vec![static_sqlite::Value::Text(mig)]).await?;
-                if changed != 0 {
-                    let _k = static_sqlite::execute(&sqlite, stmt.to_string(), vec![]).await?;
+        pub fn #ident(sqlite: &static_sqlite::sync::Sqlite, #param) -> static_sqlite::Result<static_sqlite::MigrationReport> {
+            #with_steps.run(sqlite)
+        }
+
+        /// The applied and pending migrations, without applying anything
+        pub fn #status(sqlite: &static_sqlite::sync::Sqlite) -> static_sqlite::Result<static_sqlite::MigrationStatus> {
+            #migrations.status(sqlite)
+        }
+
+        /// The pending migrations with the sql they would run
+        pub fn #plan(sqlite: &static_sqlite::sync::Sqlite) -> static_sqlite::Result<Vec<static_sqlite::Migration>> {
+            #migrations.plan(sqlite)
+        }
+
+        /// Applies the pending migrations up to the one named `target` or whose version it is
+        pub fn #to(sqlite: &static_sqlite::sync::Sqlite, #param target: &str) -> static_sqlite::Result<static_sqlite::MigrationReport> {
+            #with_steps.run_to(sqlite, target)
+        }
+
+        /// Reverts the migrations after the one named `target` or whose version it is, `"0"` reverts all
+        pub fn #down(sqlite: &static_sqlite::sync::Sqlite, target: &str) -> static_sqlite::Result<static_sqlite::MigrationReport> {
+            #migrations.run_down(sqlite, target)
+        }
+    }
+}
+
+/// The `-- migrate:rust` steps of the migrate expr. Each one gets a module with
+/// the structs of the schema as it is when the step runs and a `Context`
+struct Steps {
+    ty: Ident,
+    module: Ident,
+    steps: Vec<(Ident, TokenStream)>,
+}
+
+impl Steps {
+    fn new(expr: &SqlExpr) -> Self {
+        Steps {
+            ty: format_ident!("{}Steps", snake_to_pascal_case(&expr.ident)),
+            module: format_ident!("{}_steps", expr.ident),
+            steps: vec![],
+        }
+    }
+
+    /// Snapshots the schema `migration`'s step runs against
+    fn push(&mut self, db: &Sqlite, expr: &SqlExpr, migration: &sqlite::Migration) -> Result<()> {
+        let name = syn::parse_str::<Ident>(&migration.name).map_err(|_| {
+            expr.error(format!(
+                "`-- migrate:rust {}` needs a name that is a valid function name",
+                migration.name
+            ))
+        })?;
+        let name = Ident::new(&name.to_string(), expr.ident.span());
+        let structs = structs_tokens(expr.ident.span(), &schema(db)?);
+        self.steps.push((name, quote! { #(#structs)* }));
+
+        Ok(())
+    }
+
+    /// The steps the migrate fns take, when there are any
+    fn param(&self) -> Option<TokenStream> {
+        let ty = &self.ty;
+        (!self.steps.is_empty()).then(|| quote! { steps: #ty, })
+    }
+
+    /// How the migrate fns that run steps get their migrations
+    fn migrations(&self, migrations: &TokenStream) -> TokenStream {
+        match self.steps.is_empty() {
+            true => migrations.clone(),
+            false => quote! { steps.migrations()? },
+        }
+    }
+
+    fn tokens(&self, migrations: &TokenStream) -> TokenStream {
+        if self.steps.is_empty() {
+            return quote! {};
+        }
+        let Steps { ty, module, steps } = self;
+        let modules = steps.iter().map(|(name, structs)| {
+            quote! {
+                pub mod #name {
+                    #structs
+
+                    /// The connection, inside the savepoint of the migration
+                    pub struct Context<'a>(pub &'a static_sqlite::sync::Sqlite);
+
+                    impl std::ops::Deref for Context<'_> {
+                        type Target = static_sqlite::sync::Sqlite;
+
+                        fn deref(&self) -> &Self::Target {
+                            self.0
+                        }
+                    }
+                }
+            }
+        });
+        let fields = steps.iter().map(|(name, _)| {
+            quote! {
+                pub #name: Box<dyn Fn(#module::#name::Context<'_>) -> static_sqlite::Result<()> + Send + Sync>
+            }
+        });
+        let with_steps = steps.iter().map(|(name, _)| {
+            let lit = name.to_string();
+            quote! {
+                .with_step(#lit, {
+                    let steps = steps.clone();
+                    move |db| (steps.#name)(#module::#name::Context(db))
+                })
+            }
+        });
+
+        quote! {
+            /// The schema each `-- migrate:rust` step sees
+            pub mod #module {
+                #(#modules)*
+            }
+
+            /// The code of each `-- migrate:rust` step
+            pub struct #ty {
+                #(#fields,)*
+            }
+
+            impl #ty {
+                fn migrations(self) -> static_sqlite::Result<static_sqlite::Migrations> {
+                    let steps = std::sync::Arc::new(self);
+                    Ok(#migrations
+                        #(#with_steps)*)
                 }
             }
-            return Ok(());
         }
     }
 }
@@ -401,14 +688,26 @@ enum FunctionType {
     Stream,
 }
 
-fn fn_tokens(db: &Sqlite, schema: &Schema, exprs: &[&SqlExpr]) -> Result<Vec<TokenStream>> {
+/// Which functions `sql!` generates, see `#![sync]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Async,
+    Sync,
+    Both,
+}
+
+/// The tokens generated for one query
+struct FnTokens {
+    row_struct: TokenStream,
+    async_fn: TokenStream,
+    sync_fn: TokenStream,
+}
+
+fn fn_tokens(db: &Sqlite, schema: &Schema, exprs: &[&SqlExpr]) -> Result<Vec<FnTokens>> {
     let mut output = vec![];
     for expr in exprs {
-        if let None = expr.statements.last() {
-            return Err(syn::Error::new(
-                expr.ident.span(),
-                "At least one sql statement is required",
-            ));
+        if expr.statements.last().is_none() {
+            return Err(expr.error("At least one sql statement is required"));
         }
 
         let inputs = input_column_names(db, expr)?;
@@ -419,33 +718,51 @@ fn fn_tokens(db: &Sqlite, schema: &Schema, exprs: &[&SqlExpr]) -> Result<Vec<Tok
         let mut table_names = table_names(db, expr)?;
         // get joined table names that might not exist in select clause
         table_names.extend(join_table_names(expr));
-        let mut schema_rows = vec![];
+        let mut columns = vec![];
         for table_name in &table_names {
-            match schema.get(table_name) {
-                Some(rows) => schema_rows.extend(rows),
-                None => {}
+            if let Some(table_columns) = schema.get(table_name) {
+                columns.extend(table_columns)
             };
         }
 
+        let array_params = arrays::array_params(&expr.statements);
+        let mut array_types = HashMap::new();
+        for input in &inputs {
+            if let Some(compared_to) = array_params.get(input) {
+                let element_type = array_element_type(input, compared_to, &columns)
+                    .ok_or_else(|| {
+                        expr.error(
+                            format!("Can't tell the element type of rarray(:{input}), try a type hint like {input}__INTEGER"),
+                        )
+                    })?;
+                array_types.insert(input, element_type);
+            }
+        }
+
         let fn_args = inputs
             .iter()
             .map(|aliases_column_name| {
-                match parse_type_hinted_column_name(aliases_column_name, &schema_rows) {
+                if let Some(element_type) = array_types.get(aliases_column_name) {
+                    let field_name = array_param_name(aliases_column_name, expr.ident.span());
+                    let field_type = create_fn_argument_type(aliases_column_name, element_type);
+                    return quote! { #field_name: &[#field_type] };
+                }
+                match parse_type_hinted_column_name(aliases_column_name, &columns) {
                     TypedToken::FromTypeHint(type_hint) => {
                         let field_name = Ident::new(&type_hint.alias, expr.ident.span());
                         let field_type =
                             create_fn_argument_type(&type_hint.alias, &type_hint.column_type);
                         match type_hint.not_null {
-                            0 => quote! { #field_name: Option<#field_type> },
-                            _ => quote! { #field_name: #field_type },
+                            false => quote! { #field_name: Option<#field_type> },
+                            true => quote! { #field_name: #field_type },
                         }
                     }
-                    TypedToken::FromSchemaRow(schema_row) => {
-                        let field_name = Ident::new(&schema_row.column_name, expr.ident.span());
+                    TypedToken::FromColumn(column) => {
+                        let field_name = Ident::new(&column.name, expr.ident.span());
                         let field_type =
-                            create_fn_argument_type(aliases_column_name, &schema_row.column_type);
-                        match (schema_row.pk, schema_row.not_null) {
-                            (0, 0) => quote! { #field_name: Option<#field_type> },
+                            create_fn_argument_type(aliases_column_name, &column.column_type);
+                        match (column.pk, column.not_null) {
+                            (0, false) => quote! { #field_name: Option<#field_type> },
                             _ => quote! { #field_name: #field_type },
                         }
                     }
@@ -456,18 +773,18 @@ fn fn_tokens(db: &Sqlite, schema: &Schema, exprs: &[&SqlExpr]) -> Result<Vec<Tok
         let params = inputs
             .iter()
             .map(|aliases_column_name| {
-                match parse_type_hinted_column_name(aliases_column_name, &schema_rows) {
+                if let Some(element_type) = array_types.get(aliases_column_name) {
+                    let field_name = array_param_name(aliases_column_name, expr.ident.span());
+                    return create_array_binding_value(element_type, field_name);
+                }
+                match parse_type_hinted_column_name(aliases_column_name, &columns) {
                     TypedToken::FromTypeHint(type_hint) => {
                         let field_name = Ident::new(&type_hint.alias, expr.ident.span());
                         create_binding_value(&type_hint.column_type, type_hint.not_null, field_name)
                     }
-                    TypedToken::FromSchemaRow(schema_row) => {
-                        let field_name = Ident::new(&schema_row.column_name, expr.ident.span());
-                        create_binding_value(
-                            &schema_row.column_type,
-                            schema_row.not_null,
-                            field_name,
-                        )
+                    TypedToken::FromColumn(column) => {
+                        let field_name = Ident::new(&column.name, expr.ident.span());
+                        create_binding_value(&column.column_type, column.not_null, field_name)
                     }
                 }
             })
@@ -483,44 +800,66 @@ fn fn_tokens(db: &Sqlite, schema: &Schema, exprs: &[&SqlExpr]) -> Result<Vec<Tok
 
         let ident = &expr.ident;
         let outputs = output_column_names(db, expr)?;
-        let pascal_case = snake_to_pascal_case(&ident);
+        let pascal_case = snake_to_pascal_case(ident);
 
         let output_typed = outputs
             .iter()
-            .map(|output| parse_type_hinted_column_name(output, &schema_rows))
+            .map(|output| parse_type_hinted_column_name(output, &columns))
             .collect::<Vec<_>>();
 
         let struct_tokens = struct_tokens(expr.ident.span(), &pascal_case, &output_typed);
 
         let sql = &expr.sql;
+        let label = ident.to_string();
 
         let fn_tokens = match fn_type {
             FunctionType::QueryVec => quote! {
-                #[allow(non_snake_case)]
-                pub async fn #ident(db: &static_sqlite::Sqlite, #(#fn_args),*) -> static_sqlite::Result<Vec<#pascal_case>> {
-                    let rows: Vec<#pascal_case> = static_sqlite::query(db, #sql, vec![#(#params,)*]).await?;
+                #[allow(non_snake_case, clippy::too_many_arguments)]
+                pub async fn #ident<E: static_sqlite::Executor>(db: &E, #(#fn_args),*) -> static_sqlite::Result<Vec<#pascal_case>> {
+                    let rows: Vec<#pascal_case> = static_sqlite::Executor::query(db, Some(#label), #sql.into(), vec![#(#params,)*]).await?;
                     Ok(rows)
                 }
             },
             FunctionType::QueryOption => quote! {
-                #[allow(non_snake_case)]
-                pub async fn #ident(db: &static_sqlite::Sqlite, #(#fn_args),*) ->  static_sqlite::Result<Option<#pascal_case>> {
-                    static_sqlite::query_first(db, #sql, vec![#(#params,)*]).await
+                #[allow(non_snake_case, clippy::too_many_arguments)]
+                pub async fn #ident<E: static_sqlite::Executor>(db: &E, #(#fn_args),*) ->  static_sqlite::Result<Option<#pascal_case>> {
+                    static_sqlite::Executor::query_first(db, Some(#label), #sql.into(), vec![#(#params,)*]).await
                }
             },
             FunctionType::Stream => quote! {
-                #[allow(non_snake_case)]
-                pub async fn #ident(db: &static_sqlite::Sqlite, #(#fn_args),*) ->  static_sqlite::Result<impl futures::Stream<Item = static_sqlite::Result<#pascal_case>>> {
-                    static_sqlite::stream(db, #sql, vec![#(#params,)*]).await
+                #[allow(non_snake_case, clippy::too_many_arguments)]
+                pub async fn #ident<E: static_sqlite::Executor>(db: &E, #(#fn_args),*) ->  static_sqlite::Result<impl futures::Stream<Item = static_sqlite::Result<#pascal_case>>> {
+                    static_sqlite::Executor::stream(db, Some(#label), #sql.into(), vec![#(#params,)*]).await
                }
             },
         };
 
-        output.push(quote! {
-            #struct_tokens
-
-            #fn_tokens
+        let sync_fn = match fn_type {
+            FunctionType::QueryVec => quote! {
+                #[allow(non_snake_case, clippy::too_many_arguments)]
+                pub fn #ident(db: &static_sqlite::sync::Sqlite, #(#fn_args),*) -> static_sqlite::Result<Vec<#pascal_case>> {
+                    db.query(#sql, &[#(#params,)*])
+                }
+            },
+            FunctionType::QueryOption => quote! {
+                #[allow(non_snake_case, clippy::too_many_arguments)]
+                pub fn #ident(db: &static_sqlite::sync::Sqlite, #(#fn_args),*) -> static_sqlite::Result<Option<#pascal_case>> {
+                    db.query_first(#sql, &[#(#params,)*])
+                }
+            },
+            // prepare copies the params on bind, the iterator only borrows `db`
+            FunctionType::Stream => quote! {
+                #[allow(non_snake_case, clippy::too_many_arguments)]
+                pub fn #ident(db: &static_sqlite::sync::Sqlite, #(#fn_args),*) -> static_sqlite::Result<impl Iterator<Item = static_sqlite::Result<#pascal_case>> + '_> {
+                    db.iter(#sql, &[#(#params,)*])
+                }
+            },
+        };
 
+        output.push(FnTokens {
+            row_struct: struct_tokens,
+            async_fn: fn_tokens,
+            sync_fn,
         })
     }
     Ok(output)
@@ -540,7 +879,7 @@ fn create_fn_argument_type(fieldname: &String, column_type: &str) -> TokenStream
     }
 }
 
-fn create_binding_value(field_type: &str, not_null: i64, name: Ident) -> TokenStream {
+fn create_binding_value(field_type: &str, not_null: bool, name: Ident) -> TokenStream {
     match field_type {
         "BLOB" => {
             quote! { #name.into() }
@@ -548,34 +887,62 @@ fn create_binding_value(field_type: &str, not_null: i64, name: Ident) -> TokenSt
         "INTEGER" => quote! { #name.into() },
         "REAL" | "DOUBLE" => quote! { #name.into() },
         "TEXT" => match not_null {
-            1 => quote! {
+            true => quote! {
 
                 #name.to_string().into()
             },
-            0 => quote! {
+            false => quote! {
                 match #name {
                     Some(val) => val.to_string().into(),
                     None => static_sqlite::Value::Null
                 }
             },
-            _ => unreachable!(),
         },
         _ => unimplemented!("Sqlite param not supported"),
     }
 }
 
+fn create_array_binding_value(element_type: &str, name: Ident) -> TokenStream {
+    match element_type {
+        "TEXT" => quote! { static_sqlite::Value::array(#name.iter().map(|val| val.to_string())) },
+        _ => quote! { static_sqlite::Value::array(#name.iter().cloned()) },
+    }
+}
+
+/// The argument of a `rarray(:param)` parameter, without its type hint
+fn array_param_name(param: &str, span: Span) -> Ident {
+    Ident::new(param.split("__").next().unwrap_or(param), span)
+}
+
+/// The element type of a `rarray(:param)` parameter, from its type hint,
+/// the column it is compared to or a column with the same name
+fn array_element_type(
+    param: &str,
+    compared_to: &Option<String>,
+    columns: &[&Column],
+) -> Option<String> {
+    if let [_, column_type] = param.split("__").collect::<Vec<_>>().as_slice() {
+        return Some(column_type.to_string());
+    }
+    let name = compared_to.as_deref().unwrap_or(param);
+    columns
+        .iter()
+        .find(|column| column.name == name)
+        .map(|column| column.column_type.clone())
+}
+
 #[derive(Debug, Clone)]
 struct TypeHintedToken {
     name: String,
     alias: String,
     column_type: String,
-    not_null: i64,
+    not_null: bool,
 }
 
 #[derive(Debug, Clone)]
 enum TypedToken {
     FromTypeHint(TypeHintedToken),
-    FromSchemaRow(SchemaRow),
+    FromColumn(Column),
 }
 
 /*
@@ -585,12 +952,12 @@ enum TypedToken {
  * Otherwise it is a column name
  *
  */
-fn parse_type_hinted_column_name(alias: &str, schema_rows: &Vec<&SchemaRow>) -> TypedToken {
+fn parse_type_hinted_column_name(alias: &str, columns: &[&Column]) -> TypedToken {
     let parts = alias.split("__").collect::<Vec<_>>();
     let result = match parts.len() {
-        1 => TypedToken::FromSchemaRow(
-            match schema_rows.iter().find(|row| &row.column_name == alias) {
-                Some(row) => (**row).clone(),
+        1 => TypedToken::FromColumn(
+            match columns.iter().find(|column| column.name == alias) {
+                Some(column) => (**column).clone(),
                 None => panic!("Column {:?} referenced in binding or column not found in schema, maybe you forgot to add the type hint?", alias),
             }
         ),
@@ -598,15 +965,15 @@ fn parse_type_hinted_column_name(alias: &str, schema_rows: &Vec<&SchemaRow>) ->
             alias: alias.to_string(),
             name: parts[0].to_string(),
             column_type: parts[1].to_string(),
-            not_null: 1,
+            not_null: true,
         }),
         3 => TypedToken::FromTypeHint(TypeHintedToken {
             alias: alias.to_string(),
             name: parts[0].to_string(),
             column_type: parts[1].to_string(),
             not_null: match parts[2].to_lowercase().as_str() {
-                "nullable" => 0,
-                "not_null" => 1,
+                "nullable" => false,
+                "not_null" => true,
                 _ => panic!("Invalid type hint: {:?}, last part must be nullable or not_null", alias),
             },
         }),
@@ -617,7 +984,7 @@ fn parse_type_hinted_column_name(alias: &str, schema_rows: &Vec<&SchemaRow>) ->
 
 fn join_table_names(expr: &&SqlExpr) -> Vec<String> {
     let mut output = vec![];
-    visit_relations(&expr.statements, |rel| {
+    let _ = visit_relations(&expr.statements, |rel| {
         output.push(rel.to_string());
         ControlFlow::<()>::Continue(())
     });
@@ -643,16 +1010,16 @@ fn snake_to_pascal_case(input: &syn::Ident) -> syn::Ident {
     syn::Ident::new(&result, input.span())
 }
 
-type Schema = HashMap<String, Vec<SchemaRow>>;
+type Schema = HashMap<String, Vec<Column>>;
 
 fn structs_tokens(span: Span, schema: &Schema) -> Vec<TokenStream> {
     schema
         .iter()
         .map(|(table, cols)| {
-            let ident = proc_macro2::Ident::new(&table, span);
+            let ident = proc_macro2::Ident::new(table, span);
             let typed_tokens: Vec<TypedToken> = cols
                 .iter()
-                .map(|col| TypedToken::FromSchemaRow(col.clone()))
+                .map(|col| TypedToken::FromColumn(col.clone()))
                 .collect();
             struct_tokens(span, &ident, &typed_tokens)
         })
@@ -665,25 +1032,15 @@ fn struct_tokens(span: Span, ident: &Ident, output_typed: &[TypedToken]) -> Toke
             TypedToken::FromTypeHint(type_hint) => {
                 field_type_from_datatype_name(&type_hint.column_type)
             }
-            TypedToken::FromSchemaRow(schema_row) => field_type(schema_row),
+            TypedToken::FromColumn(column) => field_type(column),
         };
         let name = match row {
             TypedToken::FromTypeHint(type_hint) => Ident::new(&type_hint.name, span),
-            TypedToken::FromSchemaRow(schema_row) => Ident::new(&schema_row.column_name, span),
+            TypedToken::FromColumn(column) => Ident::new(&column.name, span),
         };
-        let optional = match (
-            match row {
-                TypedToken::FromTypeHint(type_hint) => type_hint.not_null,
-                TypedToken::FromSchemaRow(schema_row) => schema_row.not_null,
-            },
-            match row {
-                TypedToken::FromTypeHint(_) => 0,
-                TypedToken::FromSchemaRow(schema_row) => schema_row.pk,
-            },
-        ) {
-            (0, 0) => true,
-            (0, 1) | (1, 0) | (1, 1) => false,
-            _ => unreachable!(),
+        let optional = match row {
+            TypedToken::FromTypeHint(type_hint) => !type_hint.not_null,
+            TypedToken::FromColumn(column) => !column.not_null && column.pk == 0,
         };
 
         match optional {
@@ -695,14 +1052,14 @@ fn struct_tokens(span: Span, ident: &Ident, output_typed: &[TypedToken]) -> Toke
         let name = Ident::new(
             match row {
                 TypedToken::FromTypeHint(type_hint) => &type_hint.name,
-                TypedToken::FromSchemaRow(schema_row) => &schema_row.column_name,
+                TypedToken::FromColumn(column) => &column.name,
             },
             span,
         );
         let lit_str = LitStr::new(
             match row {
                 TypedToken::FromTypeHint(type_hint) => &type_hint.alias,
-                TypedToken::FromSchemaRow(schema_row) => &schema_row.column_name,
+                TypedToken::FromColumn(column) => &column.name,
             },
             span,
         );
@@ -733,8 +1090,8 @@ fn struct_tokens(span: Span, ident: &Ident, output_typed: &[TypedToken]) -> Toke
     tokens
 }
 
-fn field_type(row: &SchemaRow) -> TokenStream {
-    field_type_from_datatype_name(&row.column_type)
+fn field_type(column: &Column) -> TokenStream {
+    field_type_from_datatype_name(&column.column_type)
 }
 
 fn field_type_from_datatype_name(datatype_name: &str) -> TokenStream {
@@ -752,52 +1109,115 @@ struct SqlExpr {
     ident: Ident,
     sql: String,
     statements: Vec<Statement>,
+    /// Set for the sql of `include_sql!`
+    origin: Option<include::Origin>,
+}
+
+impl SqlExpr {
+    fn new(ident: Ident, sql: String, origin: Option<include::Origin>) -> Result<SqlExpr> {
+        let statements = match Parser::parse_sql(&SQLiteDialect {}, &arrays::expand_in_rarray(&sql))
+        {
+            Ok(ast) => ast,
+            Err(err) => {
+                let message = match &origin {
+                    Some(origin) => origin.message(&err.to_string()),
+                    None => err.to_string(),
+                };
+                return Err(Error::new_spanned(ident, message));
+            }
+        };
+
+        Ok(SqlExpr {
+            ident,
+            sql,
+            statements,
+            origin,
+        })
+    }
+
+    /// An error at the ident, mentioning the file and line of included sql
+    fn error(&self, message: impl std::fmt::Display) -> Error {
+        let message = message.to_string();
+        match &self.origin {
+            Some(origin) => Error::new(self.ident.span(), origin.message(&message)),
+            None => Error::new(self.ident.span(), message),
+        }
+    }
 }
 
 #[derive(Debug)]
-struct SqlExprs(pub Vec<SqlExpr>);
+struct SqlExprs(Mode, pub Vec<SqlExpr>);
 
 impl syn::parse::Parse for SqlExprs {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut mode = Mode::Async;
+        for attr in input.call(syn::Attribute::parse_inner)? {
+            if !attr.path().is_ident("sync") {
+                return Err(Error::new_spanned(attr, "Only #![sync] is supported"));
+            }
+            mode = match &attr.meta {
+                syn::Meta::Path(_) => Mode::Sync,
+                _ => {
+                    let both: Ident = attr.parse_args()?;
+                    if both != "both" {
+                        return Err(Error::new_spanned(both, "Try #![sync] or #![sync(both)]"));
+                    }
+                    Mode::Both
+                }
+            };
+        }
         let mut sql_exprs: Vec<SqlExpr> = Vec::new();
         while !input.is_empty() {
             let stmt: syn::Stmt = input.parse()?;
-            let sql_expr = sql_expr(stmt)?;
-            sql_exprs.push(sql_expr);
+            sql_exprs.extend(sql_expr(stmt)?);
         }
-        Ok(SqlExprs(sql_exprs))
+        Ok(SqlExprs(mode, sql_exprs))
     }
 }
 
-fn sql_expr(stmt: syn::Stmt) -> Result<SqlExpr> {
+fn sql_expr(stmt: syn::Stmt) -> Result<Vec<SqlExpr>> {
     match stmt {
         syn::Stmt::Local(syn::Local { pat, init, .. }) => {
             let ident = match pat {
                 syn::Pat::Ident(PatIdent { ident, .. }) => ident,
                 _ => unimplemented!("Only idents are supported for sql"),
             };
-            let sql = match init {
+            match init {
                 Some(LocalInit { expr, .. }) => match *expr {
                     syn::Expr::Lit(syn::ExprLit {
                         lit: syn::Lit::Str(lit_str),
                         ..
-                    }) => lit_str.value(),
-                    _ => return Err(Error::new_spanned(ident, "sql is missing")),
+                    }) => Ok(vec![SqlExpr::new(ident, lit_str.value(), None)?]),
+                    // let get_user = include_sql!("queries/get_user.sql");
+                    syn::Expr::Macro(syn::ExprMacro { mac, .. })
+                        if mac.path.is_ident("include_sql") =>
+                    {
+                        let (lit, path) = include::path(mac.tokens)?;
+                        if path.is_dir() {
+                            return Err(Error::new_spanned(lit, "Only a file can be bound with let, include a directory with include_sql!(\"dir\");"));
+                        }
+                        let mut queries = include::load(&lit, &path)?;
+                        if queries.len() != 1 {
+                            return Err(Error::new_spanned(lit, "The file has more than one -- name: query, include it with include_sql!(\"file\");"));
+                        }
+                        let query = queries.remove(0);
+                        Ok(vec![SqlExpr::new(ident, query.sql, Some(query.origin))?])
+                    }
+                    _ => Err(Error::new_spanned(ident, "sql is missing")),
                 },
-                None => return Err(Error::new_spanned(ident, "sql is missing")),
-            };
-            let statements = match Parser::parse_sql(&SQLiteDialect {}, &sql) {
-                Ok(ast) => ast,
-                Err(err) => {
-                    // TODO: better error handling
-                    return Err(Error::new_spanned(ident, err.to_string()));
-                }
-            };
-            Ok(SqlExpr {
-                ident,
-                sql,
-                statements,
-            })
+                None => Err(Error::new_spanned(ident, "sql is missing")),
+            }
+        }
+        // include_sql!("queries");
+        syn::Stmt::Macro(syn::StmtMacro { mac, .. }) if mac.path.is_ident("include_sql") => {
+            let (lit, path) = include::path(mac.tokens)?;
+            include::load(&lit, &path)?
+                .into_iter()
+                .map(|query| {
+                    let ident = include::ident(&lit, &query)?;
+                    SqlExpr::new(ident, query.sql, Some(query.origin))
+                })
+                .collect()
         }
         syn::Stmt::Item(_) => todo!("todo item"),
         syn::Stmt::Expr(_, _) => todo!("todo expr"),
@@ -806,47 +1226,49 @@ fn sql_expr(stmt: syn::Stmt) -> Result<SqlExpr> {
 }
 
 fn is_ddl(expr: &SqlExpr) -> bool {
-    expr.statements.iter().all(|stmt| match stmt {
-        Statement::CreateView { .. }
-        | Statement::CreateTable { .. }
-        | Statement::CreateVirtualTable { .. }
-        | Statement::CreateIndex { .. }
-        | Statement::CreateRole { .. }
-        | Statement::AlterTable { .. }
-        | Statement::AlterIndex { .. }
-        | Statement::AlterView { .. }
-        | Statement::AlterRole { .. }
-        | Statement::Drop { .. }
-        | Statement::DropFunction { .. }
-        | Statement::CreateExtension { .. }
-        | Statement::SetNamesDefault {}
-        | Statement::ShowFunctions { .. }
-        | Statement::ShowVariable { .. }
-        | Statement::ShowVariables { .. }
-        | Statement::ShowCreate { .. }
-        | Statement::ShowColumns { .. }
-        | Statement::ShowTables { .. }
-        | Statement::ShowCollation { .. }
-        | Statement::Use { .. }
-        | Statement::StartTransaction { .. }
-        | Statement::SetTransaction { .. }
-        | Statement::Comment { .. }
-        | Statement::Commit { .. }
-        | Statement::Rollback { .. }
-        | Statement::CreateSchema { .. }
-        | Statement::CreateDatabase { .. }
-        | Statement::CreateFunction { .. }
-        | Statement::CreateProcedure { .. }
-        | Statement::CreateMacro { .. }
-        | Statement::CreateStage { .. }
-        | Statement::Prepare { .. }
-        | Statement::ExplainTable { .. }
-        | Statement::Explain { .. }
-        | Statement::Savepoint { .. }
-        | Statement::ReleaseSavepoint { .. }
-        | Statement::CreateSequence { .. }
-        | Statement::CreateType { .. }
-        | Statement::Pragma { .. } => true,
-        _ => false,
+    expr.statements.iter().all(|stmt| {
+        matches!(
+            stmt,
+            Statement::CreateView { .. }
+                | Statement::CreateTable { .. }
+                | Statement::CreateVirtualTable { .. }
+                | Statement::CreateIndex { .. }
+                | Statement::CreateRole { .. }
+                | Statement::AlterTable { .. }
+                | Statement::AlterIndex { .. }
+                | Statement::AlterView { .. }
+                | Statement::AlterRole { .. }
+                | Statement::Drop { .. }
+                | Statement::DropFunction { .. }
+                | Statement::CreateExtension { .. }
+                | Statement::SetNamesDefault {}
+                | Statement::ShowFunctions { .. }
+                | Statement::ShowVariable { .. }
+                | Statement::ShowVariables { .. }
+                | Statement::ShowCreate { .. }
+                | Statement::ShowColumns { .. }
+                | Statement::ShowTables { .. }
+                | Statement::ShowCollation { .. }
+                | Statement::Use { .. }
+                | Statement::StartTransaction { .. }
+                | Statement::SetTransaction { .. }
+                | Statement::Comment { .. }
+                | Statement::Commit { .. }
+                | Statement::Rollback { .. }
+                | Statement::CreateSchema { .. }
+                | Statement::CreateDatabase { .. }
+                | Statement::CreateFunction { .. }
+                | Statement::CreateProcedure { .. }
+                | Statement::CreateMacro { .. }
+                | Statement::CreateStage { .. }
+                | Statement::Prepare { .. }
+                | Statement::ExplainTable { .. }
+                | Statement::Explain { .. }
+                | Statement::Savepoint { .. }
+                | Statement::ReleaseSavepoint { .. }
+                | Statement::CreateSequence { .. }
+                | Statement::CreateType { .. }
+                | Statement::Pragma { .. }
+        )
     })
 }
@@ -89,29 +89,27 @@ impl Visitor for AliasVisitor {
         &mut self,
         table_factor: &sqlparser::ast::TableFactor,
     ) -> ControlFlow<Self::Break> {
-        match table_factor {
-            TableFactor::Table { name, alias, .. } => match alias {
-                Some(table_alias) => {
-                    self.0.insert(
-                        Name::Table(name.0.iter().map(|ident| ident.value.clone()).collect()),
-                        Name::Table(vec![table_alias.name.value.clone()]),
-                    );
-                    self.0.insert(
-                        Name::Table(vec![table_alias.name.value.clone()]),
-                        Name::Table(name.0.iter().map(|ident| ident.value.clone()).collect()),
-                    );
-                }
-
-                None => {}
-            },
-            _ => {}
+        if let TableFactor::Table {
+            name,
+            alias: Some(table_alias),
+            ..
+        } = table_factor
+        {
+            self.0.insert(
+                Name::Table(name.0.iter().map(|ident| ident.value.clone()).collect()),
+                Name::Table(vec![table_alias.name.value.clone()]),
+            );
+            self.0.insert(
+                Name::Table(vec![table_alias.name.value.clone()]),
+                Name::Table(name.0.iter().map(|ident| ident.value.clone()).collect()),
+            );
         }
         ControlFlow::<()>::Continue(())
     }
 }
 
 #[allow(unused)]
-pub fn aliases(statements: &Vec<Statement>) -> HashMap<Name, Name> {
+pub fn aliases(statements: &[Statement]) -> HashMap<Name, Name> {
     let mut visitor = AliasVisitor::default();
     statements.iter().for_each(|statement| {
         statement.visit(&mut visitor);
@@ -204,7 +202,7 @@ pub fn validate_migrate_expr(sql_expr: &SqlExpr) -> syn::Result<()> {
                                 ..
                             } => {
                                 let mut v = vec![];
-                                v.push(table_name(&foreign_table));
+                                v.push(table_name(foreign_table));
                                 v.extend(
                                     referred_columns
                                         .iter()
@@ -225,14 +223,14 @@ pub fn validate_migrate_expr(sql_expr: &SqlExpr) -> syn::Result<()> {
 
     match validate_query(&tables, &HashMap::new(), &refs) {
         Ok(_) => Ok(()),
-        Err(err) => Err(syn::Error::new(sql_expr.ident.span(), format!("{:?}", err))),
+        Err(err) => Err(sql_expr.error(format!("{:?}", err))),
     }
 }
 
 pub fn validate_query(
-    db_schema: &Vec<Name>,
+    db_schema: &[Name],
     aliases: &HashMap<Name, Name>,
-    query_schema: &Vec<Name>,
+    query_schema: &[Name],
 ) -> Result<()> {
     query_schema.iter().try_for_each(|name| match name {
         Name::Table(vec) => match db_schema.contains(name) {
@@ -468,3 +468,1007 @@ fn ui() {
     let t = trybuild::TestCases::new();
     t.compile_fail("tests/ui/*.rs");
 }
+
+#[tokio::test]
+async fn schema_introspection_works() -> Result<()> {
+    let db = static_sqlite::open(":memory:").await?;
+    db.call(|conn| {
+        conn.execute_all(
+            "create table User (id integer primary key, email text not null unique, name text default 'anon', slug text generated always as (lower(name)) virtual)",
+        )?;
+        conn.execute_all(
+            "create table Post (id integer primary key, user_id integer not null references User(id) on delete cascade, title text)",
+        )?;
+        conn.execute_all("create index Post_title on Post (title desc)")?;
+        conn.execute_all("create view PostTitle as select id, title from Post")?;
+        conn.execute_all(
+            "create trigger Post_touch after insert on Post begin update Post set title = title where id = new.id; end",
+        )
+    })
+    .await?;
+
+    let schema = db.call(|conn| conn.schema()).await?;
+
+    let names: Vec<_> = schema.tables.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec!["Post", "User"]);
+
+    let user = schema.tables.iter().find(|t| t.name == "User").unwrap();
+    let email = user.column("email").unwrap();
+    assert_eq!(email.column_type, "TEXT");
+    assert!(email.not_null);
+    assert_eq!(user.column("id").unwrap().pk, 1);
+    assert_eq!(
+        user.column("name").unwrap().default_value,
+        Some("'anon'".into())
+    );
+    assert_eq!(
+        user.column("slug").unwrap().hidden,
+        static_sqlite::Hidden::GeneratedVirtual
+    );
+    assert_eq!(user.indexes.len(), 1);
+    assert!(user.indexes[0].unique);
+    assert_eq!(user.indexes[0].origin, static_sqlite::IndexOrigin::Unique);
+
+    let post = schema.tables.iter().find(|t| t.name == "Post").unwrap();
+    assert_eq!(post.foreign_keys.len(), 1);
+    assert_eq!(post.foreign_keys[0].table, "User");
+    assert_eq!(post.foreign_keys[0].from, vec!["user_id".to_string()]);
+    assert_eq!(post.foreign_keys[0].to, vec![Some("id".to_string())]);
+    assert_eq!(post.foreign_keys[0].on_delete, "CASCADE");
+    let index = &post.indexes[0];
+    assert_eq!(index.name, "Post_title");
+    assert!(index.columns[0].desc);
+    assert_eq!(index.columns[0].name, Some("title".into()));
+    assert!(!index.columns.last().unwrap().key);
+
+    assert_eq!(schema.views.len(), 1);
+    assert_eq!(schema.views[0].columns.len(), 2);
+    assert_eq!(schema.triggers.len(), 1);
+    assert_eq!(schema.triggers[0].table, "Post");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn background_maintenance_works() -> Result<()> {
+    let path = std::env::temp_dir().join(format!(
+        "static_sqlite_{}_maintenance.sqlite3",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let db = static_sqlite::open(path.to_str().unwrap()).await?;
+    db.call(|conn| {
+        conn.execute_all("pragma journal_mode = wal")?;
+        conn.execute_all("create table Row (id integer primary key)")?;
+        conn.execute_all("insert into Row default values")
+    })
+    .await?;
+
+    let handle = db.maintain(static_sqlite::Maintenance {
+        interval: std::time::Duration::from_millis(10),
+        checkpoint: Some(static_sqlite::CheckpointMode::Truncate),
+        quick_check: true,
+        ..Default::default()
+    });
+
+    let report = loop {
+        if let Some(report) = handle.last_report() {
+            break report;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    };
+    assert!(report.errors.is_empty());
+    assert!(report.problems.is_empty());
+    let checkpoint = report.checkpoint.unwrap();
+    assert!(!checkpoint.busy);
+    assert_eq!(checkpoint.log_frames, checkpoint.checkpointed_frames);
+    handle.stop();
+
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+#[tokio::test]
+async fn status_snapshot_works() -> Result<()> {
+    let db = static_sqlite::open(":memory:").await?;
+    db.call(|conn| {
+        conn.set_limit(static_sqlite::Limit::VariableNumber, 10);
+        conn.execute_all("create table Row (id integer primary key)")
+    })
+    .await?;
+
+    let status = db.status().await?;
+    assert_eq!(status.limits.variable_number, 10);
+    assert!(status.db.schema_used > 0);
+    assert!(status.memory.memory_used.current > 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn array_parameters_work() -> Result<()> {
+    sql! {
+        let migrate = r#"
+            create table Row (
+                id integer primary key,
+                txt text not null
+            )
+        "#;
+
+        let insert_row = r#"
+            insert into Row (txt) values (:txt) returning *
+        "#;
+
+        let rows_by_ids = r#"
+            select * from Row where id in rarray(:ids) order by id
+        "#;
+
+        let rows_by_txt = r#"
+            select * from Row where txt in (select value from rarray(:txts)) order by id
+        "#;
+
+        let rows_by_hinted_ids = r#"
+            select * from Row where rowid in rarray(:row_ids__INTEGER) order by id
+        "#;
+    }
+
+    let db = static_sqlite::open(":memory:").await?;
+    migrate(&db).await?;
+    for txt in ["a", "b", "c"] {
+        insert_row(&db, txt).await?;
+    }
+
+    let rows = rows_by_ids(&db, &[1, 3]).await?;
+    assert_eq!(
+        rows.iter().map(|row| row.txt.as_str()).collect::<Vec<_>>(),
+        vec!["a", "c"]
+    );
+    assert!(rows_by_ids(&db, &[]).await?.is_empty());
+
+    let rows = rows_by_txt(&db, &["b", "c"]).await?;
+    assert_eq!(
+        rows.iter().map(|row| row.id).collect::<Vec<_>>(),
+        vec![2, 3]
+    );
+    assert_eq!(rows_by_hinted_ids(&db, &[2]).await?[0].txt, "b");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn custom_vfs_works() -> Result<()> {
+    sql! {
+        let migrate = r#"
+            create table Row (
+                id integer primary key,
+                txt text not null
+            )
+        "#;
+
+        let insert_row = r#"
+            insert into Row (txt) values (:txt) returning *
+        "#;
+    }
+
+    let faults = static_sqlite::Faults::default();
+    static_sqlite::register_vfs(
+        "integration_faults",
+        static_sqlite::FaultVfs::new(static_sqlite::MemoryVfs::new(), faults.clone()),
+        false,
+    )?;
+    let options = static_sqlite::OpenOptions {
+        vfs: Some("integration_faults".into()),
+        ..Default::default()
+    };
+    let db = static_sqlite::open_with("vfs.db", options).await?;
+    migrate(&db).await?;
+
+    faults.fail_nth(
+        static_sqlite::FaultOp::Write,
+        1,
+        static_sqlite::FaultKind::DiskFull,
+    );
+    assert!(insert_row(&db, "a").await.is_err());
+    let row = insert_row(&db, "b").await?.first_row()?;
+    assert_eq!(row.txt, "b");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn close_works() -> Result<()> {
+    let db = static_sqlite::open(":memory:").await?;
+    let other = db.clone();
+    db.call(|conn| conn.execute_all("create table Row (id integer primary key)"))
+        .await?;
+    db.close().await?;
+
+    let result = other.call(|conn| conn.execute_all("select 1")).await;
+    assert!(matches!(
+        result,
+        Err(static_sqlite::Error::ConnectionClosed)
+    ));
+
+    let db = static_sqlite::open(":memory:").await?;
+    let other = db.clone();
+    let (slow, closed, after) = tokio::join!(
+        other.call(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            Ok(())
+        }),
+        db.close_with_timeout(std::time::Duration::from_millis(10)),
+        other.call(|conn| conn.execute_all("select 1"))
+    );
+    slow?;
+    assert!(matches!(
+        closed,
+        Err(static_sqlite::Error::CloseTimeout { .. })
+    ));
+    assert!(matches!(after, Err(static_sqlite::Error::ConnectionClosed)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pool_works() -> Result<()> {
+    sql! {
+        let migrate = r#"
+            create table Row (
+                id integer primary key,
+                txt text not null
+            )
+        "#;
+
+        let insert_row = r#"
+            insert into Row (txt) values (:txt) returning *
+        "#;
+
+        let rows_by_txt = r#"
+            select * from Row where txt = :txt
+        "#;
+    }
+
+    let path = std::env::temp_dir().join(format!(
+        "static_sqlite_{}_pool_works.sqlite3",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let pool = static_sqlite::Pool::open(
+        path.display(),
+        static_sqlite::PoolOptions {
+            readers: 2,
+            init: vec!["pragma foreign_keys = on".into()],
+            ..Default::default()
+        },
+    )
+    .await?;
+    migrate(&pool).await?;
+    insert_row(&pool, "a").await?;
+
+    let rows = rows_by_txt(&pool, "a").await?;
+    assert_eq!(rows.len(), 1);
+
+    let write = pool
+        .read(|conn| conn.execute_all("insert into Row (txt) values ('b')"))
+        .await;
+    assert!(write.unwrap_err().to_string().contains("readonly"));
+    let foreign_keys = pool
+        .read(|conn| conn.rows("pragma foreign_keys", &[]))
+        .await?;
+    assert_eq!(foreign_keys[0][0].1, static_sqlite::Value::Integer(1));
+
+    static_sqlite::execute(&pool, "begin".into(), vec![]).await?;
+    assert!(!pool.call(|conn| Ok(conn.is_autocommit())).await?);
+    static_sqlite::execute(&pool, "commit".into(), vec![]).await?;
+
+    pool.close().await?;
+    std::fs::remove_file(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn transactions_work() -> Result<()> {
+    sql! {
+        let migrate = r#"
+            create table Row (
+                id integer primary key,
+                txt text not null
+            )
+        "#;
+
+        let insert_row = r#"
+            insert into Row (txt) values (:txt) returning *
+        "#;
+
+        let all_rows = r#"
+            select * from Row
+        "#;
+    }
+
+    let db = static_sqlite::open(":memory:").await?;
+    migrate(&db).await?;
+
+    let result = db
+        .transaction(static_sqlite::TransactionMode::Immediate, |tx| {
+            tx.execute("insert into Row (txt) values (?)", vec!["a".into()])?;
+            tx.execute("insert into Row (txt) values (?)", vec![().into()])
+        })
+        .await;
+    assert!(result.is_err());
+    assert!(all_rows(&db).await?.is_empty());
+
+    let tx = db.begin().await?;
+    insert_row(&tx, "a").await?;
+    drop(tx);
+    assert!(all_rows(&db).await?.is_empty());
+
+    let tx = db.begin().await?;
+    insert_row(&tx, "b").await?;
+    assert_eq!(all_rows(&tx).await?.len(), 1);
+    tx.commit().await?;
+    assert_eq!(all_rows(&db).await?.first_row()?.txt, "b");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn bounded_streams_work() -> Result<()> {
+    struct Number(i64);
+
+    impl static_sqlite::FromRow for Number {
+        fn from_row(columns: Vec<(String, static_sqlite::Value)>) -> Result<Self> {
+            Ok(Number(columns.into_iter().next().unwrap().1.try_into()?))
+        }
+    }
+
+    let db = static_sqlite::open(":memory:").await?;
+    let numbers = "with recursive n(x) as (select 1 union all select x + 1 from n where x < 100000) select x from n";
+
+    let stream = static_sqlite::stream::<Number>(&db, "select x from missing", vec![]).await?;
+    pin_mut!(stream);
+    assert!(stream.next().await.unwrap().is_err());
+    assert!(stream.next().await.is_none());
+
+    let options = static_sqlite::StreamOptions {
+        buffer: 1,
+        yield_every: Some(1),
+    };
+    let stream = static_sqlite::stream_with::<Number>(&db, numbers, vec![], options).await?;
+    pin_mut!(stream);
+    assert_eq!(stream.next().await.unwrap()?.0, 1);
+
+    // the stream is stuck on its full buffer, yet calls still run in between
+    let one = db.call(|conn| conn.rows("select 1 as one", &[])).await?;
+    assert_eq!(one[0][0].1, static_sqlite::Value::Integer(1));
+    assert_eq!(stream.next().await.unwrap()?.0, 2);
+
+    // awaiting a call for every row of a default stream does not deadlock
+    let db = static_sqlite::open(":memory:").await?;
+    static_sqlite::execute_all(&db, "create table N (x integer)").await?;
+    let stream = static_sqlite::stream::<Number>(
+        &db,
+        "with recursive n(x) as (select 1 union all select x + 1 from n where x < 500) select x from n",
+        vec![],
+    )
+    .await?;
+    pin_mut!(stream);
+    while let Some(number) = stream.next().await {
+        let x = number?.0;
+        db.call(move |conn| conn.execute("insert into N (x) values (?)", vec![x.into()]))
+            .await?;
+    }
+    let count = db
+        .call(|conn| conn.rows("select count(*) from N", &[]))
+        .await?;
+    assert_eq!(count[0][0].1, static_sqlite::Value::Integer(500));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn panics_are_recovered() -> Result<()> {
+    let db = static_sqlite::open(":memory:").await?;
+    let events = db.events();
+    pin_mut!(events);
+
+    let result: Result<()> = db.call(|_| panic!("boom")).await;
+    assert!(matches!(result, Err(static_sqlite::Error::Panicked(message)) if message == "boom"));
+    db.ping().await?;
+    assert_eq!(
+        events.next().await,
+        Some(static_sqlite::Event::Panicked("boom".into()))
+    );
+
+    let opened = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = opened.clone();
+    let db = static_sqlite::open_supervised(":memory:", Default::default(), move |conn| {
+        counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        conn.execute_all("create table Row (id integer primary key)")?;
+        Ok(())
+    })
+    .await?;
+    let events = db.events();
+    pin_mut!(events);
+
+    let result: Result<()> = db
+        .call(|conn| {
+            conn.execute_all("insert into Row default values")?;
+            panic!("boom")
+        })
+        .await;
+    assert!(result.is_err());
+    assert_eq!(
+        events.next().await,
+        Some(static_sqlite::Event::Panicked("boom".into()))
+    );
+    assert_eq!(events.next().await, Some(static_sqlite::Event::Reopened));
+    assert_eq!(opened.load(std::sync::atomic::Ordering::SeqCst), 2);
+    let rows = db.call(|conn| conn.rows("select id from Row", &[])).await?;
+    assert!(rows.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn timeouts_work() -> Result<()> {
+    let db = static_sqlite::open(":memory:").await?;
+    let started = std::time::Instant::now();
+    let result = db
+        .with_timeout(std::time::Duration::from_millis(50))
+        .call(|conn| {
+            conn.rows(
+                "with recursive n(x) as (select 1 union all select x + 1 from n) select count(*) from n",
+                &[],
+            )
+        })
+        .await;
+    assert!(matches!(result, Err(static_sqlite::Error::Timeout { .. })));
+    // the statement was interrupted, so the worker is free again
+    db.ping().await?;
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+
+    // an interrupt between two statements fails the second one
+    let result = db
+        .with_timeout(std::time::Duration::from_millis(20))
+        .call(|conn| {
+            conn.execute_all("create table Row (id integer primary key)")?;
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            conn.execute_all("insert into Row default values")
+        })
+        .await;
+    assert!(matches!(result, Err(static_sqlite::Error::Timeout { .. })));
+    let rows = db.call(|conn| conn.rows("select id from Row", &[])).await?;
+    assert!(rows.is_empty(), "the interrupt was lost between statements");
+
+    let impatient = db.with_timeout(std::time::Duration::from_millis(20));
+    let (slow, queued) = tokio::join!(
+        db.call(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            Ok(())
+        }),
+        impatient.call(|conn| conn.execute_all("select 1"))
+    );
+    slow?;
+    assert!(matches!(
+        queued,
+        Err(static_sqlite::Error::QueueTimeout { .. })
+    ));
+
+    // a stream times out until its first row
+    let stream = static_sqlite::stream::<static_sqlite::Row>(
+        &db.with_timeout(std::time::Duration::from_millis(50)),
+        "with recursive n(x) as (select 1 union all select x + 1 from n) select count(*) from n",
+        vec![],
+    )
+    .await?;
+    pin_mut!(stream);
+    assert!(matches!(
+        stream.next().await,
+        Some(Err(static_sqlite::Error::Timeout { .. }))
+    ));
+    assert!(stream.next().await.is_none());
+    db.ping().await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn prioritized_queue_works() -> Result<()> {
+    let db = static_sqlite::open_bounded(":memory:", Default::default(), 4).await?;
+    let order = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let (first, second) = (order.clone(), order.clone());
+
+    let background = db.background();
+    let (slow, batch, interactive) = tokio::join!(
+        db.call(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            Ok(())
+        }),
+        background.call(move |_| {
+            first.lock().unwrap().push("background");
+            Ok(())
+        }),
+        db.call(move |_| {
+            second.lock().unwrap().push("interactive");
+            Ok(())
+        })
+    );
+    slow?;
+    batch?;
+    interactive?;
+    assert_eq!(*order.lock().unwrap(), vec!["interactive", "background"]);
+
+    let stats = db.queue_stats();
+    assert_eq!(stats.capacity, Some(4));
+    assert_eq!(stats.interactive_depth + stats.background_depth, 0);
+    assert_eq!(stats.interactive_wait.count, 2);
+    assert_eq!(stats.background_wait.count, 1);
+    assert!(stats.background_wait.max >= std::time::Duration::from_millis(50));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn batched_writes_work() -> Result<()> {
+    let db = static_sqlite::open(":memory:").await?;
+    static_sqlite::execute_all(&db, "create table Item (name text unique not null)").await?;
+    let batched = db.batched(static_sqlite::BatchOptions {
+        max_statements: 10,
+        max_delay: std::time::Duration::from_millis(50),
+    });
+    let insert = |name: &str| {
+        static_sqlite::execute(
+            &batched,
+            "insert into Item (name) values (?)".into(),
+            vec![name.into()],
+        )
+    };
+
+    let (a, b, duplicate, c) = tokio::join!(insert("a"), insert("b"), insert("a"), insert("c"));
+    assert_eq!(a?, 1);
+    assert_eq!(b?, 1);
+    assert!(duplicate.is_err());
+    assert_eq!(c?, 1);
+
+    let names = static_sqlite::rows(&db, "select name from Item order by name", vec![]).await?;
+    assert_eq!(names.len(), 3);
+
+    let timed_out = batched.with_timeout(std::time::Duration::from_millis(20));
+    let (slow, queued) = tokio::join!(
+        db.call(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            Ok(())
+        }),
+        static_sqlite::execute(
+            &timed_out,
+            "insert into Item (name) values (?)".into(),
+            vec!["d".into()],
+        )
+    );
+    slow?;
+    assert!(matches!(
+        queued,
+        Err(static_sqlite::Error::QueueTimeout { .. })
+    ));
+    let names = static_sqlite::rows(&db, "select name from Item", vec![]).await?;
+    assert_eq!(names.len(), 3, "the abandoned write still ran");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn metrics_work() -> Result<()> {
+    sql! {
+        let migrate = r#"
+            create table Metric (
+                id integer primary key,
+                name text unique not null
+            )
+        "#;
+
+        let insert_metric = r#"
+            insert into Metric (name) values (:name) returning *
+        "#;
+
+        let metrics = r#"
+            select * from Metric
+        "#;
+    };
+
+    let db = static_sqlite::open(":memory:").await?;
+    migrate(&db).await?;
+    insert_metric(&db, "a").await?;
+    insert_metric(&db, "b").await?;
+    assert!(insert_metric(&db, "a").await.is_err());
+    assert_eq!(metrics(&db).await?.len(), 2);
+    let tx = db.begin().await?;
+    let mut stream = Box::pin(static_sqlite::stream::<()>(&db, "select 1", vec![]).await?);
+
+    let snapshot = db.metrics();
+    assert_eq!(snapshot.open_transactions, 1);
+    assert_eq!(snapshot.active_streams, 1);
+    assert_eq!(snapshot.statements["insert_metric"].calls, 3);
+    assert_eq!(snapshot.statements["insert_metric"].errors, 1);
+    assert_eq!(snapshot.statements["metrics"].rows, 2);
+    assert!(snapshot.errors.values().sum::<u64>() >= 1);
+    assert!(snapshot.queue_wait.count >= snapshot.calls);
+    assert!(snapshot
+        .prometheus()
+        .contains("static_sqlite_statement_rows_total{statement=\"metrics\"} 2"));
+
+    tx.rollback().await?;
+    assert!(stream.next().await.is_some());
+    drop(stream);
+    let snapshot = db.metrics();
+    assert_eq!(snapshot.open_transactions, 0);
+    assert_eq!(snapshot.active_streams, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn dynamic_queries_work() -> Result<()> {
+    let db = static_sqlite::open(":memory:").await?;
+    static_sqlite::execute_all(&db, "create table Setting (key text, value text)").await?;
+    for key in ["a", "b"] {
+        let sql = format!("insert into Setting (key, value) values (?, '{key}{key}')");
+        static_sqlite::execute(&db, sql, vec![key.into()]).await?;
+    }
+
+    let table = String::from("Setting");
+    let rows = static_sqlite::query::<static_sqlite::Row>(
+        &db,
+        format!("select key, value from {table} where key > ? order by key"),
+        vec!["0".into()],
+    )
+    .await?;
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1].get::<String>("key")?, "b");
+    assert_eq!(rows[1].get::<String>(1)?, "bb");
+    assert!(rows[0].get::<String>("missing").is_err());
+    let pairs = static_sqlite::rows(&db, format!("select key from {table}"), vec![]).await?;
+    assert_eq!(pairs[0][0], ("key".into(), "a".into()));
+
+    let first: Option<static_sqlite::Row> =
+        static_sqlite::query_first(&db, format!("select count(*) as n from {table}"), vec![])
+            .await?;
+    assert_eq!(first.unwrap().get::<i64>("n")?, 2);
+    static_sqlite::execute_all(&db, format!("delete from {table} where key = 'a'")).await?;
+    assert!(
+        static_sqlite::execute_all(&db, format!("delete from Missing_{table}"))
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn executors_work() -> Result<()> {
+    sql! {
+        let migrate = r#"
+            create table Account (
+                id integer primary key,
+                name text not null
+            )
+        "#;
+
+        let insert_account = r#"
+            insert into Account (name) values (:name) returning *
+        "#;
+
+        let accounts = r#"
+            select * from Account
+        "#;
+    };
+
+    let db = static_sqlite::open(":memory:").await?;
+    migrate(&db).await?;
+    let tx = db.begin().await?;
+    insert_account(&tx, "a").await?;
+    insert_account(&tx, "b").await?;
+    tx.rollback().await?;
+    assert!(accounts(&db).await?.is_empty());
+
+    // the same functions run in place on a sync connection
+    let conn = static_sqlite_core::Sqlite::open(":memory:")?;
+    conn.execute_all("create table Account (id integer primary key, name text not null)")?;
+    let sp = conn.savepoint("accounts")?;
+    insert_account(&*sp, "c").await?;
+    insert_account(&sp, "d").await?;
+    drop(sp);
+    assert!(accounts(&conn).await?.is_empty());
+    insert_account(&conn, "e").await?;
+    assert_eq!(accounts(&conn).await?.first_row()?.name, "e");
+
+    Ok(())
+}
+
+mod sync_functions {
+    use static_sqlite::{sql, Result};
+
+    sql! {
+        #![sync]
+
+        let migrate = r#"
+            create table Note (
+                id integer primary key,
+                body text not null
+            )
+        "#;
+
+        let insert_note = r#"
+            insert into Note (body) values (:body) returning *
+        "#;
+
+        let note_first = r#"
+            select * from Note where id = :id
+        "#;
+
+        let notes_stream = r#"
+            select * from Note order by id
+        "#;
+
+        let notes_after_stream = r#"
+            select * from Note where body > :body order by id
+        "#;
+    }
+
+    #[test]
+    fn sync_functions_work() -> Result<()> {
+        let db = static_sqlite::sync::open(":memory:")?;
+        migrate(&db)?;
+        migrate(&db)?;
+        let note = insert_note(&db, "a")?.into_iter().next().unwrap();
+        insert_note(&db, "b")?;
+        assert_eq!(note_first(&db, note.id)?.unwrap().body, "a");
+        let bodies = notes_stream(&db)?
+            .map(|note| note.map(|note| note.body))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(bodies, vec!["a", "b"]);
+
+        let after = "a".repeat(2);
+        let notes = notes_after_stream(&db, &after)?;
+        drop(after);
+        let bodies = notes
+            .map(|note| note.map(|note| note.body))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(bodies, vec!["b"]);
+
+        Ok(())
+    }
+}
+
+mod sync_and_async_functions {
+    use static_sqlite::{sql, FirstRow, Result};
+
+    sql! {
+        #![sync(both)]
+
+        let migrate = r#"
+            create table Tag (
+                id integer primary key,
+                name text not null
+            )
+        "#;
+
+        let insert_tag = r#"
+            insert into Tag (name) values (:name) returning *
+        "#;
+    }
+
+    #[tokio::test]
+    async fn both_work() -> Result<()> {
+        let db = static_sqlite::open(":memory:").await?;
+        migrate(&db).await?;
+        assert_eq!(insert_tag(&db, "a").await?.first_row()?.name, "a");
+
+        let conn = static_sqlite::sync::open(":memory:")?;
+        blocking::migrate(&conn)?;
+        assert_eq!(blocking::insert_tag(&conn, "b")?.first_row()?.name, "b");
+
+        Ok(())
+    }
+}
+
+mod included_sql {
+    use static_sqlite::{sql, FirstRow, Result};
+
+    sql! {
+        let migrate = include_sql!("tests/queries/migrate.sql");
+        let add_book = include_sql!("tests/queries/insert_book.sql");
+        include_sql!("tests/queries");
+    }
+
+    #[tokio::test]
+    async fn include_sql_works() -> Result<()> {
+        let db = static_sqlite::open(":memory:").await?;
+        migrate(&db).await?;
+        add_book(&db, "a").await?;
+        insert_book(&db, "b").await?;
+
+        assert_eq!(books(&db).await?.len(), 2);
+        assert_eq!(book_first(&db, "b").await?.unwrap().id, 2);
+        assert_eq!(books(&db).await?.first_row()?.title, "a");
+
+        Ok(())
+    }
+}
+
+mod migration_status {
+    use static_sqlite::{sql, Result};
+
+    sql! {
+        let migrate = r#"
+            create table Shelf (id integer primary key);
+            create table Label (id integer primary key, text text not null);
+            alter table Shelf add column label_id integer references Label(id);
+        "#;
+    }
+
+    #[tokio::test]
+    async fn status_plan_and_to_work() -> Result<()> {
+        let db = static_sqlite::open(":memory:").await?;
+        let plan = migrate_plan(&db).await?;
+        assert_eq!(plan.len(), 3);
+        assert!(plan[1].sql.starts_with("create table Label"));
+        assert!(migrate_status(&db).await?.applied.is_empty());
+
+        let report = migrate_to(&db, "2").await?;
+        assert_eq!(report.version, 2);
+        let status = migrate_status(&db).await?;
+        assert_eq!(status.applied.len(), 2);
+        assert!(status.applied[0].applied_at > 0);
+        assert_eq!(status.pending.len(), 1);
+        assert!(migrate_to(&db, "drop table Shelf").await.is_err());
+
+        assert_eq!(migrate(&db).await?.version, 3);
+        assert!(migrate_plan(&db).await?.is_empty());
+
+        Ok(())
+    }
+}
+
+mod down_migrations {
+    use static_sqlite::{sql, Result};
+
+    sql! {
+        #![sync]
+
+        let migrate = r#"
+            create table Author (id integer primary key, name text not null);
+
+            -- migrate:up add_bio
+            alter table Author add column bio text;
+            create index author_name on Author (name);
+            -- migrate:down
+            drop index author_name;
+            alter table Author drop column bio;
+        "#;
+
+        let authors = r#"
+            select * from Author
+        "#;
+    }
+
+    #[test]
+    fn down_works() -> Result<()> {
+        let db = static_sqlite::sync::open(":memory:")?;
+        migrate(&db)?;
+        assert_eq!(migrate_down(&db, "1")?.reverted[0].name, "add_bio");
+        assert!(db.table("Author")?.unwrap().column("bio").is_none());
+        assert_eq!(migrate_plan(&db)?.len(), 1);
+        migrate(&db)?;
+        assert!(authors(&db)?.is_empty());
+
+        Ok(())
+    }
+}
+
+mod rust_migrations {
+    use static_sqlite::{sql, Result, Value};
+
+    sql! {
+        let migrate = r#"
+            create table Post (id integer primary key, title text not null);
+
+            -- migrate:up
+            alter table Post add column slug text;
+
+            -- migrate:rust backfill_slugs
+
+            -- migrate:up
+            create unique index post_slug on Post (slug);
+        "#;
+
+        let insert_post = r#"
+            insert into Post (title) values (:title) returning *
+        "#;
+
+        let posts = r#"
+            select * from Post order by id
+        "#;
+    }
+
+    #[tokio::test]
+    async fn rust_steps_work() -> Result<()> {
+        let db = static_sqlite::open(":memory:").await?;
+        migrate_to(&db, steps(), "2").await?;
+        insert_post(&db, "Hello World").await?;
+        insert_post(&db, "Second Post").await?;
+        let report = migrate(&db, steps()).await?;
+        assert_eq!(report.applied[0].name, "backfill_slugs");
+        assert_eq!(report.version, 4);
+
+        let slugs = posts(&db)
+            .await?
+            .into_iter()
+            .map(|post| post.slug)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            slugs,
+            vec![Some("hello-world".into()), Some("second-post".into())]
+        );
+
+        Ok(())
+    }
+
+    fn steps() -> MigrateSteps {
+        MigrateSteps {
+            backfill_slugs: Box::new(|ctx| {
+                let posts: Vec<migrate_steps::backfill_slugs::Post> =
+                    ctx.query("select * from Post", &[])?;
+                for post in posts {
+                    let slug = post.title.to_lowercase().replace(' ', "-");
+                    ctx.execute(
+                        "update Post set slug = ? where id = ?",
+                        vec![Value::Text(slug), Value::Integer(post.id)],
+                    )?;
+                }
+                Ok(())
+            }),
+        }
+    }
+}
+
+mod alter_column_migrations {
+    use static_sqlite::{execute_all, query, sql, Result, Row};
+
+    sql! {
+        let migrate = r#"
+            create table Account (id integer primary key, email text);
+            create table Session (id integer primary key, account_id integer not null references Account(id) on delete cascade);
+            create index account_email on Account (email);
+            alter table Account alter column email set not null;
+            alter table Account alter column email set default 'unknown';
+        "#;
+
+        let insert_account = r#"
+            insert into Account (email) values (:email) returning *
+        "#;
+    }
+
+    #[tokio::test]
+    async fn alter_column_works() -> Result<()> {
+        let db = static_sqlite::open(":memory:").await?;
+        execute_all(&db, "pragma foreign_keys = on").await?;
+        migrate_to(&db, "3").await?;
+        execute_all(&db, "insert into Account (email) values ('a@example.com')").await?;
+        execute_all(&db, "insert into Session (account_id) values (1)").await?;
+        assert!(migrate_plan(&db).await?[0]
+            .sql
+            .contains("_static_sqlite_old_Account"));
+
+        assert_eq!(migrate(&db).await?.version, 5);
+        let account = insert_account(&db, "b@example.com").await?;
+        assert_eq!(account[0].email, "b@example.com");
+        let sessions: Vec<Row> = query(&db, "select * from Session", vec![]).await?;
+        assert_eq!(sessions.len(), 1);
+        let table = db.call(|conn| conn.table("Account")).await?.unwrap();
+        assert_eq!(table.indexes.len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,7 @@
+-- Queries reading books
+
+-- name: books
+select * from Book order by id
+
+-- name: book_first
+select * from Book where title = :title
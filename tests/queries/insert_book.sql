@@ -0,0 +1 @@
+insert into Book (title) values (:title) returning *
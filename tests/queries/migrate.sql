@@ -0,0 +1,4 @@
+create table Book (
+    id integer primary key,
+    title text not null
+);
@@ -0,0 +1,14 @@
+use static_sqlite::sql;
+
+sql! {
+    let migrate = r#"
+        create table User (id integer primary key);
+
+        -- migrate:up
+        alter table User add column email text;
+        -- migrate:down
+        create table Email (id integer primary key);
+    "#;
+}
+
+fn main() {}